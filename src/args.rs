@@ -0,0 +1,280 @@
+use crate::{manifest::Compression, Anchor};
+use chrono::naive::NaiveDate;
+use std::{path::PathBuf, str::FromStr, time::Duration};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Text,
+    Markdown,
+    Json,
+    JsonCompact,
+    JsonLines,
+    Summary,
+    ComponentsAdd,
+    Archive,
+    Toml,
+    ComponentsJson,
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(Format::Text),
+            "markdown" | "md" => Ok(Format::Markdown),
+            "json" | "json-pretty" => Ok(Format::Json),
+            "json-compact" => Ok(Format::JsonCompact),
+            "json-lines" | "ndjson" => Ok(Format::JsonLines),
+            "summary" => Ok(Format::Summary),
+            "components-add" => Ok(Format::ComponentsAdd),
+            "archive" => Ok(Format::Archive),
+            "toml" => Ok(Format::Toml),
+            "components-json" => Ok(Format::ComponentsJson),
+            _ => Err(format!("unknown format: {}", s)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Args {
+    pub format: Format,
+    pub watch: Option<Duration>,
+    pub anchor: Anchor,
+    pub max_days: usize,
+    pub min_components: Option<usize>,
+    pub explain: bool,
+    pub no_banner: bool,
+    pub explain_missing: Option<String>,
+    pub target_family: Option<String>,
+    // `Some(None)` for the newest published nightly.
+    pub component_versions: Option<Option<NaiveDate>>,
+    pub no_cache: bool,
+    pub cache_read_only: bool,
+    pub clear_cache: bool,
+    pub ignore: Vec<String>,
+    pub require: Vec<String>,
+    pub diff_dates: Option<(String, String)>,
+    pub skip_docs: bool,
+    pub fail_on_missing: bool,
+    pub recommend_stable: bool,
+    pub since_installed: bool,
+    pub no_required_distinction: bool,
+    pub target_default_only: bool,
+    pub compression: Compression,
+    pub exit_code: bool,
+    pub only_if_actionable: bool,
+    pub all_channels: bool,
+    pub all_toolchains: bool,
+    pub check_rustup: bool,
+    pub compare_installed_manifest: bool,
+    pub output: Option<PathBuf>,
+    pub rustup_home: Option<PathBuf>,
+    pub manifest_dir: Option<PathBuf>,
+    pub target_from_rustc: bool,
+    pub no_progress: bool,
+    pub pin: bool,
+    pub force: bool,
+    // 1 for `-v`, 2 for `-vv` or more.
+    pub verbosity: u8,
+}
+
+impl Default for Args {
+    fn default() -> Self {
+        Args {
+            format: Format::Text,
+            watch: None,
+            anchor: Anchor::Local,
+            max_days: 30,
+            min_components: None,
+            explain: false,
+            no_banner: false,
+            explain_missing: None,
+            target_family: None,
+            component_versions: None,
+            no_cache: false,
+            cache_read_only: false,
+            clear_cache: false,
+            ignore: Vec::new(),
+            require: Vec::new(),
+            diff_dates: None,
+            skip_docs: false,
+            fail_on_missing: false,
+            recommend_stable: false,
+            since_installed: false,
+            no_required_distinction: false,
+            target_default_only: false,
+            compression: Compression::Xz,
+            exit_code: false,
+            only_if_actionable: false,
+            all_channels: false,
+            all_toolchains: false,
+            check_rustup: false,
+            compare_installed_manifest: false,
+            output: None,
+            rustup_home: None,
+            manifest_dir: None,
+            target_from_rustc: false,
+            no_progress: false,
+            pin: false,
+            force: false,
+            verbosity: 0,
+        }
+    }
+}
+
+impl Args {
+    // Machine-readable formats keep stderr quiet too, unless asked with `-v`.
+    pub fn show_progress(&self, terminal: bool) -> bool {
+        terminal
+            && !self.no_progress
+            && (matches!(self.format, Format::Text | Format::Markdown) || self.verbose())
+    }
+
+    // Component versions in the banner, the component table and notes.
+    pub fn verbose(&self) -> bool {
+        self.verbosity >= 1
+    }
+
+    // The per-date trace on stderr, with `--explain` or `-vv`.
+    pub fn show_trace(&self) -> bool {
+        self.explain || self.verbosity >= 2
+    }
+
+    // Only the human-readable formats ever carry the installed-info banner.
+    pub fn show_banner(&self) -> bool {
+        !self.no_banner && matches!(self.format, Format::Text | Format::Markdown)
+    }
+
+    pub fn parse<I>(input: I) -> Result<Args, String>
+    where
+        I: IntoIterator<Item = String>,
+    {
+        let mut args = Args::default();
+        let mut input = input.into_iter();
+        while let Some(arg) = input.next() {
+            let (name, value) = match arg.find('=') {
+                Some(pos) => (arg[..pos].to_string(), Some(arg[pos + 1..].to_string())),
+                None => (arg, None),
+            };
+            match name.as_str() {
+                "--format" => args.format = value_of(&name, value, &mut input)?.parse()?,
+                "--watch" => {
+                    args.watch = Some(parse_interval(&value_of(&name, value, &mut input)?)?)
+                }
+                "--json" | "--json-pretty" => args.format = Format::Json,
+                "--json-compact" => args.format = Format::JsonCompact,
+                "--json-lines" => args.format = Format::JsonLines,
+                "--summary" => args.format = Format::Summary,
+                "--components-add-only" => args.format = Format::ComponentsAdd,
+                "--archive" => args.format = Format::Archive,
+                "--output-components-as-json" => args.format = Format::ComponentsJson,
+                "--exit-code" => args.exit_code = true,
+                "--only-if-actionable" => args.only_if_actionable = true,
+                "--all-channels" => args.all_channels = true,
+                "--all-toolchains" => args.all_toolchains = true,
+                "--check-rustup" => args.check_rustup = true,
+                "--compare-installed-manifest" => args.compare_installed_manifest = true,
+                "--output" => {
+                    args.output = Some(PathBuf::from(value_of(&name, value, &mut input)?))
+                }
+                "--rustup-home" => {
+                    args.rustup_home = Some(PathBuf::from(value_of(&name, value, &mut input)?))
+                }
+                "--manifest-dir" => {
+                    args.manifest_dir = Some(PathBuf::from(value_of(&name, value, &mut input)?))
+                }
+                "--max-days" => {
+                    args.max_days = value_of(&name, value, &mut input)?
+                        .parse()
+                        .map_err(|_| format!("wrong value for {}", name))?
+                }
+                "--min-components" => {
+                    args.min_components = Some(
+                        value_of(&name, value, &mut input)?
+                            .parse()
+                            .map_err(|_| format!("wrong value for {}", name))?,
+                    )
+                }
+                "--explain" => args.explain = true,
+                "--no-banner" => args.no_banner = true,
+                "--target-family" => args.target_family = Some(value_of(&name, value, &mut input)?),
+                "--component-versions" => {
+                    args.component_versions = Some(match value {
+                        Some(date) => Some(
+                            NaiveDate::parse_from_str(&date, "%Y-%m-%d")
+                                .map_err(|_| format!("wrong date for {}: {}", name, date))?,
+                        ),
+                        None => None,
+                    })
+                }
+                "--explain-missing" => {
+                    args.explain_missing = Some(value_of(&name, value, &mut input)?)
+                }
+                "--no-cache" => args.no_cache = true,
+                "--cache-read-only" => args.cache_read_only = true,
+                "--clear-cache" => args.clear_cache = true,
+                "--ignore" => args.ignore.extend(
+                    value_of(&name, value, &mut input)?
+                        .split(',')
+                        .filter(|s| !s.is_empty())
+                        .map(|s| s.to_string()),
+                ),
+                "--require" => args.require.extend(
+                    value_of(&name, value, &mut input)?
+                        .split(',')
+                        .filter(|s| !s.is_empty())
+                        .map(|s| s.to_string()),
+                ),
+                "--diff-dates" => {
+                    let from = value_of(&name, value, &mut input)?;
+                    let to = value_of(&name, None, &mut input)?;
+                    args.diff_dates = Some((from, to));
+                }
+                "--skip-docs" => args.skip_docs = true,
+                "--fail-on-missing" => args.fail_on_missing = true,
+                "--recommend-stable-if-nightly-broken" => args.recommend_stable = true,
+                "--since-installed" => args.since_installed = true,
+                "--no-required-distinction" => args.no_required_distinction = true,
+                "--target-default-only" => args.target_default_only = true,
+                "--prefer-gz" => args.compression = Compression::Gz,
+                "--prefer-xz" => args.compression = Compression::Xz,
+                "--target-from-rustc" => args.target_from_rustc = true,
+                "--no-progress" => args.no_progress = true,
+                "--pin" => args.pin = true,
+                "--force" => args.force = true,
+                "--verbose" | "-v" => args.verbosity += 1,
+                "-vv" => args.verbosity += 2,
+                "--utc" => args.anchor = Anchor::Utc,
+                _ => return Err(format!("unknown argument: {}", name)),
+            }
+        }
+        Ok(args)
+    }
+}
+
+fn value_of<I>(name: &str, value: Option<String>, input: &mut I) -> Result<String, String>
+where
+    I: Iterator<Item = String>,
+{
+    match value {
+        Some(value) => Ok(value),
+        None => input
+            .next()
+            .ok_or_else(|| format!("missing value for {}", name)),
+    }
+}
+
+// Accepts plain seconds or a number with an `s`, `m` or `h` suffix.
+pub fn parse_interval(s: &str) -> Result<Duration, String> {
+    let (number, unit) = match s.char_indices().last() {
+        Some((pos, 's')) => (&s[..pos], 1),
+        Some((pos, 'm')) => (&s[..pos], 60),
+        Some((pos, 'h')) => (&s[..pos], 60 * 60),
+        _ => (s, 1),
+    };
+    match number.parse::<u64>() {
+        Ok(n) if n > 0 => Ok(Duration::from_secs(n * unit)),
+        _ => Err(format!("wrong interval: {}", s)),
+    }
+}
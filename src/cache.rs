@@ -0,0 +1,157 @@
+use std::{
+    env,
+    fs::{self, File},
+    io::{ErrorKind, Read, Write},
+    path::PathBuf,
+    time::{Duration, SystemTime},
+};
+
+// Only gaps a few days old are remembered (see `Manifest::from_date`), and
+// those are rarely backfilled, so the miss is trusted for a while.
+pub const MISSING_TTL: Duration = Duration::from_secs(6 * 60 * 60);
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Cache {
+    dir: PathBuf,
+    read: bool,
+    write: bool,
+    missing_ttl: Duration,
+    // Tells apart copies fetched from a mirror or another path layout;
+    // `None` for the official server.
+    source: Option<String>,
+}
+
+impl Cache {
+    pub fn new(dir: PathBuf) -> Cache {
+        Cache {
+            dir,
+            read: true,
+            write: true,
+            missing_ttl: MISSING_TTL,
+            source: None,
+        }
+    }
+
+    // Always miss, but keep storing what gets fetched.
+    pub fn without_read(mut self) -> Cache {
+        self.read = false;
+        self
+    }
+
+    pub fn without_write(mut self) -> Cache {
+        self.write = false;
+        self
+    }
+
+    pub fn with_missing_ttl(mut self, ttl: Duration) -> Cache {
+        self.missing_ttl = ttl;
+        self
+    }
+
+    // Files of this cache get a short, stable digest of `source` in their
+    // name, so manifests of different servers never overwrite each other.
+    pub fn for_source(mut self, source: &str) -> Cache {
+        self.source = Some(format!("{:016x}", fnv1a(source.as_bytes())));
+        self
+    }
+
+    pub fn default_dir() -> Option<PathBuf> {
+        let mut dir = match env::var_os("XDG_CACHE_HOME") {
+            Some(dir) => PathBuf::from(dir),
+            None => {
+                let mut dir = PathBuf::from(env::var_os("HOME")?);
+                dir.push(".cache");
+                dir
+            }
+        };
+        dir.push("rustupscheck");
+        Some(dir)
+    }
+
+    fn path(&self, date: &str, channel: &str) -> PathBuf {
+        let mut path = self.dir.clone();
+        match &self.source {
+            Some(source) => path.push(format!("channel-rust-{}-{}.{}.toml", channel, date, source)),
+            None => path.push(format!("channel-rust-{}-{}.toml", channel, date)),
+        }
+        path
+    }
+
+    pub fn get(&self, date: &str, channel: &str) -> Option<String> {
+        if !self.read {
+            return None;
+        }
+        let mut file = File::open(self.path(date, channel)).ok()?;
+        let mut contents = String::new();
+        file.read_to_string(&mut contents).ok()?;
+        Some(contents)
+    }
+
+    fn missing_path(&self, date: &str, channel: &str) -> PathBuf {
+        self.path(date, channel).with_extension("missing")
+    }
+
+    // Whether the date was recently found unpublished.
+    pub fn is_missing(&self, date: &str, channel: &str) -> bool {
+        if !self.read {
+            return false;
+        }
+        fs::metadata(self.missing_path(date, channel))
+            .and_then(|m| m.modified())
+            .ok()
+            .and_then(|modified| SystemTime::now().duration_since(modified).ok())
+            .is_some_and(|age| age < self.missing_ttl)
+    }
+
+    pub fn put_missing(&self, date: &str, channel: &str) -> Result<(), String> {
+        if !self.write {
+            return Ok(());
+        }
+        fs::create_dir_all(&self.dir).map_err(|e| e.to_string())?;
+        File::create(self.missing_path(date, channel))
+            .map(|_| ())
+            .map_err(|e| e.to_string())
+    }
+
+    // Write to a temporary file first so an interrupted run never leaves a
+    // truncated manifest behind.
+    pub fn put(&self, date: &str, channel: &str, contents: &str) -> Result<(), String> {
+        if !self.write {
+            return Ok(());
+        }
+        fs::create_dir_all(&self.dir).map_err(|e| e.to_string())?;
+        let path = self.path(date, channel);
+        let tmp = path.with_extension("tmp");
+        let mut file = File::create(&tmp).map_err(|e| e.to_string())?;
+        file.write_all(contents.as_bytes())
+            .map_err(|e| e.to_string())?;
+        fs::rename(&tmp, &path).map_err(|e| e.to_string())
+    }
+
+    // Removes the cache directory and returns how many entries it held.
+    pub fn clear(&self) -> Result<usize, String> {
+        let entries = match fs::read_dir(&self.dir) {
+            Ok(entries) => entries,
+            Err(ref e) if e.kind() == ErrorKind::NotFound => return Ok(0),
+            Err(e) => return Err(e.to_string()),
+        };
+        let mut removed = 0;
+        for entry in entries {
+            let entry = entry.map_err(|e| e.to_string())?;
+            if entry.path().is_file() {
+                fs::remove_file(entry.path()).map_err(|e| e.to_string())?;
+                removed += 1;
+            }
+        }
+        fs::remove_dir(&self.dir).map_err(|e| e.to_string())?;
+        Ok(removed)
+    }
+}
+
+// Unlike `DefaultHasher`, guaranteed to give the same digest across Rust
+// releases, which matters for file names.
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3)
+    })
+}
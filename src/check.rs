@@ -0,0 +1,64 @@
+use crate::{
+    active_toolchain_name, report::Report, source::Dist, source::Source, split_toolchain_name,
+    toolchain_dir, validate_target, Anchor, CheckError, Rust, Toolchain,
+};
+use std::{path::PathBuf, rc::Rc};
+
+// Everything `check_toolchain` needs besides the toolchain name; the defaults
+// match running the binary without arguments.
+#[derive(Debug, Clone)]
+pub struct CheckOptions {
+    // Overrides the channel taken from the toolchain name.
+    pub channel: Option<String>,
+    // Overrides the target taken from the toolchain name.
+    pub target: Option<String>,
+    pub max_days: usize,
+    pub ignore: Vec<String>,
+    // Components a nightly must provide instead of the installed ones.
+    pub require: Vec<String>,
+    pub anchor: Anchor,
+    // Defaults to `$RUSTUP_HOME`, then `~/.rustup`.
+    pub rustup_home: Option<PathBuf>,
+    // Defaults to the dist server behind the disk cache.
+    pub source: Option<Rc<dyn Source>>,
+}
+
+impl Default for CheckOptions {
+    fn default() -> Self {
+        CheckOptions {
+            channel: None,
+            target: None,
+            max_days: 30,
+            ignore: Vec::new(),
+            require: Vec::new(),
+            anchor: Anchor::Local,
+            rustup_home: None,
+            source: None,
+        }
+    }
+}
+
+// The main integration point for other tools: checks the named toolchain, or
+// the active one for `None`, and returns the fully computed report.
+pub fn check_toolchain(name: Option<&str>, opts: &CheckOptions) -> Result<Report, CheckError> {
+    let name = match name {
+        Some(name) => name.to_string(),
+        None => {
+            active_toolchain_name(opts.rustup_home.as_deref()).map_err(CheckError::Toolchain)?
+        }
+    };
+    let (channel, target) = split_toolchain_name(&name).map_err(CheckError::Toolchain)?;
+    let dir = toolchain_dir(&name, opts.rustup_home.as_deref()).map_err(CheckError::Toolchain)?;
+    let target = opts.target.clone().unwrap_or(target);
+    validate_target(&target).map_err(CheckError::Toolchain)?;
+    let toolchain = Toolchain::from_dir(opts.channel.clone().unwrap_or(channel), target, &dir)
+        .map_err(CheckError::Toolchain)?;
+    let source = match &opts.source {
+        Some(source) => source.clone(),
+        None => Rc::new(Dist::cached()),
+    };
+    let mut rust = Rust::for_toolchain(toolchain, source, opts.anchor)?;
+    rust.set_ignored(opts.ignore.clone());
+    rust.set_required(opts.require.clone());
+    rust.to_report(opts.max_days)
+}
@@ -0,0 +1,39 @@
+use crate::{manifest::FetchError, print_vec};
+use std::{error::Error, fmt};
+
+// Why a check couldn't produce a report.
+#[derive(Debug, Clone, PartialEq)]
+pub enum CheckError {
+    // The installed toolchain couldn't be located or read.
+    Toolchain(String),
+    // A manifest couldn't be downloaded: a network, TLS or HTTP failure.
+    Fetch(String),
+    // A manifest was downloaded but couldn't be parsed.
+    Malformed(String),
+    // The candidate lacks components the toolchain has installed.
+    MissingComponents(Vec<String>),
+}
+
+impl fmt::Display for CheckError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CheckError::Toolchain(e) => write!(f, "{}", e),
+            CheckError::Fetch(e) => write!(f, "{}", e),
+            CheckError::Malformed(e) => write!(f, "malformed manifest: {}", e),
+            CheckError::MissingComponents(missing) => {
+                write!(f, "Missing components: {}", print_vec(missing, ", "))
+            }
+        }
+    }
+}
+
+impl Error for CheckError {}
+
+impl From<FetchError> for CheckError {
+    fn from(e: FetchError) -> Self {
+        match e {
+            FetchError::Fetch(e) => CheckError::Fetch(e),
+            FetchError::Malformed(e) => CheckError::Malformed(e),
+        }
+    }
+}
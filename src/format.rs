@@ -0,0 +1,574 @@
+use crate::{
+    manifest::{MissingReason, PackageChange},
+    print_vec, ChannelStatus, CheckResult, ComponentDiff, ComponentRow, ComponentStatus,
+    ComponentVersion, MissingComponent, ProbeRecord, Version,
+};
+use chrono::{naive::NaiveDate, Duration};
+use serde::Serialize;
+
+pub fn text(result: &CheckResult) -> String {
+    match result {
+        CheckResult::UpToDate => String::from("Current version is up to date\n"),
+        CheckResult::Update { date, updates } => format!(
+            "{}\nUse: {} (new version from {})\n",
+            update_list(updates),
+            quoted_command(result),
+            date
+        ),
+        CheckResult::Switch { updates, .. } => format!(
+            "{}\nUse: {}\n",
+            update_list(updates),
+            quoted_command(result)
+        ),
+        CheckResult::NotFound {
+            days,
+            newest,
+            missing,
+        } => format!(
+            "No complete nightly found in the last {} days\n{}",
+            days,
+            match newest {
+                Some(date) => format!(
+                    "Newest nightly {} is missing: {}\n{}\n",
+                    date,
+                    print_vec(&names(missing), ", "),
+                    not_found_advice(missing, "\"")
+                ),
+                None => String::new(),
+            }
+        ),
+        CheckResult::TargetUnavailable {
+            target,
+            channel,
+            date,
+        } => format!(
+            "Target {} is not available on {}'s {}\n",
+            target, date, channel
+        ),
+    }
+}
+
+fn quoted_command(result: &CheckResult) -> String {
+    let lines: Vec<String> = result
+        .command()
+        .unwrap_or_default()
+        .lines()
+        .map(|line| format!("\"{}\"", line))
+        .collect();
+    print_vec(&lines, "\n     ")
+}
+
+pub fn markdown(result: &CheckResult) -> String {
+    match result {
+        CheckResult::UpToDate => String::from("### Current version is up to date\n"),
+        CheckResult::Update { date, updates } => format!(
+            "### Use `rustup update` (new version from {})\n{}",
+            date,
+            markdown_table(updates)
+        ),
+        CheckResult::Switch {
+            channel,
+            date,
+            updates,
+            components,
+        } => format!(
+            "### Use `rustup default {}-{}`\n{}{}",
+            channel,
+            date,
+            match components.len() {
+                0 => String::new(),
+                _ => format!(
+                    "\nThen `rustup component add {}`\n",
+                    print_vec(components, " ")
+                ),
+            },
+            markdown_table(updates)
+        ),
+        CheckResult::NotFound {
+            days,
+            newest,
+            missing,
+        } => format!(
+            "### No complete nightly found in the last {} days\n{}",
+            days,
+            match newest {
+                Some(date) => format!(
+                    "\nNewest nightly {} is missing: {}\n\n{}\n",
+                    date,
+                    print_vec(&names(missing), ", "),
+                    not_found_advice(missing, "`")
+                ),
+                None => String::new(),
+            }
+        ),
+        CheckResult::TargetUnavailable {
+            target,
+            channel,
+            date,
+        } => format!(
+            "### Target `{}` is not available on {}'s {}\n",
+            target, date, channel
+        ),
+    }
+}
+
+pub fn summary(result: &CheckResult) -> String {
+    match result {
+        CheckResult::UpToDate => String::from("up to date"),
+        CheckResult::Update { date, .. } => {
+            format!("update available: rustup update ({})", date)
+        }
+        CheckResult::Switch { channel, date, .. } => {
+            format!("switch available: rustup default {}-{}", channel, date)
+        }
+        CheckResult::NotFound {
+            days,
+            newest: Some(date),
+            missing,
+        } => format!(
+            "no complete nightly in the last {} days ({} missing {})",
+            days,
+            date,
+            print_vec(&names(missing), ", ")
+        ),
+        CheckResult::NotFound { days, .. } => {
+            format!("no complete nightly in the last {} days", days)
+        }
+        CheckResult::TargetUnavailable {
+            target,
+            channel,
+            date,
+        } => format!(
+            "target {} is not available on {}'s {}",
+            target, date, channel
+        ),
+    }
+}
+
+// A standalone `rustup component add` for `toolchain`, or nothing when there
+// is nothing to reinstall.
+pub fn component_add(toolchain: &str, components: &[String]) -> Option<String> {
+    match components.len() {
+        0 => None,
+        _ => Some(format!(
+            "rustup component add --toolchain {} {}",
+            toolchain,
+            print_vec(components, " ")
+        )),
+    }
+}
+
+pub fn channel_status(status: &ChannelStatus) -> String {
+    if let Some(error) = &status.error {
+        return format!("{}: check failed: {}", status.channel, error);
+    }
+    match (&status.installed, &status.latest) {
+        (_, None) => format!("{}: latest release not found", status.channel),
+        (None, Some(latest)) => format!("{}: not installed (latest {})", status.channel, latest),
+        (Some(installed), Some(latest)) if status.has_update() => format!(
+            "{}: {} is available (installed {})",
+            status.channel, latest, installed
+        ),
+        (Some(installed), Some(_)) => format!("{}: up to date ({})", status.channel, installed),
+    }
+}
+
+pub fn manifest_diff(from: &str, to: &str, changes: &[PackageChange]) -> String {
+    if changes.is_empty() {
+        return format!("No changes between {} and {}\n", from, to);
+    }
+    let name_width = changes
+        .iter()
+        .map(|c| c.name.len())
+        .chain(Some("component".len()))
+        .max()
+        .unwrap_or_default();
+    let from_width = changes
+        .iter()
+        .map(|c| c.from.len())
+        .chain(Some(from.len()))
+        .max()
+        .unwrap_or_default();
+    let row = |name: &str, old: &str, new: &str| {
+        format!(
+            "{:name_width$}  {:from_width$}  {}\n",
+            name,
+            old,
+            new,
+            name_width = name_width,
+            from_width = from_width
+        )
+    };
+    changes
+        .iter()
+        .fold(row("component", from, to), |mut acc, c| {
+            acc.push_str(&row(&c.name, &c.from, &c.to));
+            acc
+        })
+}
+
+pub fn target_family(prefix: &str, date: &str, family: &[(String, bool)]) -> String {
+    if family.is_empty() {
+        return format!("No targets matching {} on {}'s nightly\n", prefix, date);
+    }
+    let width = family
+        .iter()
+        .map(|(t, _)| t.len())
+        .max()
+        .unwrap_or_default();
+    let unavailable: Vec<String> = family
+        .iter()
+        .filter(|(_, available)| !available)
+        .map(|(target, _)| target.clone())
+        .collect();
+    let mut out = family.iter().fold(
+        format!(
+            "{} of {} {} targets have rust-std on {}\n",
+            family.len() - unavailable.len(),
+            family.len(),
+            prefix,
+            date
+        ),
+        |mut acc, (target, available)| {
+            acc.push_str(&format!(
+                "{:width$}  {}\n",
+                target,
+                if *available {
+                    "available"
+                } else {
+                    "unavailable"
+                },
+                width = width
+            ));
+            acc
+        },
+    );
+    if !unavailable.is_empty() {
+        out.push_str(&format!("Unavailable: {}\n", print_vec(&unavailable, ", ")));
+    }
+    out
+}
+
+// Versions lose their commit part when the full table won't fit `width`.
+pub fn component_table(rows: &[ComponentRow], width: usize) -> String {
+    let render = |short: bool| {
+        let version = |v: &Option<Version>| match v {
+            Some(v) if short => v.version.clone(),
+            Some(v) => v.to_string(),
+            None => "-".to_string(),
+        };
+        let cells: Vec<[String; 4]> = Some([
+            "component".to_string(),
+            "installed".to_string(),
+            "candidate".to_string(),
+            "status".to_string(),
+        ])
+        .into_iter()
+        .chain(rows.iter().map(|r| {
+            [
+                r.name.clone(),
+                version(&r.installed),
+                version(&r.candidate),
+                r.status.to_string(),
+            ]
+        }))
+        .collect();
+        let widths: Vec<usize> = (0..3)
+            .map(|i| cells.iter().map(|c| c[i].len()).max().unwrap_or_default())
+            .collect();
+        cells
+            .iter()
+            .map(|c| {
+                format!(
+                    "{:w0$}  {:w1$}  {:w2$}  {}\n",
+                    c[0],
+                    c[1],
+                    c[2],
+                    c[3],
+                    w0 = widths[0],
+                    w1 = widths[1],
+                    w2 = widths[2]
+                )
+            })
+            .collect::<String>()
+    };
+    let full = render(false);
+    if full.lines().all(|line| line.len() <= width) {
+        full
+    } else {
+        render(true)
+    }
+}
+
+pub fn component_versions(date: &str, versions: &[ComponentVersion]) -> String {
+    let width = versions
+        .iter()
+        .map(|c| c.name.len())
+        .max()
+        .unwrap_or_default();
+    versions.iter().fold(
+        format!("Component versions on {}'s nightly\n", date),
+        |mut acc, c| {
+            acc.push_str(&format!(
+                "{:width$}  {}\n",
+                c.name,
+                match (&c.version, c.available) {
+                    (Some(version), _) => version.to_string(),
+                    (None, true) => "(unknown version)".to_string(),
+                    (None, false) => "(unavailable)".to_string(),
+                },
+                width = width
+            ));
+            acc
+        },
+    )
+}
+
+pub fn availability(component: &str, history: &[(String, Option<bool>)]) -> String {
+    let published = history.iter().filter(|(_, a)| a.is_some()).count();
+    let available = history.iter().filter(|(_, a)| *a == Some(true)).count();
+    history.iter().fold(
+        format!(
+            "{} was available on {} of {} published nightlies\n",
+            component, available, published
+        ),
+        |mut acc, (date, a)| {
+            acc.push_str(&format!(
+                "{}  {}\n",
+                date,
+                match a {
+                    Some(true) => "available",
+                    Some(false) => "unavailable",
+                    None => "not published",
+                }
+            ));
+            acc
+        },
+    )
+}
+
+pub fn explain(probe: &ProbeRecord) -> String {
+    match (probe.found, probe.missing.len()) {
+        (false, _) if probe.unusable.is_some() => format!(
+            "{}: unusable, {}",
+            probe.date,
+            probe.unusable.as_deref().unwrap_or_default()
+        ),
+        (false, _) => format!("{}: not published", probe.date),
+        (true, _) if !probe.target_available => format!("{}: target not published", probe.date),
+        (true, 0) => format!("{}: complete", probe.date),
+        (true, _) => format!(
+            "{}: missing {}",
+            probe.date,
+            print_vec(&probe.missing, ", ")
+        ),
+    }
+}
+
+fn names(missing: &[MissingComponent]) -> Vec<String> {
+    missing.iter().map(|m| m.name.clone()).collect()
+}
+
+// The `rust` meta-package can't be removed, only waited for. Waiting won't
+// help components that are gone or never built for the target either, so
+// those get called out.
+fn not_found_advice(missing: &[MissingComponent], quote: &str) -> String {
+    let removable: Vec<MissingComponent> = missing
+        .iter()
+        .filter(|m| m.name != "rust")
+        .cloned()
+        .collect();
+    let mut advice = match removable.len() {
+        0 => String::from("Wait for a newer nightly"),
+        _ => format!(
+            "Remove them with {0}rustup component remove {1}{0} or wait for a newer nightly",
+            quote,
+            print_vec(&names(&removable), " ")
+        ),
+    };
+    for reason in &[MissingReason::Absent, MissingReason::TargetNotPublished] {
+        let stuck: Vec<String> = removable
+            .iter()
+            .filter(|m| m.reason == *reason)
+            .map(|m| m.name.clone())
+            .collect();
+        if !stuck.is_empty() {
+            advice.push_str(&format!(
+                "\n{}: {}, a newer nightly is unlikely to help",
+                print_vec(&stuck, ", "),
+                reason
+            ));
+        }
+    }
+    advice
+}
+
+// Empty when only `rust` itself changed, e.g. with no components installed.
+fn update_list(updates: &[ComponentDiff]) -> String {
+    if updates.is_empty() {
+        return String::new();
+    }
+    updates
+        .iter()
+        .fold(String::from("Update components:\n"), |mut acc, c| {
+            acc.push_str(&c.to_string());
+            acc.push('\n');
+            acc
+        })
+}
+
+fn markdown_table(updates: &[ComponentDiff]) -> String {
+    if updates.is_empty() {
+        return String::new();
+    }
+    updates.iter().fold(
+        String::from("\n| component | from | to |\n| --- | --- | --- |\n"),
+        |mut acc, c| {
+            acc.push_str(&format!("| {} | {} | {} |\n", c.name, c.from, c.to));
+            acc
+        },
+    )
+}
+
+#[derive(Serialize)]
+struct ComponentJson<'a> {
+    name: &'a str,
+    installed: Option<&'a Version>,
+    candidate: Option<&'a Version>,
+    status: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    reason: Option<MissingReason>,
+}
+
+// Only the component inventory, for editor integrations that do their own
+// wording; `reason` is present only for missing components.
+pub fn components_json(rows: &[ComponentRow]) -> String {
+    let components: Vec<ComponentJson> = rows
+        .iter()
+        .map(|row| {
+            let (status, reason) = match row.status {
+                ComponentStatus::UpToDate => ("up-to-date", None),
+                ComponentStatus::Update => ("update", None),
+                ComponentStatus::Add => ("add", None),
+                ComponentStatus::Missing(reason) => ("missing", Some(reason)),
+                ComponentStatus::Ignored => ("ignored", None),
+            };
+            ComponentJson {
+                name: &row.name,
+                installed: row.installed.as_ref(),
+                candidate: row.candidate.as_ref(),
+                status,
+                reason,
+            }
+        })
+        .collect();
+    format!(
+        "{}\n",
+        serde_json::to_string_pretty(&components).unwrap_or_else(|e| e.to_string())
+    )
+}
+
+// One compact JSON document without newlines, for line-delimited output.
+pub fn json_line<T: Serialize>(value: &T) -> String {
+    serde_json::to_string(value)
+        .unwrap_or_else(|e| serde_json::json!({ "error": e.to_string() }).to_string())
+}
+
+// How many release series the installed version trails `newest` by, or
+// `None` when it doesn't or either version can't be told apart.
+pub fn release_gap(installed: &Version, newest: &Version) -> Option<String> {
+    let (major, minor) = installed.major_minor().ok()?;
+    let (newest_major, newest_minor) = newest.major_minor().ok()?;
+    if major != newest_major || newest_minor <= minor {
+        return None;
+    }
+    let behind = newest_minor - minor;
+    Some(format!(
+        "You're {} release{} behind (on {}.{} series, newest is {}.{})\n",
+        behind,
+        if behind == 1 { "" } else { "s" },
+        major,
+        minor,
+        newest_major,
+        newest_minor
+    ))
+}
+
+// Releases ship every six weeks, so the installed series becomes stable
+// roughly that often after the current stable, counted from the stable
+// manifest's date. Point releases move that date, hence only "about".
+pub fn release_train(
+    installed: &Version,
+    stable: &Version,
+    stable_date: NaiveDate,
+    today: NaiveDate,
+) -> Option<String> {
+    let (major, minor) = installed.major_minor().ok()?;
+    let (stable_major, stable_minor) = stable.major_minor().ok()?;
+    if major != stable_major {
+        return None;
+    }
+    let cycle = Duration::weeks(6);
+    let mut next = stable_date + cycle;
+    while next <= today {
+        next += cycle;
+    }
+    let ahead = minor.checked_sub(stable_minor)?;
+    let (series, due) = match ahead {
+        0 => (format!("stable {}.{}", major, minor + 1), next),
+        _ => (
+            format!("{} {}.{}", installed.channel, major, minor),
+            next + cycle * (ahead as i32 - 1),
+        ),
+    };
+    let weeks = ((due - today).num_days() + 6) / 7;
+    Some(format!(
+        "{} {} in ~{} week{} (around {})",
+        series,
+        if ahead == 0 {
+            "is due"
+        } else {
+            "will become stable"
+        },
+        weeks,
+        if weeks == 1 { "" } else { "s" },
+        due
+    ))
+}
+
+// Labelled as an alternative: it trades the nightly for a different
+// channel rather than fixing the scan's result.
+pub fn stable_alternative(from: &str, version: Option<&Version>, components: &[String]) -> String {
+    let mut out = format!(
+        "Alternative: stable{} has every component you need\n\
+         Note: leaving {} gives up features only it has\n\
+         Use: \"rustup default stable\"\n",
+        version
+            .map(|v| format!(" {}", v.version))
+            .unwrap_or_default(),
+        from
+    );
+    if !components.is_empty() {
+        out.push_str(&format!(
+            "     \"rustup component add {}\"\n",
+            print_vec(components, " ")
+        ));
+    }
+    out
+}
+
+// Waiting won't bring these back, so removing them unblocks updates.
+pub fn removed_upstream(components: &[String]) -> Option<String> {
+    match components.len() {
+        0 => None,
+        _ => Some(format!(
+            "Note: {} missing from every nightly checked and likely removed upstream\n\
+             Use: \"rustup component remove {}\"\n",
+            match components.len() {
+                1 => format!("{} is", components[0]),
+                _ => format!("{} are", print_vec(components, ", ")),
+            },
+            print_vec(components, " ")
+        )),
+    }
+}
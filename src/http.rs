@@ -0,0 +1,538 @@
+use crate::{manifest::dist_server, proxy::Proxy};
+use native_tls::{HandshakeError, TlsConnector};
+use std::{
+    error::Error,
+    io::{self, ErrorKind, Read, Write},
+    net::TcpStream,
+    str::FromStr,
+    thread,
+    time::Duration,
+};
+
+pub const HOST: &str = "static.rust-lang.org";
+pub const HANDSHAKE_RETRIES: usize = 3;
+// Headers end well within this; past it the response is not HTTP.
+pub const MAX_HEADER_LEN: usize = 64 * 1024;
+// Channel manifests are around a megabyte; anything far past that is a
+// broken or hostile server.
+pub const MAX_RESPONSE_LEN: usize = 32 * 1024 * 1024;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Failure {
+    // Worth another attempt on a fresh connection, e.g. a reset mid-handshake.
+    Transient(String),
+    Fatal(String),
+}
+
+// Where requests go: a URL like `https://mirror.example.com:8443/rust`,
+// whose path is prepended to every request path.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Server {
+    pub tls: bool,
+    pub host: String,
+    pub port: u16,
+    pub prefix: String,
+}
+
+impl Default for Server {
+    fn default() -> Self {
+        Server {
+            tls: true,
+            host: HOST.to_string(),
+            port: 443,
+            prefix: String::new(),
+        }
+    }
+}
+
+impl FromStr for Server {
+    type Err = String;
+
+    fn from_str(url: &str) -> Result<Self, Self::Err> {
+        let invalid = |what: &str| format!("Invalid dist server {} in {}", what, url);
+        let (tls, rest) = if let Some(rest) = url.strip_prefix("https://") {
+            (true, rest)
+        } else if let Some(rest) = url.strip_prefix("http://") {
+            (false, rest)
+        } else {
+            return Err(invalid("scheme (only http:// and https:// are supported)"));
+        };
+        let rest = rest.trim_end_matches('/');
+        let (address, prefix) = match rest.find('/') {
+            Some(pos) => (&rest[..pos], &rest[pos..]),
+            None => (rest, ""),
+        };
+        let (host, port) = match address.rfind(':') {
+            Some(pos) => (
+                &address[..pos],
+                address[pos + 1..].parse().map_err(|_| invalid("port"))?,
+            ),
+            None => (address, if tls { 443 } else { 80 }),
+        };
+        if host.is_empty() {
+            return Err(invalid("host"));
+        }
+        Ok(Server {
+            tls,
+            host: host.to_string(),
+            port,
+            prefix: prefix.to_string(),
+        })
+    }
+}
+
+impl Server {
+    // `RUSTUP_DIST_SERVER`, like rustup itself, or the official server.
+    pub fn from_env() -> Result<Server, String> {
+        dist_server().parse()
+    }
+
+    // The `Host` header, which leaves out a default port.
+    pub fn host_header(&self) -> String {
+        match (self.tls, self.port) {
+            (true, 443) | (false, 80) => self.host.clone(),
+            _ => format!("{}:{}", self.host, self.port),
+        }
+    }
+}
+
+pub fn get(path: &str) -> Result<String, String> {
+    get_from(&Server::from_env()?, path)
+}
+
+pub fn get_from(server: &Server, path: &str) -> Result<String, String> {
+    fetch_from(server, path)?.ok_or_else(|| format!("HTTP status 404 for {}", path))
+}
+
+// Like `get`, but a 404 is an expected answer rather than an error: the dist
+// server has simply published nothing at that path.
+pub fn fetch(path: &str) -> Result<Option<String>, String> {
+    fetch_from(&Server::from_env()?, path)
+}
+
+pub fn fetch_from(server: &Server, path: &str) -> Result<Option<String>, String> {
+    let connector = TlsConnector::new().map_err(|e| e.to_string())?;
+    let proxy = Proxy::from_env()?;
+    let mut stream = retry_handshake(HANDSHAKE_RETRIES, Duration::from_millis(500), || {
+        connect(&connector, proxy.as_ref(), server)
+    })?;
+    let request = format!(
+        "GET {}{} HTTP/1.1\r\nHost: {}\r\nAccept: application/toml, text/plain, */*;q=0.1\r\n\
+         Connection: close\r\n\r\n",
+        server.prefix,
+        path,
+        server.host_header()
+    )
+    .into_bytes();
+    stream.write_all(&request).map_err(|e| e.to_string())?;
+    let response = read_limited(&mut stream, MAX_RESPONSE_LEN)?;
+    match status(&response)? {
+        200 => {
+            reject_html(&response)?;
+            content(&response).map(Some)
+        }
+        404 => Ok(None),
+        code => Err(format!("HTTP status {} for {}", code, path)),
+    }
+}
+
+// Reads to the end, but fails rather than buffer more than `limit` bytes.
+pub fn read_limited<R: Read>(reader: R, limit: usize) -> Result<Vec<u8>, String> {
+    let mut response = vec![];
+    reader
+        .take(limit as u64 + 1)
+        .read_to_end(&mut response)
+        .map_err(|e| e.to_string())?;
+    if response.len() > limit {
+        return Err(format!("response too large: over {} bytes", limit));
+    }
+    Ok(response)
+}
+
+trait Stream: Read + Write {}
+
+impl<T: Read + Write> Stream for T {}
+
+// Plain HTTP mirrors go through the proxy's tunnel too, just without TLS on
+// top.
+fn connect(
+    connector: &TlsConnector,
+    proxy: Option<&Proxy>,
+    server: &Server,
+) -> Result<Box<dyn Stream>, Failure> {
+    let host = server.host.as_str();
+    let stream = match proxy {
+        Some(proxy) => proxy.tunnel(host, server.port)?,
+        None => {
+            TcpStream::connect((host, server.port)).map_err(|e| Failure::Fatal(e.to_string()))?
+        }
+    };
+    if !server.tls {
+        return Ok(Box::new(stream));
+    }
+    match connector.connect(host, stream) {
+        Ok(stream) => Ok(Box::new(stream)),
+        Err(HandshakeError::Failure(e)) => Err(classify(&e)),
+        Err(HandshakeError::WouldBlock(_)) => {
+            Err(Failure::Fatal(String::from("TLS handshake would block")))
+        }
+    }
+}
+
+// Handshakes interrupted by the network are transient; certificate or
+// protocol errors will fail the same way again.
+pub fn classify(error: &(dyn Error + 'static)) -> Failure {
+    let mut source = Some(error);
+    while let Some(e) = source {
+        if let Some(io) = e.downcast_ref::<io::Error>() {
+            if is_transient(io.kind()) {
+                return Failure::Transient(error.to_string());
+            }
+        }
+        source = e.source();
+    }
+    Failure::Fatal(error.to_string())
+}
+
+fn is_transient(kind: ErrorKind) -> bool {
+    matches!(
+        kind,
+        ErrorKind::ConnectionReset
+            | ErrorKind::ConnectionAborted
+            | ErrorKind::BrokenPipe
+            | ErrorKind::UnexpectedEof
+            | ErrorKind::TimedOut
+            | ErrorKind::Interrupted
+    )
+}
+
+pub fn retry_handshake<T, F>(retries: usize, delay: Duration, mut attempt: F) -> Result<T, String>
+where
+    F: FnMut() -> Result<T, Failure>,
+{
+    let mut tries = 0;
+    loop {
+        match attempt() {
+            Ok(stream) => return Ok(stream),
+            Err(Failure::Transient(_)) if tries < retries => {
+                tries += 1;
+                thread::sleep(delay * tries as u32);
+            }
+            Err(Failure::Transient(e)) => {
+                return Err(format!(
+                    "TLS handshake failed after {} attempts: {}",
+                    tries + 1,
+                    e
+                ))
+            }
+            Err(Failure::Fatal(e)) => return Err(e),
+        }
+    }
+}
+
+pub(crate) fn status(response: &[u8]) -> Result<u16, String> {
+    let end = response
+        .windows(2)
+        .position(|x| x == b"\r\n")
+        .ok_or("Not search status line")?;
+    let line = std::str::from_utf8(&response[..end]).map_err(|e| e.to_string())?;
+    let mut parts = line.splitn(3, ' ');
+    match (parts.next(), parts.next()) {
+        (Some(version), Some(code))
+            if version.starts_with("HTTP/")
+                && code.len() == 3
+                && code.bytes().all(|b| b.is_ascii_digit()) =>
+        {
+            code.parse()
+                .map_err(|_| format!("Wrong status line: {}", line))
+        }
+        _ => Err(format!("Wrong status line: {}", line)),
+    }
+}
+
+// Captive portals and some proxies answer with an HTML page and a 200;
+// saying so beats a TOML parse error.
+fn reject_html(response: &[u8]) -> Result<(), String> {
+    let html_type = header(response, "content-type")
+        .map(|value| value.to_ascii_lowercase().contains("html"))
+        .unwrap_or(false);
+    let html_body = content(response)
+        .map(|body| {
+            let start = body.trim_start().to_ascii_lowercase();
+            start.starts_with("<!doctype") || start.starts_with("<html")
+        })
+        .unwrap_or(false);
+    if html_type || html_body {
+        Err("unexpected HTML response; possible captive portal".to_string())
+    } else {
+        Ok(())
+    }
+}
+
+// The value of the first header called `name`, ignoring case.
+fn header<'a>(response: &'a [u8], name: &str) -> Option<&'a str> {
+    let end = response[..response.len().min(MAX_HEADER_LEN)]
+        .windows(4)
+        .position(|x| x == b"\r\n\r\n")?;
+    std::str::from_utf8(&response[..end])
+        .ok()?
+        .split("\r\n")
+        .skip(1)
+        .find_map(|line| {
+            let (key, value) = line.split_once(':')?;
+            if key.trim().eq_ignore_ascii_case(name) {
+                Some(value.trim())
+            } else {
+                None
+            }
+        })
+}
+
+fn raw_body(response: &[u8]) -> Result<&[u8], String> {
+    let pos = response[..response.len().min(MAX_HEADER_LEN)]
+        .windows(4)
+        .position(|x| x == b"\r\n\r\n")
+        .ok_or("Not search pattern")?;
+    Ok(&response[pos + 4..response.len()])
+}
+
+fn body(response: &[u8]) -> Result<&str, String> {
+    std::str::from_utf8(raw_body(response)?).map_err(|e| e.to_string())
+}
+
+// The body with a chunked transfer coding undone, which an HTTP/1.1 server
+// may use even when it closes the connection afterwards.
+fn content(response: &[u8]) -> Result<String, String> {
+    let chunked = header(response, "transfer-encoding")
+        .map(|value| value.to_ascii_lowercase().contains("chunked"))
+        .unwrap_or(false);
+    if !chunked {
+        return body(response).map(|body| body.to_string());
+    }
+    let body = dechunk(raw_body(response)?)?;
+    String::from_utf8(body).map_err(|e| e.to_string())
+}
+
+// Chunk extensions and trailers are skipped; a body cut short is an error
+// rather than a truncated manifest.
+fn dechunk(mut data: &[u8]) -> Result<Vec<u8>, String> {
+    let truncated = || String::from("truncated chunked body");
+    let mut body = vec![];
+    loop {
+        let end = data
+            .windows(2)
+            .position(|x| x == b"\r\n")
+            .ok_or_else(truncated)?;
+        let line = std::str::from_utf8(&data[..end]).map_err(|e| e.to_string())?;
+        let size = line.split(';').next().unwrap_or_default().trim();
+        let size =
+            usize::from_str_radix(size, 16).map_err(|_| format!("Wrong chunk size: {}", line))?;
+        data = &data[end + 2..];
+        if size == 0 {
+            return Ok(body);
+        }
+        if data.len() < 2 || size > data.len() - 2 || &data[size..size + 2] != b"\r\n" {
+            return Err(truncated());
+        }
+        body.extend_from_slice(&data[..size]);
+        data = &data[size + 2..];
+    }
+}
+
+#[test]
+fn test_body() {
+    let response = b"HTTP/2.0 200 OK\r\nx-amz-bucket-region: us-west-1\r\nserver: AmazonS3\r\nx-cache: Miss from cloudfront\r\n\r\ntest message";
+    assert_eq!(body(response), Ok("test message"));
+    let response = b"\r\n\r\ntest message";
+    assert_eq!(body(response), Ok("test message"));
+    let response = b"\r\n\r\ntest message\r\n\r\ntest message";
+    assert_eq!(body(response), Ok("test message\r\n\r\ntest message"));
+}
+
+#[test]
+fn test_chunked() {
+    let response = b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n\
+        9\r\nmanifest-\r\n\
+        b;name=value\r\nversion = \"\r\n\
+        2\r\n2\"\r\n\
+        0\r\nx-trailer: ignored\r\n\r\n";
+    assert_eq!(
+        content(response),
+        Ok(String::from("manifest-version = \"2\""))
+    );
+    // Chunk boundaries may split a character.
+    let response =
+        b"HTTP/1.1 200 OK\r\ntransfer-encoding: Chunked\r\n\r\n1\r\n\xc3\r\n1\r\n\xa9\r\n0\r\n\r\n";
+    assert_eq!(content(response), Ok(String::from("\u{e9}")));
+    // Without the header the body is taken as it is.
+    let response = b"HTTP/1.1 200 OK\r\n\r\n0\r\n\r\n";
+    assert_eq!(content(response), Ok(String::from("0\r\n\r\n")));
+
+    let truncated = Err(String::from("truncated chunked body"));
+    let head = b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n";
+    for chunks in &[
+        &b""[..],
+        b"5\r\nabc",
+        b"3\r\nabc",
+        b"3\r\nabcde\r\n0\r\n\r\n",
+    ] {
+        let response = [&head[..], chunks].concat();
+        assert_eq!(content(&response), truncated, "{:?}", chunks);
+    }
+    let response = [&head[..], b"x\r\nabc\r\n0\r\n\r\n"].concat();
+    assert_eq!(content(&response), Err(String::from("Wrong chunk size: x")));
+    let response = [&head[..], b"ffffffffffffffff\r\nabc\r\n"].concat();
+    assert_eq!(content(&response), truncated);
+
+    let portal = [&head[..], b"6\r\n<html>\r\n0\r\n\r\n"].concat();
+    assert!(reject_html(&portal).is_err());
+}
+
+#[test]
+fn test_status() {
+    assert_eq!(status(b"HTTP/1.1 200 OK\r\n\r\n"), Ok(200));
+    assert_eq!(
+        status(b"HTTP/1.0 404 Not Found\r\nserver: AmazonS3\r\n\r\n"),
+        Ok(404)
+    );
+    assert_eq!(status(b"HTTP/2 426\r\n\r\n"), Ok(426));
+    let garbage: &[&[u8]] = &[
+        b"",
+        b"HTTP/1.1",
+        b"HTTP/1.1 200 OK",
+        b"\r\n\r\n",
+        b"HTTP/1.1200OK\r\n\r\n",
+        b"HTTP/1.1 20 OK\r\n\r\n",
+        b"HTTP/1.1 2000 OK\r\n\r\n",
+        b"HTTP/1.1 +20 OK\r\n\r\n",
+        b"HTTP/1.1 \xff\xfe\xfd\r\n\r\n",
+        b"\xff\xfe HTTP/1.1 200 OK\r\n\r\n",
+        b"<!DOCTYPE html><html>captive portal</html>\r\n\r\n",
+        b"200 OK HTTP/1.1\r\n\r\n",
+    ];
+    for response in garbage {
+        assert!(status(response).is_err(), "{:?}", response);
+    }
+    assert!(body(b"HTTP/1.1 200 OK\r\ncontent-length: 0").is_err());
+    assert!(body(b"HTTP/1.1 200 OK\r\n\r\n\xff\xfe").is_err());
+
+    // A multi-megabyte response without headers is rejected after the
+    // header window instead of being scanned to the end.
+    let mut headless = vec![b'x'; 4 * MAX_HEADER_LEN];
+    assert!(body(&headless).is_err());
+    headless.extend_from_slice(b"\r\n\r\ntail");
+    assert!(body(&headless).is_err());
+    let mut late = vec![b'x'; MAX_HEADER_LEN - 4];
+    late.extend_from_slice(b"\r\n\r\nbody");
+    assert_eq!(body(&late), Ok("body"));
+}
+
+#[test]
+fn test_server() {
+    assert_eq!(
+        crate::manifest::DIST_SERVER.parse::<Server>(),
+        Ok(Server::default())
+    );
+    let mirror: Server = "http://mirror.internal:8080/rust/".parse().unwrap();
+    assert_eq!(
+        mirror,
+        Server {
+            tls: false,
+            host: "mirror.internal".to_string(),
+            port: 8080,
+            prefix: "/rust".to_string(),
+        }
+    );
+    assert_eq!(mirror.host_header(), "mirror.internal:8080");
+    assert_eq!(Server::default().host_header(), HOST);
+    assert_eq!(
+        "https://mirror.internal".parse::<Server>().map(|s| s.port),
+        Ok(443)
+    );
+    assert!("ftp://mirror.internal".parse::<Server>().is_err());
+    assert!("mirror.internal".parse::<Server>().is_err());
+    assert!("https://mirror.internal:port".parse::<Server>().is_err());
+    assert!("https:///rust".parse::<Server>().is_err());
+}
+
+#[test]
+fn test_read_limited() {
+    // Endless, like a misconfigured mirror streaming a huge file.
+    let endless = io::repeat(b'x');
+    assert_eq!(
+        read_limited(endless, 1024),
+        Err(String::from("response too large: over 1024 bytes"))
+    );
+    assert_eq!(read_limited(&b"HTTP/1.1 200 OK"[..], 15).unwrap().len(), 15);
+    assert_eq!(read_limited(&b""[..], 0), Ok(vec![]));
+    assert!(read_limited(&b"x"[..], 0).is_err());
+}
+
+#[test]
+fn test_reject_html() {
+    let portal = b"HTTP/1.1 200 OK\r\nContent-Type: text/html; charset=utf-8\r\n\r\n<html><body>Sign in</body></html>";
+    let error = Err(String::from(
+        "unexpected HTML response; possible captive portal",
+    ));
+    assert_eq!(reject_html(portal), error);
+    assert_eq!(
+        header(portal, "CONTENT-TYPE"),
+        Some("text/html; charset=utf-8")
+    );
+    // Mislabelled pages are caught by their body.
+    let untyped =
+        b"HTTP/1.1 200 OK\r\nContent-Type: text/plain\r\n\r\n\n  <!DOCTYPE html><html></html>";
+    assert_eq!(reject_html(untyped), error);
+    let manifest =
+        b"HTTP/1.1 200 OK\r\ncontent-type: application/toml\r\n\r\nmanifest-version = \"2\"";
+    assert_eq!(reject_html(manifest), Ok(()));
+    assert_eq!(
+        reject_html(b"HTTP/1.1 200 OK\r\n\r\ndate = \"2019-01-01\""),
+        Ok(())
+    );
+    assert_eq!(header(manifest, "server"), None);
+}
+
+#[test]
+fn test_retry_handshake() {
+    let reset = || io::Error::new(ErrorKind::ConnectionReset, "connection reset by peer");
+    assert_eq!(
+        classify(&reset()),
+        Failure::Transient(String::from("connection reset by peer"))
+    );
+    assert_eq!(
+        classify(&io::Error::new(ErrorKind::InvalidData, "bad certificate")),
+        Failure::Fatal(String::from("bad certificate"))
+    );
+
+    let mut attempts = 0;
+    let result = retry_handshake(3, Duration::from_millis(0), || {
+        attempts += 1;
+        match attempts {
+            1 => Err(classify(&reset())),
+            _ => Ok("stream"),
+        }
+    });
+    assert_eq!(result, Ok("stream"));
+    assert_eq!(attempts, 2);
+
+    let mut attempts = 0;
+    let result: Result<(), String> = retry_handshake(2, Duration::from_millis(0), || {
+        attempts += 1;
+        Err(classify(&reset()))
+    });
+    assert_eq!(
+        result,
+        Err(String::from(
+            "TLS handshake failed after 3 attempts: connection reset by peer"
+        ))
+    );
+
+    let mut attempts = 0;
+    let result: Result<(), String> = retry_handshake(2, Duration::from_millis(0), || {
+        attempts += 1;
+        Err(Failure::Fatal(String::from("bad certificate")))
+    });
+    assert_eq!(result, Err(String::from("bad certificate")));
+    assert_eq!(attempts, 1);
+}
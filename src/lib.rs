@@ -0,0 +1,1724 @@
+#[macro_use]
+extern crate serde_derive;
+
+pub mod args;
+pub mod cache;
+pub mod check;
+pub mod error;
+pub mod format;
+pub mod http;
+pub mod manifest;
+pub mod pin;
+pub mod prelude;
+pub mod progress;
+pub mod proxy;
+pub mod report;
+pub mod rustup;
+pub mod source;
+
+use crate::manifest::{dist_server, Compression, Download, FetchError, MissingReason};
+pub use crate::{
+    check::{check_toolchain, CheckOptions},
+    error::CheckError,
+    manifest::{rustc_host, validate_target, Channel, Manifest, Version},
+};
+use crate::{
+    pin::Pin,
+    report::Report,
+    rustup::RustupSettings,
+    source::{Dist, Source},
+};
+use chrono::{naive::NaiveDate, DateTime, Duration, Local, TimeZone};
+use std::{
+    env, fmt,
+    fs::{self, File},
+    io::{self, Read},
+    ops::Sub,
+    path::{Path, PathBuf},
+    process::Command,
+    rc::Rc,
+};
+
+#[cfg(test)]
+mod tests;
+
+const MANIFEST_FILE: &str = "lib/rustlib/multirust-channel-manifest.toml";
+const COMPONENTS_FILE: &str = "lib/rustlib/components";
+const MAX_CLOCK_SKEW_DAYS: i64 = 2;
+
+#[derive(Debug, Clone)]
+pub struct Component {
+    pub name: String,
+    pub required: bool,
+    pub version: Option<Version>,
+}
+
+impl Component {
+    fn from(manifest: Option<&Manifest>, name: &str) -> Self {
+        let required = matches!(name, "rustc" | "cargo");
+        Component {
+            name: name.to_string(),
+            required,
+            version: manifest.and_then(|m| m.pkg_version(std_target(name, "").0)),
+        }
+    }
+
+    pub fn update_info(&self, other: Option<Version>) -> Option<ComponentDiff> {
+        match (&self.version, other) {
+            (Some(version), Some(other)) => {
+                if version < &other {
+                    Some(ComponentDiff {
+                        name: self.name.clone(),
+                        from: version.clone(),
+                        to: other,
+                    })
+                } else {
+                    None
+                }
+            }
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ComponentDiff {
+    pub name: String,
+    pub from: Version,
+    pub to: Version,
+}
+
+impl fmt::Display for ComponentDiff {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} - from {} to {}", self.name, self.from, self.to)
+    }
+}
+
+// The short answer of a check: where to go and how to get there.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Recommendation {
+    // The newest complete nightly within range, if any.
+    pub date: Option<NaiveDate>,
+    pub updates: Vec<ComponentDiff>,
+    // What the newest published nightly lacks when none is complete.
+    pub missing: Vec<String>,
+    pub command: Option<String>,
+}
+
+// One line of the `--verbose` component table.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ComponentRow {
+    pub name: String,
+    pub installed: Option<Version>,
+    pub candidate: Option<Version>,
+    pub status: ComponentStatus,
+}
+
+// One row of `--component-versions`. Some manifests publish versions that
+// don't parse, so an available component can still lack one.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ComponentVersion {
+    pub name: String,
+    pub available: bool,
+    pub version: Option<Version>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ComponentStatus {
+    UpToDate,
+    Update,
+    // Required with `--require` but not installed yet.
+    Add,
+    Missing(MissingReason),
+    Ignored,
+}
+
+impl fmt::Display for ComponentStatus {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ComponentStatus::UpToDate => write!(f, "up-to-date"),
+            ComponentStatus::Update => write!(f, "update"),
+            ComponentStatus::Add => write!(f, "add"),
+            ComponentStatus::Missing(reason) => write!(f, "missing: {}", reason),
+            ComponentStatus::Ignored => write!(f, "ignored"),
+        }
+    }
+}
+
+// A component that keeps a nightly from being complete. Serialized as just
+// the name, so JSON output lists missing components as before.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MissingComponent {
+    pub name: String,
+    pub reason: MissingReason,
+}
+
+impl serde::Serialize for MissingComponent {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        serializer.serialize_str(&self.name)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "result", rename_all = "kebab-case")]
+pub enum CheckResult {
+    UpToDate,
+    Update {
+        date: String,
+        updates: Vec<ComponentDiff>,
+    },
+    Switch {
+        channel: String,
+        date: String,
+        updates: Vec<ComponentDiff>,
+        components: Vec<String>,
+    },
+    // `newest` is the freshest published nightly of the window, with the
+    // components that kept it from being complete.
+    NotFound {
+        days: usize,
+        newest: Option<String>,
+        missing: Vec<MissingComponent>,
+    },
+    TargetUnavailable {
+        target: String,
+        channel: String,
+        date: String,
+    },
+}
+
+// Why the check settled on its candidate, or on none: one concise reason
+// next to the `CheckResult`, where the probe trace lists every date.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "kind", rename_all = "kebab-case")]
+pub enum Rationale {
+    // The newest published release has everything installed.
+    NewestComplete {
+        date: String,
+    },
+    // The newest published nightly lacks components, an older one doesn't.
+    NewestIncomplete {
+        newest: String,
+        missing: Vec<String>,
+        date: String,
+        days_back: i64,
+    },
+    TargetDropped {
+        date: String,
+    },
+    // Nothing complete since the installed nightly, with `--since-installed`.
+    NothingSinceInstalled,
+    NoneComplete {
+        days: usize,
+    },
+}
+
+impl fmt::Display for Rationale {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Rationale::NewestComplete { date } => {
+                write!(f, "the newest published {} is complete", date)
+            }
+            Rationale::NewestIncomplete {
+                newest,
+                missing,
+                date,
+                days_back,
+            } => write!(
+                f,
+                "{} is missing {}; newest complete is {}, {} day{} back",
+                newest,
+                print_vec(missing, ", "),
+                date,
+                days_back,
+                if *days_back == 1 { "" } else { "s" }
+            ),
+            Rationale::TargetDropped { date } => {
+                write!(f, "{} no longer publishes the installed target", date)
+            }
+            Rationale::NothingSinceInstalled => {
+                write!(f, "nothing complete since the installed nightly")
+            }
+            Rationale::NoneComplete { days } => write!(
+                f,
+                "no complete nightly in the last {} day{}",
+                days,
+                if *days == 1 { "" } else { "s" }
+            ),
+        }
+    }
+}
+
+impl CheckResult {
+    // The rustup commands that act on the result, one per line, or `None`
+    // when there is nothing to run.
+    pub fn command(&self) -> Option<String> {
+        match self {
+            CheckResult::Update { .. } => Some("rustup update".to_string()),
+            CheckResult::Switch {
+                channel,
+                date,
+                components,
+                ..
+            } => Some(match components.len() {
+                0 => format!("rustup default {}-{}", channel, date),
+                _ => format!(
+                    "rustup default {}-{}\nrustup component add {}",
+                    channel,
+                    date,
+                    print_vec(components, " ")
+                ),
+            }),
+            _ => None,
+        }
+    }
+}
+
+// A toolchain directory's name and what could be read from it.
+pub type InstalledToolchain = (String, Result<Toolchain, String>);
+
+#[derive(Debug, Clone)]
+pub struct Toolchain {
+    pub channel: String,
+    pub target: String,
+    pub components: Vec<Component>,
+    pub manifest: Option<Manifest>,
+    // Reported by `rustc -vV`, when the install has no manifest to read it
+    // from or detection was forced through rustc.
+    pub rustc: Option<Version>,
+}
+
+impl Toolchain {
+    pub fn new() -> Result<Toolchain, String> {
+        Toolchain::new_in(None)
+    }
+
+    // The active toolchain, looked up under `rustup_home` rather than
+    // `$RUSTUP_HOME` when given.
+    pub fn new_in(rustup_home: Option<&Path>) -> Result<Toolchain, String> {
+        Toolchain::named(&active_toolchain_name(rustup_home)?, rustup_home)
+    }
+
+    // Custom toolchains, e.g. linked local builds, are only named: there is
+    // no upstream manifest to compare them with.
+    pub fn named(name: &str, rustup_home: Option<&Path>) -> Result<Toolchain, String> {
+        if is_custom_name(name) {
+            return Ok(Toolchain {
+                channel: name.to_string(),
+                target: String::new(),
+                components: Vec::new(),
+                manifest: None,
+                rustc: None,
+            });
+        }
+        let (channel, target) = split_toolchain_name(name)?;
+        Toolchain::from_dir(channel, target, &toolchain_dir(name, rustup_home)?)
+    }
+
+    // Every toolchain under `rustup_home`, by name; custom or unreadable
+    // ones keep the reason they can't be checked.
+    pub fn installed_in(rustup_home: Option<&Path>) -> Result<Vec<InstalledToolchain>, String> {
+        let dir = toolchains_dir(rustup_home)?;
+        let mut names: Vec<String> = fs::read_dir(&dir)
+            .map_err(|e| format!("Can't list {}: {}", dir.display(), e))?
+            .filter_map(|entry| entry.ok())
+            .filter(|entry| entry.path().is_dir())
+            .filter_map(|entry| entry.file_name().into_string().ok())
+            .collect();
+        names.sort();
+        Ok(names
+            .into_iter()
+            .map(|name| {
+                let toolchain = Toolchain::named(&name, rustup_home);
+                (name, toolchain)
+            })
+            .collect())
+    }
+
+    // Very old or minimal installs may lack the channel manifest; the
+    // components file is still there, and rustc can name its own version.
+    pub fn from_dir(channel: String, target: String, dir: &Path) -> Result<Toolchain, String> {
+        let manifest = installed_manifest(&dir.join(MANIFEST_FILE))?;
+        let rustc = match manifest {
+            Some(_) => None,
+            None => rustc_version(&dir.join("bin").join("rustc")),
+        };
+        let mut components: Vec<Component> = installed_components(dir, &target)?
+            .iter()
+            .map(|s| Component::from(manifest.as_ref(), s))
+            .collect();
+        components.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(Toolchain {
+            channel,
+            target,
+            components,
+            manifest,
+            rustc,
+        })
+    }
+
+    // Detects the toolchain from the `rustc` on PATH instead of
+    // `RUSTUP_TOOLCHAIN`, for environments where the variable is unreliable.
+    pub fn from_rustc() -> Result<Toolchain, String> {
+        Toolchain::from_rustc_in(None)
+    }
+
+    pub fn from_rustc_in(rustup_home: Option<&Path>) -> Result<Toolchain, String> {
+        let output = rustc_verbose(Path::new("rustc"))
+            .map_err(|e| format!("rustc not found on PATH: {}", e))?;
+        Toolchain::from_rustc_verbose(&output, |name| toolchain_dir(name, rustup_home))
+    }
+
+    fn from_rustc_verbose(
+        output: &str,
+        dir: impl FnOnce(&str) -> Result<PathBuf, String>,
+    ) -> Result<Toolchain, String> {
+        let version = Version::parse_rustc_verbose(output)
+            .ok_or_else(|| "Can't read the version from rustc -vV".to_string())?;
+        let target =
+            rustc_host(output).ok_or_else(|| "Can't read the host from rustc -vV".to_string())?;
+        validate_target(&target)?;
+        let channel = version.channel_str();
+        let dir = dir(&format!("{}-{}", channel, target))?;
+        Ok(Toolchain {
+            rustc: Some(version),
+            ..Toolchain::from_dir(channel, target, &dir)?
+        })
+    }
+
+    // A version reported by rustc itself wins over the manifest's.
+    pub fn pkg_version(&self, name: &str) -> Option<Version> {
+        match (&self.manifest, name) {
+            (_, "rust") | (_, "rustc") if self.rustc.is_some() => self.rustc.clone(),
+            (Some(manifest), _) => manifest.pkg_version(name),
+            (None, _) => None,
+        }
+    }
+
+    // Sorted, since the components file order differs between installs.
+    pub fn component_list(&self) -> Vec<String> {
+        let mut list: Vec<String> = self
+            .components
+            .iter()
+            .filter(|c| !c.required)
+            .map(|c| c.name.to_string())
+            .collect();
+        list.sort();
+        list
+    }
+
+    // Same order as `component_list`, with `None` where the local manifest
+    // doesn't know the component.
+    pub fn components_with_versions(&self) -> Vec<(String, Option<Version>)> {
+        self.component_list()
+            .into_iter()
+            .map(|name| {
+                let version = self.pkg_version(&name);
+                (name, version)
+            })
+            .collect()
+    }
+
+    // Treats rustc and cargo like any other component in listings, diffs
+    // and add commands.
+    pub fn without_required(mut self) -> Toolchain {
+        for component in &mut self.components {
+            component.required = false;
+        }
+        self
+    }
+
+    // Cross targets with an installed `rust-std`; the host's own std is
+    // listed as plain `rust-std` once its target suffix is stripped.
+    pub fn extra_std_targets(&self) -> Vec<String> {
+        self.components
+            .iter()
+            .filter_map(|c| c.name.strip_prefix("rust-std-"))
+            .map(|target| target.to_string())
+            .collect()
+    }
+
+    // Where the install disagrees with itself: components its own manifest
+    // doesn't provide, or a `rustc` other than the one the manifest lists.
+    pub fn inconsistencies(&self, rustc: Option<&Version>) -> Vec<String> {
+        let manifest = match &self.manifest {
+            Some(manifest) => manifest,
+            None => return vec!["No local manifest to compare with".to_string()],
+        };
+        let mut problems: Vec<String> = self
+            .components
+            .iter()
+            .filter(|c| {
+                let (name, target) = std_target(&c.name, &self.target);
+                !manifest.contains_component(name, target)
+            })
+            .map(|c| format!("{} is installed but not in the manifest", c.name))
+            .collect();
+        if let (Some(rustc), Some(listed)) = (rustc, manifest.pkg_version("rustc")) {
+            if *rustc != listed {
+                problems.push(format!(
+                    "rustc reports {} but the manifest lists {}",
+                    rustc, listed
+                ));
+            }
+        }
+        problems
+    }
+
+    pub fn is_custom(&self) -> bool {
+        !is_channel_name(&self.channel)
+    }
+
+    // What to say instead of checking a custom toolchain.
+    pub fn custom_note(&self) -> Option<String> {
+        if self.is_custom() {
+            Some(custom_toolchain(&self.channel))
+        } else {
+            None
+        }
+    }
+
+    pub fn installed_date(&self) -> Option<NaiveDate> {
+        match &self.manifest {
+            Some(manifest) => Some(manifest.date),
+            None => self.rustc.as_ref().map(|v| v.commit.date),
+        }
+    }
+
+    // A guard for CI, where a broken install shouldn't quietly pass.
+    pub fn check_min_components(&self, min: usize) -> Result<(), String> {
+        if self.components.len() < min {
+            Err(format!(
+                "Expected at least {} components but detected {}; detection may be broken",
+                min,
+                self.components.len()
+            ))
+        } else {
+            Ok(())
+        }
+    }
+
+    // A freshly repaired toolchain can have an empty components file; the
+    // check then only compares the `rust` package itself.
+    pub fn no_components(&self) -> Option<String> {
+        if self.components.is_empty() {
+            Some("No components detected; only the base toolchain will be compared".to_string())
+        } else {
+            None
+        }
+    }
+
+    // A build can't predate its commit, and a nightly is normally cut within a
+    // day or two of it; anything else hints at a stale or hand-edited install.
+    pub fn date_mismatch(&self) -> Option<String> {
+        let commit = self.pkg_version("rustc")?.commit.date;
+        let installed = self.installed_date()?;
+        let lag = (installed - commit).num_days();
+        let suspicious = lag < 0 || (self.channel == "nightly" && lag > 7);
+        if suspicious {
+            Some(format!(
+                "Installed manifest is dated {} but rustc was built from a commit of {}; \
+                 the install may be stale or modified",
+                installed, commit
+            ))
+        } else {
+            None
+        }
+    }
+
+    pub fn info(&self) -> String {
+        let components = match self.component_list().len() {
+            0 => "With no components".to_string(),
+            1 => format!("With component: {}", self.component_list()[0]),
+            _ => format!(
+                "With components: {}",
+                print_vec(&self.component_list(), ", ")
+            ),
+        };
+        self.info_with(components)
+    }
+
+    // Like `info`, but one component per line with its installed version.
+    pub fn info_verbose(&self) -> String {
+        let mut components = match self.component_list().len() {
+            0 => "With no components".to_string(),
+            _ => "With components:".to_string(),
+        };
+        for (name, version) in self.components_with_versions() {
+            match version {
+                Some(version) => components.push_str(&format!(
+                    "\n  {} {} ({} {})",
+                    name, version.version, version.commit.hash, version.commit.date
+                )),
+                None => components.push_str(&format!("\n  {} (unknown version)", name)),
+            }
+        }
+        self.info_with(components)
+    }
+
+    fn info_with(&self, components: String) -> String {
+        match self.pkg_version("rustc") {
+            Some(version) => format!(
+                "Installed: {}-{} {} ({} {})\n{}",
+                self.channel,
+                self.target,
+                version.version,
+                version.commit.hash,
+                version.commit.date,
+                components
+            ),
+            None => format!("Not found installed rustc\n{}", components),
+        }
+    }
+}
+
+// How one channel's newest release compares to the installed toolchain of
+// that channel, if there is one. A failed lookup leaves its side `None` and
+// says why in `error`.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ChannelStatus {
+    pub channel: Channel,
+    pub installed: Option<Version>,
+    pub latest: Option<Version>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl ChannelStatus {
+    pub fn has_update(&self) -> bool {
+        match (&self.installed, &self.latest) {
+            (Some(installed), Some(latest)) => installed < latest,
+            _ => false,
+        }
+    }
+}
+
+// `installed` gives `None` for a channel that isn't installed.
+pub fn all_channels(
+    source: &dyn Source,
+    installed: &dyn Fn(&Channel) -> Result<Option<Manifest>, String>,
+) -> Vec<ChannelStatus> {
+    Channel::all()
+        .iter()
+        .map(|channel| {
+            let mut errors = vec![];
+            let installed = match installed(channel) {
+                Ok(manifest) => manifest.and_then(|m| m.pkg_version("rust")),
+                Err(e) => {
+                    errors.push(format!("installed toolchain: {}", e));
+                    None
+                }
+            };
+            let latest = match source.latest(&channel.to_string()) {
+                Ok(manifest) => manifest.pkg_version("rust"),
+                Err(e) => {
+                    errors.push(format!("latest release: {}", CheckError::from(e)));
+                    None
+                }
+            };
+            ChannelStatus {
+                channel: channel.clone(),
+                installed,
+                latest,
+                error: if errors.is_empty() {
+                    None
+                } else {
+                    Some(errors.join("; "))
+                },
+            }
+        })
+        .collect()
+}
+
+// What fetching a single date gave: a manifest, nothing published, or an
+// error that left the answer unknown.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FetchOutcome {
+    pub date: NaiveDate,
+    pub manifest: Option<Manifest>,
+    pub error: Option<CheckError>,
+}
+
+impl FetchOutcome {
+    pub fn is_published(&self) -> bool {
+        self.manifest.is_some()
+    }
+
+    // Neither published nor failed: nothing is out for that date.
+    pub fn is_unpublished(&self) -> bool {
+        self.manifest.is_none() && self.error.is_none()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ProbeRecord {
+    pub date: String,
+    pub found: bool,
+    // Only emitted for published manifests that dropped the target.
+    #[serde(skip_serializing_if = "is_true")]
+    pub target_available: bool,
+    pub missing: Vec<String>,
+    pub version: Option<Version>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub unusable: Option<String>,
+}
+
+fn is_true(value: &bool) -> bool {
+    *value
+}
+
+// Documentation components `--skip-docs` stops requiring, including the names
+// they were published under before being renamed.
+pub const DOCS_COMPONENTS: &[&str] = &["rust-docs", "rust-docs-json", "rust-docs-json-preview"];
+
+// Which calendar decides what "today" is. Nightlies are published on a UTC
+// schedule, so near midnight the local date can be a day ahead of the newest
+// build (e.g. in UTC+14) and the first probe is a guaranteed miss; anchoring
+// to UTC avoids that at the cost of dates that differ from the wall clock.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Anchor {
+    Local,
+    Utc,
+}
+
+impl Anchor {
+    pub fn date<Tz: TimeZone>(self, now: &DateTime<Tz>) -> NaiveDate {
+        match self {
+            Anchor::Local => now.naive_local().date(),
+            Anchor::Utc => now.naive_utc().date(),
+        }
+    }
+
+    pub fn today(self) -> NaiveDate {
+        self.date(&Local::now())
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Rust {
+    offset: i64,
+    date: NaiveDate,
+    today: NaiveDate,
+    anchor: Anchor,
+    // Shared by every position of a scan rather than copied into each.
+    toolchain: Rc<Toolchain>,
+    manifest: Option<Manifest>,
+    source: Rc<dyn Source>,
+    ignored: Vec<String>,
+    required: Vec<String>,
+    fail_on_missing: bool,
+    // The oldest date the scan goes back to, if narrower than `max_days`.
+    floor: Option<NaiveDate>,
+    // Why this date's manifest was skipped, when it couldn't be parsed.
+    unusable: Option<String>,
+    compression: Compression,
+}
+
+impl Rust {
+    pub fn new() -> Option<Rust> {
+        Rust::with_source(Rc::new(Dist::cached()), Anchor::Local).ok()
+    }
+
+    pub fn with_source(source: Rc<dyn Source>, anchor: Anchor) -> Result<Rust, CheckError> {
+        let toolchain = Toolchain::new().map_err(CheckError::Toolchain)?;
+        Rust::for_toolchain(toolchain, source, anchor)
+    }
+
+    // The scan starts at offset -1 so that the first `next` probes `today`
+    // itself; `date` holds the manifest fetched for today until then.
+    pub fn for_toolchain(
+        toolchain: Toolchain,
+        source: Rc<dyn Source>,
+        anchor: Anchor,
+    ) -> Result<Rust, CheckError> {
+        let today = anchor.today();
+        let mut rust = Rust {
+            offset: -1,
+            date: today,
+            today,
+            anchor,
+            toolchain: Rc::new(toolchain),
+            manifest: None,
+            source,
+            ignored: Vec::new(),
+            required: Vec::new(),
+            fail_on_missing: false,
+            floor: None,
+            unusable: None,
+            compression: Compression::default(),
+        };
+        rust.load()?;
+        Ok(rust)
+    }
+
+    // Fetches the manifest for the current date. A malformed one leaves the
+    // date unusable, as in a scan; a failed download is an error rather
+    // than a date with nothing published.
+    fn load(&mut self) -> Result<(), CheckError> {
+        self.manifest = None;
+        self.unusable = None;
+        match self
+            .source
+            .manifest(&self.date_str(), &self.toolchain.channel)
+        {
+            Ok(manifest) => self.manifest = manifest,
+            Err(FetchError::Malformed(e)) => self.unusable = Some(e),
+            Err(FetchError::Fetch(e)) => return Err(CheckError::Fetch(e)),
+        }
+        Ok(())
+    }
+
+    pub fn toolchain(&self) -> &Toolchain {
+        &self.toolchain
+    }
+
+    pub fn from_date(date_str: &str) -> Option<Rust> {
+        match Toolchain::new() {
+            Ok(toolchain) => {
+                let source: Rc<dyn Source> = Rc::new(Dist::cached());
+                let anchor = Anchor::Local;
+                let today = anchor.today();
+                let date = NaiveDate::parse_from_str(date_str, "%Y-%m-%d").ok()?;
+                let offset = (today - date).num_days() - 1;
+                let mut rust = Rust {
+                    offset,
+                    date,
+                    today,
+                    anchor,
+                    toolchain: Rc::new(toolchain),
+                    manifest: None,
+                    source,
+                    ignored: Vec::new(),
+                    required: Vec::new(),
+                    fail_on_missing: false,
+                    floor: None,
+                    unusable: None,
+                    compression: Compression::default(),
+                };
+                rust.load().ok()?;
+                Some(rust)
+            }
+            Err(_) => None,
+        }
+    }
+
+    // Re-detects the installed toolchain and restarts the scan from today, so
+    // a long-running process notices both `rustup update` and new nightlies.
+    pub fn refresh(&mut self) -> Result<(), CheckError> {
+        self.refresh_with(Toolchain::new().map_err(CheckError::Toolchain)?)
+    }
+
+    pub fn refresh_with(&mut self, toolchain: Toolchain) -> Result<(), CheckError> {
+        self.toolchain = Rc::new(toolchain);
+        self.offset = -1;
+        self.today = self.anchor.today();
+        self.date = self.today;
+        self.load()
+    }
+
+    // Ignored components don't have to be available for a nightly to count
+    // as complete.
+    pub fn set_ignored(&mut self, ignored: Vec<String>) {
+        self.ignored = ignored;
+    }
+
+    // Lets a lagging cross-target std stop blocking the host toolchain.
+    // Returns the targets now ignored.
+    pub fn ignore_extra_targets(&mut self) -> Vec<String> {
+        let targets = self.toolchain.extra_std_targets();
+        self.ignored
+            .extend(targets.iter().map(|t| format!("rust-std-{}", t)));
+        targets
+    }
+
+    // Components a nightly must provide regardless of what is installed;
+    // empty means the installed components.
+    pub fn set_required(&mut self, required: Vec<String>) {
+        self.required = required;
+    }
+
+    // Stops the scan at the newest published nightly instead of falling back
+    // to an older complete one.
+    pub fn set_fail_on_missing(&mut self, fail_on_missing: bool) {
+        self.fail_on_missing = fail_on_missing;
+    }
+
+    // With a floor the scan never looks at older dates, and finding nothing
+    // complete since then means there is nothing newer to move to.
+    pub fn set_floor(&mut self, floor: Option<NaiveDate>) {
+        self.floor = floor;
+    }
+
+    // The tarball format `downloads` lists when both are published.
+    pub fn set_compression(&mut self, compression: Compression) {
+        self.compression = compression;
+    }
+
+    pub fn is_ignored(&self, name: &str) -> bool {
+        let resolved = match &self.manifest {
+            Some(manifest) => manifest.resolve_name(name),
+            None => name,
+        };
+        self.ignored.iter().any(|i| i == name || i == resolved)
+    }
+
+    // Broken nightlies can list a `rust` version without it being installable,
+    // so the meta-package counts as missing like any component.
+    pub fn missing_components(&self) -> Vec<String> {
+        self.missing_components_detailed()
+            .into_iter()
+            .map(|m| m.name)
+            .collect()
+    }
+
+    // Like `missing_components`, with why each one is missing.
+    pub fn missing_components_detailed(&self) -> Vec<MissingComponent> {
+        let manifest = match &self.manifest {
+            Some(manifest) => manifest,
+            None => return Vec::new(),
+        };
+        let target = &self.toolchain.target;
+        let wanted: Vec<&String> = match self.required.len() {
+            0 => self.toolchain.components.iter().map(|c| &c.name).collect(),
+            _ => self.required.iter().collect(),
+        };
+        let rust = "rust".to_string();
+        Some(&rust)
+            .into_iter()
+            .chain(wanted.into_iter().filter(|c| !self.is_ignored(c)))
+            .filter_map(|name| {
+                let (pkg, target) = std_target(name, target);
+                Some(MissingComponent {
+                    name: name.clone(),
+                    reason: manifest.missing_reason(pkg, target)?,
+                })
+            })
+            .collect()
+    }
+
+    pub fn manifest_pkg_version(&self, name: &str) -> Option<Version> {
+        match &self.manifest {
+            Some(manifest) => manifest.pkg_version(name),
+            None => None,
+        }
+    }
+
+    // The date the fetched manifest itself carries, which a dist server may
+    // set apart from the date it was requested under.
+    pub fn candidate_manifest_date(&self) -> Option<NaiveDate> {
+        self.manifest.as_ref().map(|manifest| manifest.date)
+    }
+
+    // Only when the two dates disagree.
+    pub fn date_note(&self) -> Option<String> {
+        match self.candidate_manifest_date() {
+            Some(date) if date != self.date => Some(format!(
+                "requested {}, newest complete {}",
+                self.date_str(),
+                date
+            )),
+            _ => None,
+        }
+    }
+
+    pub fn date_str(&self) -> String {
+        self.date.format("%Y-%m-%d").to_string()
+    }
+
+    pub fn probe(&self) -> ProbeRecord {
+        ProbeRecord {
+            date: self.date_str(),
+            found: self.manifest.is_some(),
+            target_available: self.manifest.is_none() || self.target_available(),
+            missing: match self.target_available() {
+                true => self.missing_components(),
+                false => Vec::new(),
+            },
+            version: self.manifest_pkg_version("rust"),
+            unusable: self.unusable.clone(),
+        }
+    }
+
+    // Stable only publishes dated manifests on release days, so compare
+    // against the current stable manifest instead of scanning dates.
+    pub fn check_stable(&self) -> Result<CheckResult, CheckError> {
+        self.latest_stable()?.checked_result()
+    }
+
+    // Nightlies are published daily, so a today well past the newest one
+    // points at a wrong system clock rather than a slow release.
+    pub fn clock_skew(&self) -> Option<String> {
+        let latest = self.source.latest(&Channel::Nightly.to_string()).ok()?;
+        let ahead = (self.today - latest.date).num_days();
+        if ahead > MAX_CLOCK_SKEW_DAYS {
+            Some(format!(
+                "System date {} is {} days past the latest nightly ({}); \
+                 the clock may be wrong",
+                self.today, ahead, latest.date
+            ))
+        } else {
+            None
+        }
+    }
+
+    fn latest_stable(&self) -> Result<Rust, CheckError> {
+        let manifest = self.source.latest(&self.toolchain.channel)?;
+        Ok(Rust {
+            offset: 0,
+            date: manifest.date,
+            manifest: Some(manifest),
+            ..self.clone()
+        })
+    }
+
+    // The current stable as a way out of a run of broken nightlies, if it
+    // has every component this check requires.
+    pub fn stable_alternative(&self) -> Result<Option<Rust>, CheckError> {
+        let manifest = self.source.latest("stable")?;
+        let stable = Rust {
+            offset: 0,
+            date: manifest.date,
+            manifest: Some(manifest),
+            toolchain: Rc::new(Toolchain {
+                channel: "stable".to_string(),
+                ..(*self.toolchain).clone()
+            }),
+            ..self.clone()
+        };
+        Ok(if stable.is_complete() {
+            Some(stable)
+        } else {
+            None
+        })
+    }
+
+    fn checked_result(&self) -> Result<CheckResult, CheckError> {
+        if !self.target_available() {
+            return Ok(self.target_unavailable());
+        }
+        self.check_result()
+            .ok_or_else(|| CheckError::MissingComponents(self.missing_components()))
+    }
+
+    pub fn to_report(&self, max_days: usize) -> Result<Report, CheckError> {
+        self.report_with(max_days, &mut |_| {})
+    }
+
+    // Runs the whole check: stable compares against the current release,
+    // other channels scan back for the newest complete nightly.
+    pub fn report_with(
+        &self,
+        max_days: usize,
+        on_probe: &mut dyn FnMut(&Rust),
+    ) -> Result<Report, CheckError> {
+        // The newest published manifest, to tell a dropped target apart from
+        // nightlies that are merely incomplete.
+        let mut newest: Option<Rust> = None;
+        // Fewer than `max_days` when `fail_on_missing` cut the scan short.
+        let mut probed = 0;
+        // Installed components missing from every published nightly probed,
+        // and how many of those there were.
+        let mut always_missing: Option<Vec<String>> = None;
+        let mut published = 0;
+        let candidate = if self.toolchain.channel == "stable" {
+            Some(self.latest_stable()?)
+        } else {
+            self.scan(max_days, &mut |r| {
+                probed += 1;
+                if newest.is_none() && r.manifest.is_some() {
+                    newest = Some(r.clone());
+                }
+                if r.manifest.is_some() && r.target_available() {
+                    published += 1;
+                    let missing = r.missing_components();
+                    always_missing = Some(match always_missing.take() {
+                        None => missing,
+                        Some(before) => {
+                            before.into_iter().filter(|c| missing.contains(c)).collect()
+                        }
+                    });
+                }
+                on_probe(r)
+            })?
+        };
+        // Measured from the newest published nightly rather than today, so a
+        // today that isn't out yet doesn't turn `rustup update` into a switch.
+        let candidate = match (candidate, &newest) {
+            (Some(v), Some(newest)) => Some(v.anchored_at(newest.date)),
+            (candidate, _) => candidate,
+        };
+        let result = match (&candidate, &newest) {
+            (Some(v), _) => v.checked_result()?,
+            (None, Some(v)) if !v.target_available() => v.target_unavailable(),
+            (None, _) if self.floor.is_some() => CheckResult::UpToDate,
+            (None, newest) => CheckResult::NotFound {
+                days: probed,
+                newest: newest.as_ref().map(Rust::date_str),
+                missing: newest
+                    .as_ref()
+                    .map(Rust::missing_components_detailed)
+                    .unwrap_or_default(),
+            },
+        };
+        let rationale = match (&candidate, &newest) {
+            (Some(v), _) if !v.target_available() => {
+                Rationale::TargetDropped { date: v.date_str() }
+            }
+            (Some(v), Some(newest)) if v.date < newest.date => Rationale::NewestIncomplete {
+                newest: newest.date_str(),
+                missing: newest.missing_components(),
+                date: v.date_str(),
+                days_back: (newest.date - v.date).num_days(),
+            },
+            (Some(v), _) => Rationale::NewestComplete { date: v.date_str() },
+            (None, Some(v)) if !v.target_available() => {
+                Rationale::TargetDropped { date: v.date_str() }
+            }
+            (None, _) if self.floor.is_some() => Rationale::NothingSinceInstalled,
+            (None, _) => Rationale::NoneComplete { days: probed },
+        };
+        // The candidate when it is worth moving to.
+        let recommended = match (&candidate, &result) {
+            (Some(v), CheckResult::Update { .. }) | (Some(v), CheckResult::Switch { .. }) => {
+                Some(v)
+            }
+            _ => None,
+        };
+        Ok(Report {
+            banner: self.toolchain.info(),
+            toolchain: format!("{}-{}", self.toolchain.channel, self.toolchain.target),
+            installed: self.toolchain.pkg_version("rust"),
+            installed_components: self.toolchain.component_list(),
+            candidate: candidate.as_ref().map(Rust::date_str),
+            date_note: candidate.as_ref().and_then(Rust::date_note),
+            // One broken nightly says little, so it takes at least two.
+            removed_upstream: match always_missing {
+                Some(missing) if published >= 2 => missing
+                    .into_iter()
+                    .filter(|c| self.toolchain.components.iter().any(|i| &i.name == c))
+                    .collect(),
+                _ => Vec::new(),
+            },
+            missing: match &candidate {
+                Some(v) if v.target_available() => v.missing_components(),
+                _ => Vec::new(),
+            },
+            component_add: recommended
+                .and_then(|v| format::component_add(&v.toolchain_name(), &v.add_components())),
+            pin: recommended.map(Rust::pin),
+            downloads: recommended
+                .map(|v| v.downloads(&dist_server()))
+                .unwrap_or_default(),
+            components: candidate
+                .as_ref()
+                .or(newest.as_ref())
+                .map(Rust::component_table)
+                .unwrap_or_default(),
+            rationale,
+            result,
+        })
+    }
+
+    fn provides(&self, name: &str) -> bool {
+        let (name, target) = std_target(name, &self.toolchain.target);
+        match &self.manifest {
+            Some(manifest) => manifest.contains_component(name, target),
+            None => false,
+        }
+    }
+
+    pub fn is_complete(&self) -> bool {
+        self.manifest.is_some() && self.target_available() && self.missing_components().is_empty()
+    }
+
+    // False when the manifest no longer publishes anything for the installed
+    // target, in which case every component would show up as missing.
+    pub fn target_available(&self) -> bool {
+        match &self.manifest {
+            Some(manifest) => manifest.has_target(&self.toolchain.target),
+            None => false,
+        }
+    }
+
+    // Installed optional components under the names this manifest publishes
+    // them as, leaving out ignored ones it doesn't provide.
+    pub fn add_components(&self) -> Vec<String> {
+        let mut components: Vec<String> = self
+            .toolchain
+            .component_list()
+            .into_iter()
+            .filter(|c| !self.is_ignored(c) || self.provides(c))
+            .map(|c| match &self.manifest {
+                Some(manifest) => manifest.resolve_name(&c).to_string(),
+                None => c,
+            })
+            .collect();
+        components.sort();
+        components
+    }
+
+    // Where to fetch `rust` and every installed component of this position,
+    // with `server` in place of the official dist server.
+    pub fn downloads(&self, server: &str) -> Vec<(String, Download)> {
+        let manifest = match &self.manifest {
+            Some(manifest) => manifest,
+            None => return Vec::new(),
+        };
+        let mut names: Vec<String> = self
+            .toolchain
+            .components
+            .iter()
+            .map(|c| manifest.resolve_name(&c.name).to_string())
+            .filter(|name| name != "rust")
+            .collect();
+        names.sort();
+        names.dedup();
+        names.insert(0, "rust".to_string());
+        names
+            .into_iter()
+            .filter_map(|name| {
+                let download = manifest
+                    .pkg_for_target(&name, &self.toolchain.target)?
+                    .resolved_url(server, self.compression)?;
+                Some((name, download))
+            })
+            .collect()
+    }
+
+    pub fn pin(&self) -> Pin {
+        Pin {
+            channel: format!("{}-{}", self.toolchain.channel, self.date_str()),
+            components: self.add_components(),
+            targets: vec![self.toolchain.target.clone()],
+        }
+    }
+
+    // The fully qualified toolchain this position corresponds to.
+    pub fn toolchain_name(&self) -> String {
+        format!(
+            "{}-{}-{}",
+            self.toolchain.channel,
+            self.date_str(),
+            self.toolchain.target
+        )
+    }
+
+    fn target_unavailable(&self) -> CheckResult {
+        CheckResult::TargetUnavailable {
+            target: self.toolchain.target.clone(),
+            channel: self.toolchain.channel.clone(),
+            date: self.date_str(),
+        }
+    }
+
+    // The `max_days` dates before the current position, newest first, each
+    // as its own snapshot, stopping early at the floor. Unlike iterating
+    // `Rust` directly this ends, leaves `self` alone and reports failed
+    // fetches instead of treating them as unpublished dates.
+    pub fn scan_iter(&self, max_days: usize) -> impl Iterator<Item = Result<Rust, CheckError>> {
+        let start = self.clone();
+        self.scan_offsets(max_days)
+            .map(move |offset| start.at_offset(offset))
+    }
+
+    // Like `scan_iter`, but every date yields what happened to it and the
+    // iteration goes on past failures.
+    pub fn outcomes(&self, max_days: usize) -> impl Iterator<Item = FetchOutcome> {
+        let start = self.clone();
+        self.scan_offsets(max_days).map(move |offset| {
+            let date = start.today.sub(Duration::days(offset));
+            let (manifest, error) = match start.source.manifest(
+                &date.format("%Y-%m-%d").to_string(),
+                &start.toolchain.channel,
+            ) {
+                Ok(manifest) => (manifest, None),
+                Err(FetchError::Malformed(e)) => (None, Some(CheckError::Malformed(e))),
+                Err(FetchError::Fetch(e)) => (None, Some(CheckError::Fetch(e))),
+            };
+            FetchOutcome {
+                date,
+                manifest,
+                error,
+            }
+        })
+    }
+
+    fn scan_offsets(&self, max_days: usize) -> impl Iterator<Item = i64> {
+        let (today, floor, offset) = (self.today, self.floor, self.offset);
+        (1..=max_days as i64)
+            .map(move |step| offset + step)
+            .take_while(move |offset| match floor {
+                Some(floor) => today.sub(Duration::days(*offset)) >= floor,
+                None => true,
+            })
+    }
+
+    // `rust-std` availability across a target family in the channel's
+    // newest release, with that release's date.
+    pub fn target_family(
+        &self,
+        prefix: &str,
+    ) -> Result<(NaiveDate, Vec<(String, bool)>), CheckError> {
+        let manifest = self.source.latest(&self.toolchain.channel)?;
+        Ok((manifest.date, manifest.target_family(prefix)))
+    }
+
+    // Where the installed version sits on the six-week release train, from
+    // the current stable release.
+    pub fn release_train(&self) -> Result<Option<String>, CheckError> {
+        let stable = self.source.latest("stable")?;
+        Ok(
+            match (
+                self.toolchain.pkg_version("rust"),
+                stable.pkg_version("rust"),
+            ) {
+                (Some(installed), Some(current)) => {
+                    format::release_train(&installed, &current, stable.date, self.today)
+                }
+                _ => None,
+            },
+        )
+    }
+
+    // The newest date within `max_days` with any manifest, complete or not.
+    pub fn latest_published(&self, max_days: usize) -> Result<Option<Rust>, CheckError> {
+        for rust in self.scan_iter(max_days) {
+            let rust = rust?;
+            if rust.manifest.is_some() {
+                return Ok(Some(rust));
+            }
+        }
+        Ok(None)
+    }
+
+    fn anchored_at(&self, today: NaiveDate) -> Rust {
+        Rust {
+            offset: (today - self.date).num_days(),
+            today,
+            ..self.clone()
+        }
+    }
+
+    // Whether `component` was available on each of the `max_days` dates,
+    // newest first; `None` for dates with nothing published.
+    pub fn availability(
+        &self,
+        component: &str,
+        max_days: usize,
+    ) -> Result<Vec<(String, Option<bool>)>, CheckError> {
+        self.scan_iter(max_days)
+            .map(|rust| {
+                let rust = rust?;
+                let available = rust
+                    .manifest
+                    .as_ref()
+                    .map(|m| m.contains_component(component, &rust.toolchain.target));
+                Ok((rust.date_str(), available))
+            })
+            .collect()
+    }
+
+    // A malformed manifest doesn't end the scan; the date is kept as
+    // unusable, like one with nothing published, and the reason recorded.
+    fn at_offset(&self, offset: i64) -> Result<Rust, CheckError> {
+        let mut rust = Rust {
+            offset,
+            date: self.today.sub(Duration::days(offset)),
+            ..self.clone()
+        };
+        rust.load()?;
+        Ok(rust)
+    }
+
+    // The position at `date`, which may have nothing published.
+    pub fn at_date(&self, date: NaiveDate) -> Result<Rust, CheckError> {
+        self.at_offset((self.today - date).num_days())
+    }
+
+    // Every installed component with its version on this position's
+    // nightly, looked up under the name it is published as; no list at all
+    // when nothing was published.
+    pub fn component_versions(&self) -> Option<Vec<ComponentVersion>> {
+        let manifest = self.manifest.as_ref()?;
+        Some(
+            self.toolchain
+                .components
+                .iter()
+                .map(|c| {
+                    let available = self.provides(&c.name);
+                    ComponentVersion {
+                        name: c.name.clone(),
+                        available,
+                        version: match available {
+                            true => manifest
+                                .pkg_version(manifest.resolve_name(std_target(&c.name, "").0)),
+                            false => None,
+                        },
+                    }
+                })
+                .collect(),
+        )
+    }
+
+    pub fn unusable(&self) -> Option<&str> {
+        self.unusable.as_deref()
+    }
+
+    // Walks back from the current position for at most `max_days` dates and
+    // stops at the first complete nightly, or the first published one with
+    // `fail_on_missing`, reporting every probe on the way.
+    pub fn scan(
+        &self,
+        max_days: usize,
+        on_probe: &mut dyn FnMut(&Rust),
+    ) -> Result<Option<Rust>, CheckError> {
+        for rust in self.scan_iter(max_days) {
+            let rust = rust?;
+            on_probe(&rust);
+            if rust.is_complete() {
+                return Ok(Some(rust));
+            }
+            if self.fail_on_missing && rust.manifest.is_some() {
+                return Ok(None);
+            }
+        }
+        Ok(None)
+    }
+
+    pub fn latest_complete(&self, max_days: usize) -> Result<Option<Rust>, CheckError> {
+        self.scan(max_days, &mut |_| {})
+    }
+
+    // The scan, version diff and command in one call, measured from the
+    // newest published nightly like `report_with`.
+    pub fn as_of_latest_complete(&self, max_days: usize) -> Result<Recommendation, CheckError> {
+        let newest = self.latest_published(max_days)?;
+        let candidate = match (self.latest_complete(max_days)?, &newest) {
+            (Some(v), Some(newest)) => Some(v.anchored_at(newest.date)),
+            (candidate, _) => candidate,
+        };
+        Ok(match candidate {
+            Some(v) => Recommendation {
+                date: Some(v.date),
+                updates: v.component_diff().unwrap_or_default(),
+                missing: Vec::new(),
+                command: v.recommended_command(),
+            },
+            None => Recommendation {
+                date: None,
+                updates: Vec::new(),
+                missing: newest
+                    .as_ref()
+                    .map(Rust::missing_components)
+                    .unwrap_or_default(),
+                command: None,
+            },
+        })
+    }
+
+    pub fn latest_complete_with_trace(
+        &self,
+        max_days: usize,
+    ) -> (Result<Option<Rust>, CheckError>, Vec<ProbeRecord>) {
+        let mut trace = Vec::new();
+        let rust = self.scan(max_days, &mut |r| trace.push(r.probe()));
+        (rust, trace)
+    }
+
+    pub fn print_info(&self) {
+        println!("{}", &self.toolchain.info());
+    }
+
+    // Every installed or required component against this position.
+    pub fn component_table(&self) -> Vec<ComponentRow> {
+        let manifest = match &self.manifest {
+            Some(manifest) => manifest,
+            None => return Vec::new(),
+        };
+        let missing = self.missing_components_detailed();
+        let mut names: Vec<&String> = self
+            .toolchain
+            .components
+            .iter()
+            .map(|c| &c.name)
+            .chain(self.required.iter())
+            .collect();
+        names.sort();
+        names.dedup();
+        names
+            .into_iter()
+            .map(|name| {
+                let installed = self.toolchain.components.iter().find(|c| &c.name == name);
+                let candidate = manifest.pkg_version(manifest.resolve_name(name));
+                let status = match (missing.iter().find(|m| &m.name == name), installed) {
+                    (Some(m), _) => ComponentStatus::Missing(m.reason),
+                    (None, _)
+                        if manifest
+                            .missing_reason(name, &self.toolchain.target)
+                            .is_some() =>
+                    {
+                        ComponentStatus::Ignored
+                    }
+                    (None, None) => ComponentStatus::Add,
+                    (None, Some(c)) if c.update_info(candidate.clone()).is_some() => {
+                        ComponentStatus::Update
+                    }
+                    (None, Some(_)) => ComponentStatus::UpToDate,
+                };
+                ComponentRow {
+                    name: name.clone(),
+                    installed: installed.and_then(|c| c.version.clone()),
+                    candidate,
+                    status,
+                }
+            })
+            .collect()
+    }
+
+    pub fn component_diff(&self) -> Option<Vec<ComponentDiff>> {
+        if self.missing_components().is_empty() {
+            let manifest = self.manifest.as_ref()?;
+            Some(
+                self.toolchain
+                    .components
+                    .iter()
+                    .filter(|c| !self.is_ignored(&c.name) || self.provides(&c.name))
+                    .filter_map(|c| c.update_info(manifest.pkg_version(std_target(&c.name, "").0)))
+                    .collect(),
+            )
+        } else {
+            None
+        }
+    }
+
+    // Commit-date aware: the same version built on a later date is newer.
+    // Components can be rebuilt without `rust` changing, so any newer
+    // installed component counts as an update too.
+    pub fn is_up_to_date(&self) -> bool {
+        let candidate = match &self.manifest {
+            Some(manifest) => manifest.rust_version_for_target(&self.toolchain.target),
+            None => None,
+        };
+        self.toolchain.pkg_version("rust") >= candidate
+            && self.component_diff().unwrap_or_default().is_empty()
+    }
+
+    // What to run to move to this position, `None` when it is already
+    // installed or can't be used.
+    pub fn recommended_command(&self) -> Option<String> {
+        self.check_result()?.command()
+    }
+
+    pub fn check_result(&self) -> Option<CheckResult> {
+        let updates = self.component_diff()?;
+        Some(match (self.offset, self.is_up_to_date()) {
+            (_, true) => CheckResult::UpToDate,
+            (0, false) => CheckResult::Update {
+                date: self.date_str(),
+                updates,
+            },
+            _ => CheckResult::Switch {
+                channel: self.toolchain.channel.clone(),
+                date: self.date_str(),
+                updates,
+                components: self.add_components(),
+            },
+        })
+    }
+}
+
+// Kept for compatibility: it never ends and a failed fetch looks the same as
+// an unpublished date. `Rust::scan_iter` and `Rust::outcomes` have neither
+// problem.
+impl Iterator for Rust {
+    type Item = Rust;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.offset += 1;
+        self.date = self.today.sub(Duration::days(self.offset));
+        self.manifest = self
+            .source
+            .manifest(&self.date_str(), &self.toolchain.channel)
+            .ok()
+            .flatten();
+        Some(self.clone())
+    }
+}
+
+pub fn current_channel_target(rustup_home: Option<&Path>) -> Result<(String, String), String> {
+    split_toolchain_name(&active_toolchain_name(rustup_home)?)
+}
+
+// Dated names like `nightly-2019-01-01-<target>` keep only the channel.
+fn split_toolchain_name(toolchain: &str) -> Result<(String, String), String> {
+    if is_custom_name(toolchain) {
+        return Err(custom_toolchain(toolchain));
+    }
+    let (channel, target) = match toolchain.splitn(2, '-').collect::<Vec<&str>>()[..] {
+        [channel, target] => (channel, target),
+        _ => return Err(format!("Toolchain {} has no target", toolchain)),
+    };
+    let target = match target.get(..11) {
+        Some(date)
+            if date.ends_with('-')
+                && NaiveDate::parse_from_str(&date[..10], "%Y-%m-%d").is_ok() =>
+        {
+            &target[11..]
+        }
+        _ => target,
+    };
+    validate_target(target)?;
+    Ok((channel.to_string(), target.to_string()))
+}
+
+fn custom_toolchain(name: &str) -> String {
+    format!(
+        "Update checking not supported for custom toolchain {}",
+        name
+    )
+}
+
+// Toolchain names that don't start with a channel, or have nothing after it.
+fn is_custom_name(name: &str) -> bool {
+    match name.split_once('-') {
+        Some((channel, _)) => !is_channel_name(channel),
+        None => !is_channel_name(name),
+    }
+}
+
+// A release channel, or a pinned release such as `1.70.0` or `1.70`.
+fn is_channel_name(name: &str) -> bool {
+    ["stable", "beta", "nightly"].contains(&name)
+        || (name.split('.').count() >= 2
+            && name
+                .split('.')
+                .all(|part| !part.is_empty() && part.bytes().all(|b| b.is_ascii_digit())))
+}
+
+fn installed_components(dir: &Path, target: &str) -> Result<Vec<String>, String> {
+    let mut file = File::open(dir.join(COMPONENTS_FILE)).map_err(|e| e.to_string())?;
+    let mut contents = String::new();
+    file.read_to_string(&mut contents)
+        .map_err(|e| e.to_string())?;
+    let components: Vec<String> = contents
+        .split('\n')
+        .filter(|s| !s.is_empty())
+        .map(|s| s.replace(&format!("-{}", target), ""))
+        .collect();
+    Ok(components)
+}
+
+// `rustup_home` defaults to `$RUSTUP_HOME`, then to rustup's own default
+// of `~/.rustup`.
+fn rustup_home_dir(rustup_home: Option<&Path>) -> Result<PathBuf, String> {
+    match (rustup_home, env::var_os("RUSTUP_HOME"), env::var_os("HOME")) {
+        (Some(home), _, _) => Ok(home.to_path_buf()),
+        (None, Some(home), _) => Ok(PathBuf::from(home)),
+        (None, None, Some(home)) => Ok(PathBuf::from(home).join(".rustup")),
+        (None, None, None) => Err("Neither RUSTUP_HOME nor HOME is set".to_string()),
+    }
+}
+
+fn toolchains_dir(rustup_home: Option<&Path>) -> Result<PathBuf, String> {
+    Ok(rustup_home_dir(rustup_home)?.join("toolchains"))
+}
+
+// `RUSTUP_TOOLCHAIN` under a rustup proxy; run directly, what rustup's
+// settings pick for the current directory.
+fn active_toolchain_name(rustup_home: Option<&Path>) -> Result<String, String> {
+    if let Ok(name) = env::var("RUSTUP_TOOLCHAIN") {
+        return Ok(name);
+    }
+    let settings = RustupSettings::read(&rustup_home_dir(rustup_home)?)?;
+    let dir = env::current_dir().map_err(|e| e.to_string())?;
+    settings.toolchain_for(&dir).ok_or_else(|| {
+        "RUSTUP_TOOLCHAIN is not set and rustup has no default toolchain".to_string()
+    })
+}
+
+fn toolchain_dir(toolchain: &str, rustup_home: Option<&Path>) -> Result<PathBuf, String> {
+    Ok(toolchains_dir(rustup_home)?.join(toolchain))
+}
+
+// The active toolchain compared with its own manifest, components file and
+// `rustc -vV`, without the network.
+pub fn compare_installed_manifest(rustup_home: Option<&Path>) -> Result<Vec<String>, String> {
+    let name = active_toolchain_name(rustup_home)?;
+    let (channel, target) = split_toolchain_name(&name)?;
+    let dir = toolchain_dir(&name, rustup_home)?;
+    let toolchain = Toolchain::from_dir(channel, target, &dir)?;
+    let rustc = rustc_version(&dir.join("bin").join("rustc"));
+    Ok(toolchain.inconsistencies(rustc.as_ref()))
+}
+
+// Cross-target std components, as listed in the components file, are
+// `rust-std` built for another triple; every installed one is checked.
+fn std_target<'a>(name: &'a str, target: &'a str) -> (&'a str, &'a str) {
+    match name.strip_prefix("rust-std-") {
+        Some(triple) => ("rust-std", triple),
+        None => (name, target),
+    }
+}
+
+// `None` when the toolchain isn't installed, or is too old or minimal to
+// have a channel manifest.
+pub fn toolchain_manifest(
+    toolchain: &str,
+    rustup_home: Option<&Path>,
+) -> Result<Option<Manifest>, String> {
+    installed_manifest(&toolchain_dir(toolchain, rustup_home)?.join(MANIFEST_FILE))
+}
+
+// Only a missing file means no manifest; one that can't be read or parsed is
+// an error.
+fn installed_manifest(path: &Path) -> Result<Option<Manifest>, String> {
+    match File::open(path) {
+        Ok(file) => Manifest::from_reader(file)
+            .map(Some)
+            .map_err(|e| format!("Can't read {}: {}", path.display(), e)),
+        Err(ref e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(format!("Can't open {}: {}", path.display(), e)),
+    }
+}
+
+fn rustc_version(rustc: &Path) -> Option<Version> {
+    Version::parse_rustc_verbose(&rustc_verbose(rustc).ok()?)
+}
+
+fn rustc_verbose(rustc: &Path) -> Result<String, String> {
+    let output = Command::new(rustc)
+        .arg("-vV")
+        .output()
+        .map_err(|e| format!("Can't run {} -vV: {}", rustc.display(), e))?;
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+pub fn print_vec(input: &[String], comma: &str) -> String {
+    input
+        .iter()
+        .enumerate()
+        .fold(String::new(), |mut acc, (i, s)| {
+            if i > 0 {
+                acc.push_str(comma);
+            }
+            acc.push_str(s);
+            acc
+        })
+}
+
+// Replaces the file's contents so that in watch mode it always holds the
+// latest report; stdout when no file was given.
+pub fn write_output(output: Option<&Path>, rendered: &str) -> Result<(), String> {
+    match output {
+        Some(path) => {
+            fs::write(path, rendered).map_err(|e| format!("Can't write {}: {}", path.display(), e))
+        }
+        None => {
+            print!("{}", rendered);
+            Ok(())
+        }
+    }
+}
@@ -1,328 +1,496 @@
-#[macro_use]
-extern crate serde_derive;
+use rustupscheck::{
+    all_channels,
+    args::{Args, Format},
+    cache::Cache,
+    compare_installed_manifest, current_channel_target, format, print_vec,
+    report::{render_all, Report},
+    rustup::{self, RustupRelease},
+    source::{Dist, ManifestDir, Source},
+    toolchain_manifest, write_output, Channel, CheckError, Manifest, Rust, Toolchain,
+    DOCS_COMPONENTS,
+};
+use std::{
+    env,
+    io::{self, IsTerminal},
+    path::Path,
+    process,
+    rc::Rc,
+    thread,
+};
 
-pub mod manifest;
-
-pub use crate::manifest::{Manifest, Version};
-use chrono::{naive::NaiveDate, Duration, Local};
-use std::{env, fs::File, io::Read, ops::Sub, path::PathBuf};
+fn print_probe(args: &Args, rust: &Rust) {
+    if args.format == Format::JsonLines {
+        println!("{}", format::json_line(&rust.probe()));
+    }
+    if args.show_trace() {
+        eprintln!("{}", format::explain(&rust.probe()));
+    } else if let Some(e) = rust.unusable() {
+        eprintln!("Warning: skipping {}: {}", rust.date_str(), e);
+    }
+}
 
-#[cfg(test)]
-mod tests;
+fn report(args: &Args, rust: &Rust) -> Result<Report, CheckError> {
+    let mut report = rust.report_with(args.max_days, &mut |r| print_probe(args, r))?;
+    if !args.show_banner() {
+        report.banner.clear();
+    } else if args.verbose() {
+        report.banner = rust.toolchain().info_verbose();
+    }
+    Ok(report)
+}
 
-#[derive(Debug, Clone)]
-struct Component {
-    name: String,
-    required: bool,
-    version: Option<Version>,
+// `COLUMNS` when the shell exports it, a common width otherwise.
+fn terminal_width() -> usize {
+    env::var("COLUMNS")
+        .ok()
+        .and_then(|c| c.parse().ok())
+        .unwrap_or(100)
 }
 
-impl Component {
-    fn from(manifest: &Manifest, name: &str) -> Self {
-        let required = match name {
-            "rustc" | "cargo" => true,
-            _ => false,
-        };
-        Component {
-            name: name.to_string(),
-            required,
-            version: manifest.pkg_version(name),
+fn print_report(args: &Args, report: &Result<Report, CheckError>) {
+    match report {
+        Ok(report) => {
+            let mut output = report.render(args.format);
+            if args.verbose() && args.format == Format::Text && !report.components.is_empty() {
+                output.push('\n');
+                output.push_str(&format::component_table(
+                    &report.components,
+                    terminal_width(),
+                ));
+                let gap = report
+                    .components
+                    .iter()
+                    .find(|row| row.name == "rustc")
+                    .and_then(|row| {
+                        format::release_gap(row.installed.as_ref()?, row.candidate.as_ref()?)
+                    });
+                if let Some(gap) = gap {
+                    output.push_str(&gap);
+                }
+                output.push_str(&format!("Why: {}\n", report.rationale));
+            }
+            if let Err(e) = write_output(args.output.as_deref(), &output) {
+                eprintln!("{}", e);
+                process::exit(1);
+            }
         }
+        Err(e) => eprintln!("{}", e),
     }
+}
 
-    pub fn update_info(&self, other: Option<Version>) -> Option<String> {
-        match (&self.version, &other) {
-            (Some(version), Some(other)) => {
-                if version < other {
-                    Some(format!(
-                        "{} - from {} to {}",
-                        self.name,
-                        version.to_string(),
-                        other.to_string()
-                    ))
-                } else {
-                    None
-                }
-            }
-            _ => None,
+fn pin(args: &Args, report: &Report) {
+    let pin = match &report.pin {
+        Some(pin) => pin,
+        None => {
+            eprintln!("Nothing to pin");
+            return;
+        }
+    };
+    match pin.write(Path::new("."), args.force) {
+        Ok(path) => eprintln!("Pinned {} in {}", pin.channel, path.display()),
+        Err(e) => {
+            eprintln!("{}", e);
+            process::exit(1);
         }
     }
 }
 
-#[derive(Debug, Clone)]
-struct Toolchain {
-    channel: String,
-    target: String,
-    components: Vec<Component>,
-    manifest: Manifest,
+fn diff_dates(
+    source: &dyn Source,
+    rustup_home: Option<&Path>,
+    from: &str,
+    to: &str,
+) -> Result<String, String> {
+    let (_, target) = current_channel_target(rustup_home)?;
+    let fetch = |date: &str| {
+        source
+            .manifest(date, "nightly")
+            .map_err(|e| e.to_string())?
+            .ok_or_else(|| format!("No nightly published on {}", date))
+    };
+    let changes = fetch(from)?.diff(&fetch(to)?, &target);
+    Ok(format::manifest_diff(from, to, &changes))
 }
 
-impl Toolchain {
-    fn new() -> Result<Toolchain, String> {
-        let (channel, target) = current_channel_target()?;
-        let manifest = local_manifest()?;
-        let components = installed_components(&target)?
-            .iter()
-            .map(|s| Component::from(&manifest, s))
-            .collect();
-        Ok(Toolchain {
-            channel,
-            target,
-            components,
-            manifest,
-        })
+// On stderr, so machine-readable output stays intact.
+fn check_rustup() {
+    let status = rustup::installed_version().and_then(|installed| {
+        RustupRelease::latest().map(|latest| rustup::status(&installed, &latest))
+    });
+    match status {
+        Ok(status) => eprintln!("{}", status),
+        Err(e) => eprintln!("Can't check rustup: {}", e),
     }
+}
 
-    fn component_list(&self) -> Vec<String> {
-        self.components
-            .iter()
-            .filter(|c| !c.required)
-            .map(|c| c.name.to_string())
-            .collect()
+fn detect_toolchain(args: &Args) -> Result<Toolchain, String> {
+    let rustup_home = args.rustup_home.as_deref();
+    let toolchain = if args.target_from_rustc {
+        Toolchain::from_rustc_in(rustup_home)
+    } else {
+        Toolchain::new_in(rustup_home)
+    }?;
+    Ok(prepare_toolchain(args, toolchain))
+}
+
+fn prepare_toolchain(args: &Args, toolchain: Toolchain) -> Toolchain {
+    if args.no_required_distinction {
+        toolchain.without_required()
+    } else {
+        toolchain
     }
+}
 
-    fn info(&self) -> String {
-        match self.manifest.pkg_version("rustc") {
-            Some(version) => format!(
-                "Installed: {}-{} {} ({} {})\n{}",
-                self.channel,
-                self.target,
-                version.version,
-                version.commit.hash,
-                version.commit.date,
-                match self.component_list().len() {
-                    0 => "With no components".to_string(),
-                    1 => format!("With component: {}", self.component_list()[0]),
-                    _ => format!(
-                        "With components: {}",
-                        print_vec(&self.component_list(), ", ")
-                    ),
-                }
-            ),
-            None => String::from("Not found installed rustc"),
+// Ignored and required components, the same for every checked toolchain.
+fn configure(args: &Args, rust: &mut Rust) {
+    let mut ignored = args.ignore.clone();
+    if args.skip_docs {
+        let docs: Vec<String> = rust
+            .toolchain()
+            .components
+            .iter()
+            .map(|c| c.name.clone())
+            .filter(|c| DOCS_COMPONENTS.contains(&c.as_str()))
+            .collect();
+        if !docs.is_empty() {
+            eprintln!("Skipping docs: {}", print_vec(&docs, ", "));
         }
+        ignored.extend(DOCS_COMPONENTS.iter().map(|c| c.to_string()));
+    }
+    rust.set_ignored(ignored);
+    if args.target_default_only {
+        let targets = rust.ignore_extra_targets();
+        if !targets.is_empty() {
+            eprintln!(
+                "Ignoring std for extra targets: {}",
+                print_vec(&targets, ", ")
+            );
+        }
+    }
+    rust.set_required(args.require.clone());
+    rust.set_fail_on_missing(args.fail_on_missing);
+    rust.set_compression(args.compression);
+    if args.since_installed {
+        let floor = rust.toolchain().installed_date();
+        rust.set_floor(floor);
     }
 }
 
-#[derive(Debug, Clone)]
-pub struct Rust {
-    offset: i64,
-    date: NaiveDate,
-    toolchain: Toolchain,
-    manifest: Option<Manifest>,
+// One report per installed toolchain; the exit code is the worst of them.
+fn check_all_toolchains(args: &Args, source: Rc<dyn Source>) -> i32 {
+    let toolchains = match Toolchain::installed_in(args.rustup_home.as_deref()) {
+        Ok(toolchains) => toolchains,
+        Err(e) => {
+            eprintln!("{}", e);
+            return 1;
+        }
+    };
+    let mut reports = vec![];
+    let mut code = 0;
+    for (name, toolchain) in toolchains {
+        let toolchain = match toolchain {
+            Ok(toolchain) if toolchain.is_custom() => {
+                eprintln!("Note: skipping {}: custom toolchain", name);
+                continue;
+            }
+            Ok(toolchain) => prepare_toolchain(args, toolchain),
+            Err(e) => {
+                eprintln!("Note: skipping {}: {}", name, e);
+                continue;
+            }
+        };
+        let mut rust = match Rust::for_toolchain(toolchain, source.clone(), args.anchor) {
+            Ok(rust) => rust,
+            Err(e) => {
+                eprintln!("{}: {}", name, e);
+                code = code.max(1);
+                continue;
+            }
+        };
+        configure(args, &mut rust);
+        match report(args, &rust) {
+            Ok(report) => {
+                code = code.max(report.exit_code());
+                reports.push(report);
+            }
+            Err(e) => {
+                eprintln!("{}: {}", name, e);
+                code = code.max(1);
+            }
+        }
+    }
+    if let Err(e) = write_output(args.output.as_deref(), &render_all(&reports, args.format)) {
+        eprintln!("{}", e);
+        return 1;
+    }
+    code
 }
 
-impl Rust {
-    pub fn new() -> Option<Rust> {
-        match Toolchain::new() {
-            Ok(toolchain) => {
-                let date = Local::today().naive_local();
-                let manifest =
-                    Manifest::from_date(&date.format("%Y-%m-%d").to_string(), &toolchain.channel)
-                        .ok();
-                Some(Rust {
-                    offset: -1,
-                    date,
-                    toolchain,
-                    manifest,
-                })
+fn main() {
+    let args = match Args::parse(env::args().skip(1)) {
+        Ok(args) => args,
+        Err(e) => {
+            eprintln!("{}", e);
+            process::exit(2);
+        }
+    };
+
+    if args.compare_installed_manifest {
+        match compare_installed_manifest(args.rustup_home.as_deref()) {
+            Ok(problems) if problems.is_empty() => println!("Installed toolchain is consistent"),
+            Ok(problems) => {
+                for problem in problems {
+                    println!("{}", problem);
+                }
+                process::exit(1);
+            }
+            Err(e) => {
+                eprintln!("{}", e);
+                process::exit(1);
             }
-            Err(_) => None,
         }
+        return;
     }
 
-    pub fn from_date(date_str: &str) -> Option<Rust> {
-        match Toolchain::new() {
-            Ok(toolchain) => {
-                let date = NaiveDate::parse_from_str(date_str, "%Y-%m-%d").ok()?;
-                let offset = (Local::today().naive_local() - date).num_days() - 1;
-                let manifest = Manifest::from_date(date_str, &toolchain.channel).ok();
-                Some(Rust {
-                    offset,
-                    date,
-                    toolchain,
-                    manifest,
-                })
+    let cache = Cache::default_dir().map(Cache::new);
+    if args.clear_cache {
+        match cache.map(|cache| cache.clear()) {
+            Some(Ok(removed)) => println!("Removed {} cache entries", removed),
+            Some(Err(e)) => {
+                eprintln!("{}", e);
+                process::exit(1);
             }
-            Err(_) => None,
+            None => println!("No cache directory"),
         }
+        return;
     }
+    let progress = args.show_progress(io::stderr().is_terminal());
+    let source: Rc<dyn Source> = match (&args.manifest_dir, cache) {
+        (Some(dir), _) => Rc::new(ManifestDir::new(dir)),
+        (None, Some(cache)) => {
+            let cache = if args.no_cache {
+                cache.without_read()
+            } else {
+                cache
+            };
+            let cache = if args.cache_read_only {
+                cache.without_write()
+            } else {
+                cache
+            };
+            Rc::new(Dist::with_cache(cache).with_progress(progress))
+        }
+        (None, None) => Rc::new(Dist::new().with_progress(progress)),
+    };
 
-    pub fn missing_components(&self) -> Vec<String> {
-        match &self.manifest {
-            Some(manifest) => self
-                .toolchain
-                .components
-                .iter()
-                .map(|c| &c.name)
-                .filter(|&c| {
-                    let component = match manifest.renames.get(c) {
-                        Some(rename) => rename.to.clone(),
-                        None => c.to_string(),
-                    };
-                    match manifest.pkg_for_target(&component, &self.toolchain.target) {
-                        Some(package_info) => !package_info.available,
-                        None => true,
-                    }
-                })
-                .cloned()
-                .collect(),
-            None => Vec::new(),
+    if args.all_channels {
+        let rustup_home = args.rustup_home.as_deref();
+        let target = match current_channel_target(rustup_home) {
+            Ok((_, target)) => target,
+            Err(e) => {
+                eprintln!("{}", e);
+                process::exit(1);
+            }
+        };
+        let installed = |channel: &Channel| -> Result<Option<Manifest>, String> {
+            toolchain_manifest(&format!("{}-{}", channel, target), rustup_home)
+        };
+        for status in all_channels(source.as_ref(), &installed) {
+            println!("{}", format::channel_status(&status));
         }
+        return;
     }
 
-    pub fn manifest_pkg_version(&self, name: &str) -> Option<Version> {
-        match &self.manifest {
-            Some(manifest) => manifest.pkg_version(name),
-            None => None,
+    if let Some((from, to)) = &args.diff_dates {
+        match diff_dates(source.as_ref(), args.rustup_home.as_deref(), from, to) {
+            Ok(diff) => print!("{}", diff),
+            Err(e) => {
+                eprintln!("{}", e);
+                process::exit(1);
+            }
         }
+        return;
     }
 
-    pub fn date_str(&self) -> String {
-        self.date.format("%Y-%m-%d").to_string()
+    if args.all_toolchains {
+        let code = check_all_toolchains(&args, source);
+        if args.exit_code || code == 1 || (args.fail_on_missing && code == 20) {
+            process::exit(code);
+        }
+        return;
     }
 
-    pub fn print_info(&self) {
-        println!("{}", &self.toolchain.info());
+    let toolchain = match detect_toolchain(&args) {
+        Ok(toolchain) => toolchain,
+        Err(e) => {
+            eprintln!("{}", e);
+            process::exit(1);
+        }
+    };
+    if let Some(note) = toolchain.custom_note() {
+        eprintln!("{}", note);
+        process::exit(1);
     }
-
-    fn update_info(&self) -> Option<Vec<String>> {
-        if self.missing_components().is_empty() {
-            let manifest = self.manifest.clone()?;
-            Some(
-                self.toolchain
-                    .components
-                    .iter()
-                    .filter_map(|c| c.update_info(manifest.pkg_version(&c.name)))
-                    .collect(),
-            )
-        } else {
-            None
+    if let Some(min) = args.min_components {
+        if let Err(e) = toolchain.check_min_components(min) {
+            eprintln!("{}", e);
+            process::exit(1);
         }
     }
-}
-
-impl Iterator for Rust {
-    type Item = Rust;
-
-    fn next(&mut self) -> Option<Self::Item> {
-        self.offset += 1;
-        self.date = Local::today()
-            .naive_local()
-            .sub(Duration::days(self.offset));
-        self.manifest = Manifest::from_date(
-            &self.date.format("%Y-%m-%d").to_string(),
-            &self.toolchain.channel,
-        )
-        .ok();
-        Some(self.clone())
+    let mut rust = match Rust::for_toolchain(toolchain, source, args.anchor) {
+        Ok(rust) => rust,
+        Err(e) => {
+            eprintln!("{}", e);
+            process::exit(1);
+        }
+    };
+    if let Some(warning) = rust.toolchain().date_mismatch() {
+        eprintln!("Warning: {}", warning);
     }
-}
-
-fn current_channel_target() -> Result<(String, String), String> {
-    let toolchain = env::var("RUSTUP_TOOLCHAIN").map_err(|e| e.to_string())?;
-    let split: Vec<&str> = toolchain.splitn(2, '-').collect();
-    let channel = split[0].to_string();
-    let target = split[1].to_string();
-    Ok((channel, target))
-}
-
-fn installed_components(target: &str) -> Result<Vec<String>, String> {
-    let rustup_home = env::var("RUSTUP_HOME").map_err(|e| e.to_string())?;
-    let toolchain = env::var("RUSTUP_TOOLCHAIN").map_err(|e| e.to_string())?;
-    let mut path = PathBuf::from(rustup_home);
-    path.push("toolchains");
-    path.push(toolchain);
-    path.push("lib");
-    path.push("rustlib");
-    path.push("components");
-    let mut file = File::open(path).map_err(|e| e.to_string())?;
-    let mut contents = String::new();
-    file.read_to_string(&mut contents)
-        .map_err(|e| e.to_string())?;
-    let components: Vec<String> = contents
-        .split('\n')
-        .filter(|s| !s.is_empty())
-        .map(|s| s.replace(&format!("-{}", target), ""))
-        .collect();
-    Ok(components)
-}
-
-fn local_manifest() -> Result<Manifest, String> {
-    let rustup_home = env::var("RUSTUP_HOME").map_err(|e| e.to_string())?;
-    let toolchain = env::var("RUSTUP_TOOLCHAIN").map_err(|e| e.to_string())?;
-    let mut path = PathBuf::from(rustup_home);
-    path.push("toolchains");
-    path.push(toolchain);
-    path.push("lib");
-    path.push("rustlib");
-    path.push("multirust-channel-manifest");
-    path.set_extension("toml");
-    let mut file = File::open(path).map_err(|e| e.to_string())?;
-    let mut contents = String::new();
-    file.read_to_string(&mut contents)
-        .map_err(|e| e.to_string())?;
-    toml::from_str(&contents).map_err(|e| e.to_string())
-}
+    if let Some(warning) = rust.clock_skew() {
+        eprintln!("Warning: {}", warning);
+    }
+    if let Some(note) = rust.toolchain().no_components() {
+        eprintln!("Note: {}", note);
+    }
+    configure(&args, &mut rust);
 
-fn print_vec(input: &[String], comma: &str) -> String {
-    input
-        .iter()
-        .enumerate()
-        .fold(String::new(), |mut acc, (i, s)| {
-            if i > 0 {
-                acc.push_str(comma);
+    if let Some(prefix) = &args.target_family {
+        match rust.target_family(prefix) {
+            Ok((date, family)) => {
+                print!(
+                    "{}",
+                    format::target_family(prefix, &date.to_string(), &family)
+                );
+                if family.iter().any(|(_, available)| !available) && args.exit_code {
+                    process::exit(20);
+                }
             }
-            acc.push_str(&s);
-            acc
-        })
-}
+            Err(e) => {
+                eprintln!("{}", e);
+                process::exit(1);
+            }
+        }
+        return;
+    }
 
-fn main() {
-    let rust = Rust::new().unwrap();
-    rust.print_info();
+    if let Some(date) = args.component_versions {
+        let position = match date {
+            Some(date) => rust.at_date(date).map(Some),
+            None => rust.latest_published(args.max_days),
+        };
+        match position {
+            Ok(Some(position)) => match position.component_versions() {
+                Some(versions) => print!(
+                    "{}",
+                    format::component_versions(&position.date_str(), &versions)
+                ),
+                None => {
+                    eprintln!("No nightly published on {}", position.date_str());
+                    process::exit(1);
+                }
+            },
+            Ok(None) => {
+                eprintln!("No nightly published in the last {} days", args.max_days);
+                process::exit(1);
+            }
+            Err(e) => {
+                eprintln!("{}", e);
+                process::exit(1);
+            }
+        }
+        return;
+    }
 
-    let v = rust
-        .filter(|r| r.manifest.is_some() && r.missing_components().is_empty())
-        .nth(0)
-        .unwrap();
+    if let Some(component) = &args.explain_missing {
+        match rust.availability(component, args.max_days) {
+            Ok(history) => print!("{}", format::availability(component, &history)),
+            Err(e) => {
+                eprintln!("{}", e);
+                process::exit(1);
+            }
+        }
+        return;
+    }
 
-    match (
-        v.offset,
-        v.toolchain.manifest.pkg_version("rust") < v.manifest_pkg_version("rust"),
-    ) {
-        (0, true) => println!(
-            "{}\nUse: \"rustup update\" (new version from {})",
-            v.update_info().unwrap().iter().fold(
-                String::from("Update components:\n"),
-                |mut acc, c| {
-                    acc.push_str(c);
-                    acc.push('\n');
-                    acc
+    match args.watch {
+        None => {
+            let report = report(&args, &rust);
+            // Cron only mails output, so up to date has to stay silent.
+            if let (true, Ok(report)) = (args.only_if_actionable, &report) {
+                if !report.is_actionable() {
+                    return;
                 }
-            ),
-            v.date_str()
-        ),
-        (0, false) => println!("Current version is up to date"),
-        _ => println!(
-            "{}\nUse: \"rustup default {}-{}\"{}",
-            v.update_info().unwrap().iter().fold(
-                String::from("Update components:\n"),
-                |mut acc, c| {
-                    acc.push_str(c);
-                    acc.push('\n');
-                    acc
+            }
+            print_report(&args, &report);
+            if let (true, Ok(report)) = (args.recommend_stable, &report) {
+                if report.exit_code() == 20 && rust.toolchain().channel != "stable" {
+                    match rust.stable_alternative() {
+                        Ok(Some(stable)) => print!(
+                            "{}",
+                            format::stable_alternative(
+                                &rust.toolchain().channel,
+                                stable.manifest_pkg_version("rust").as_ref(),
+                                &stable.add_components(),
+                            )
+                        ),
+                        Ok(None) => eprintln!("Note: stable lacks some components too"),
+                        Err(e) => eprintln!("Can't check stable: {}", e),
+                    }
+                }
+            }
+            if let (true, Ok(report)) = (args.pin, &report) {
+                pin(&args, report);
+            }
+            if args.verbose() && args.show_banner() {
+                if let Ok(Some(train)) = rust.release_train() {
+                    eprintln!("Note: {}", train);
+                }
+            }
+            if let (true, Ok(report)) = (args.show_banner(), &report) {
+                if let Some(note) = &report.date_note {
+                    eprintln!("Note: {}", note);
+                }
+                if let Some(removed) = format::removed_upstream(&report.removed_upstream) {
+                    eprint!("{}", removed);
+                }
+            }
+            if let (true, Ok(report)) = (args.since_installed, &report) {
+                if report.candidate.is_none() && report.exit_code() == 0 {
+                    eprintln!("Note: you're on the newest complete nightly");
                 }
-            ),
-            v.toolchain.channel,
-            v.date_str(),
-            match v.toolchain.components.len() {
-                0 => String::new(),
-                _ => format!(
-                    "\n     \"rustup component add {}\"",
-                    print_vec(&v.toolchain.component_list(), " ")
-                ),
             }
-        ),
+            if args.check_rustup {
+                check_rustup();
+            }
+            match report {
+                Ok(report) if args.exit_code || args.only_if_actionable => {
+                    process::exit(report.exit_code())
+                }
+                Ok(report) if args.fail_on_missing && report.exit_code() == 20 => {
+                    process::exit(report.exit_code())
+                }
+                Ok(_) => (),
+                Err(_) => process::exit(1),
+            }
+        }
+        // Runs until interrupted; the default Ctrl-C handling is enough since
+        // cache writes are atomic and nothing else needs flushing.
+        Some(interval) => {
+            let mut last = None;
+            loop {
+                let report = report(&args, &rust);
+                if last.as_ref() != Some(&report) {
+                    print_report(&args, &report);
+                    last = Some(report);
+                }
+                thread::sleep(interval);
+                let refreshed = detect_toolchain(&args)
+                    .and_then(|toolchain| rust.refresh_with(toolchain).map_err(|e| e.to_string()));
+                if let Err(e) = refreshed {
+                    eprintln!("{}", e);
+                }
+            }
+        }
     }
 }
@@ -1,16 +1,25 @@
-use chrono::naive::NaiveDate;
-use native_tls::TlsConnector;
-use serde::{de::Error, Deserialize, Deserializer};
+use crate::{cache::Cache, http, progress::Spinner};
+use chrono::{naive::NaiveDate, Utc};
+use serde::{de::Error, Deserialize, Deserializer, Serialize, Serializer};
 use std::{
     cmp::Ordering,
     collections::HashMap,
-    fmt,
-    io::{Read, Write},
-    net::TcpStream,
+    env, fmt,
+    fs::File,
+    hash::{Hash, Hasher},
+    io::Read,
+    path::Path,
+    rc::Rc,
     str::FromStr,
+    thread,
+    time::Duration,
 };
 use toml;
 
+pub const MANIFEST_VERSION: u8 = 2;
+pub const PATH_TEMPLATE: &str = "/dist/{date}/channel-rust-{channel}.toml";
+pub const DIST_SERVER: &str = "https://static.rust-lang.org";
+
 #[derive(Debug, Clone, Deserialize, Eq)]
 #[serde(rename_all = "kebab-case")]
 pub struct Manifest {
@@ -21,50 +30,393 @@ pub struct Manifest {
     pub renames: HashMap<String, Rename>,
 }
 
+// Why a dated manifest couldn't be had. A malformed one is worth skipping,
+// while a fetch failure leaves the answer unknown.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FetchError {
+    Fetch(String),
+    Malformed(String),
+}
+
+impl fmt::Display for FetchError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FetchError::Fetch(e) => write!(f, "{}", e),
+            FetchError::Malformed(e) => write!(f, "malformed manifest: {}", e),
+        }
+    }
+}
+
+impl From<String> for FetchError {
+    fn from(e: String) -> Self {
+        FetchError::Fetch(e)
+    }
+}
+
+// A download function: the body, or `None` for a 404.
+pub type Transport = Rc<dyn Fn(&str) -> Result<Option<String>, String>>;
+
+// How `Manifest::from_date` obtains a dated manifest.
+#[derive(Clone)]
+pub struct FetchConfig {
+    pub cache: Option<Cache>,
+    // Further attempts after a failed download; a 404 is an answer, not a
+    // failure.
+    pub retries: usize,
+    // Multiplied by the attempt number before each retry.
+    pub retry_delay: Duration,
+    // Shows a spinner on stderr while a download is in flight.
+    pub progress: bool,
+    pub transport: Transport,
+}
+
+impl Default for FetchConfig {
+    fn default() -> Self {
+        FetchConfig {
+            cache: None,
+            retries: 2,
+            retry_delay: Duration::from_secs(1),
+            progress: false,
+            transport: Rc::new(http::fetch),
+        }
+    }
+}
+
+impl fmt::Debug for FetchConfig {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("FetchConfig")
+            .field("cache", &self.cache)
+            .field("retries", &self.retries)
+            .field("retry_delay", &self.retry_delay)
+            .field("progress", &self.progress)
+            .finish()
+    }
+}
+
+impl FetchConfig {
+    fn download(&self, path: &str, message: String) -> Result<Option<String>, String> {
+        let _spinner = if self.progress {
+            Some(Spinner::start(message))
+        } else {
+            None
+        };
+        let mut tries = 0;
+        loop {
+            match (self.transport)(path) {
+                Err(_) if tries < self.retries => {
+                    tries += 1;
+                    thread::sleep(self.retry_delay * tries as u32);
+                }
+                result => return result,
+            }
+        }
+    }
+}
+
+// Tonight's nightly may not be out yet and yesterday's may still be
+// running late, so a 404 for either is never remembered; only older gaps
+// are. Measured in UTC, the schedule nightlies are published on, one day
+// further back to cover local dates behind UTC.
+fn may_still_publish(date: &str) -> bool {
+    NaiveDate::parse_from_str(date, "%Y-%m-%d").map_or(true, |date| {
+        date >= Utc::now().naive_utc().date() - chrono::Duration::days(2)
+    })
+}
+
 impl Manifest {
-    pub fn from_date(date: &str, channel: &str) -> Result<Self, String> {
-        let path = format!("/dist/{}/channel-rust-{}.toml", date, channel);
-        Manifest::from_url(&path)
+    // The one well-behaved way to get a dated manifest: a cached copy or a
+    // remembered 404 answers first, then the network with retries, whose
+    // answer is cached for next time. `Ok(None)` when nothing was published.
+    pub fn from_date(
+        date: &str,
+        channel: &str,
+        config: &FetchConfig,
+    ) -> Result<Option<Manifest>, FetchError> {
+        let cache = config.cache.as_ref().map(|cache| match Manifest::source() {
+            Some(source) => cache.clone().for_source(&source),
+            None => cache.clone(),
+        });
+        if let Some(cache) = &cache {
+            if let Some(manifest) = cache.get(date, channel).and_then(|s| s.parse().ok()) {
+                return Ok(Some(manifest));
+            }
+            if cache.is_missing(date, channel) && !may_still_publish(date) {
+                return Ok(None);
+            }
+        }
+        let path = Manifest::path(date, channel)?;
+        let body = match config.download(&path, format!("Fetching {} {}", channel, date))? {
+            Some(body) => body,
+            None => {
+                match &cache {
+                    Some(cache) if !may_still_publish(date) => {
+                        let _ = cache.put_missing(date, channel);
+                    }
+                    _ => (),
+                }
+                return Ok(None);
+            }
+        };
+        let manifest = body.parse().map_err(FetchError::Malformed)?;
+        if let Some(cache) = &cache {
+            // A failed cache write only costs a refetch next time.
+            let _ = cache.put(date, channel, &body);
+        }
+        Ok(Some(manifest))
     }
 
     pub fn from_url(path: &str) -> Result<Manifest, String> {
-        let connector = TlsConnector::new().map_err(|e| e.to_string())?;
-        let stream = TcpStream::connect("static.rust-lang.org:443").map_err(|e| e.to_string())?;
-        let mut stream = connector
-            .connect("static.rust-lang.org", stream)
-            .map_err(|e| e.to_string())?;
-        let request = format!(
-            "GET {} HTTP/1.0\r\nHost: static.rust-lang.org\r\n\r\n",
-            path
-        )
-        .into_bytes();
-        stream.write_all(&request).map_err(|e| e.to_string())?;
-        let mut response = vec![];
-        stream
-            .read_to_end(&mut response)
+        Manifest::from_reader(Manifest::download(path)?.as_bytes())
+    }
+
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Manifest, String> {
+        Manifest::from_reader(File::open(path).map_err(|e| e.to_string())?)
+    }
+
+    // Every way of obtaining a manifest ends up here.
+    pub fn from_reader<R: Read>(mut reader: R) -> Result<Manifest, String> {
+        let mut contents = String::new();
+        reader
+            .read_to_string(&mut contents)
             .map_err(|e| e.to_string())?;
-        let body = body(&response)?;
-        let manifest = toml::from_str(&body).map_err(|e| e.to_string())?;
+        // Transforming proxies sometimes prepend a UTF-8 BOM or blank lines.
+        let contents = contents.trim_start_matches('\u{feff}').trim_start();
+        let manifest: Manifest = toml::from_str(contents).map_err(|e| e.to_string())?;
+        if manifest.manifest_version != MANIFEST_VERSION {
+            return Err(format!(
+                "unsupported manifest version {}",
+                manifest.manifest_version
+            ));
+        }
         Ok(manifest)
     }
 
+    pub fn latest(channel: &str) -> Result<Self, String> {
+        Manifest::from_url(&Manifest::latest_path(channel))
+    }
+
+    // The channel's newest manifest through `config`; never cached, since
+    // it changes over time.
+    pub fn latest_with(channel: &str, config: &FetchConfig) -> Result<Self, FetchError> {
+        let path = Manifest::latest_path(channel);
+        config
+            .download(&path, format!("Fetching latest {}", channel))?
+            .ok_or_else(|| format!("HTTP status 404 for {}", path))?
+            .parse()
+            .map_err(FetchError::Malformed)
+    }
+
+    pub fn latest_path(channel: &str) -> String {
+        format!("/dist/channel-rust-{}.toml", channel)
+    }
+
+    // Relative to `RUSTUP_DIST_SERVER`. Mirrors with a different layout can
+    // override it with `RUSTUPSCHECK_PATH_TEMPLATE`, e.g.
+    // `/rust/{channel}/{date}.toml`.
+    pub fn path(date: &str, channel: &str) -> Result<String, String> {
+        expand_path_template(&path_template(), date, channel)
+    }
+
+    // The server and layout dated manifests come from, or `None` for the
+    // official ones; what the cache keeps copies apart by.
+    pub fn source() -> Option<String> {
+        let (server, template) = (dist_server(), path_template());
+        if server == DIST_SERVER && template == PATH_TEMPLATE {
+            None
+        } else {
+            Some(format!("{}{}", server, template))
+        }
+    }
+
+    pub fn download(path: &str) -> Result<String, String> {
+        http::get(path)
+    }
+
+    // A concrete target entry is authoritative: when it says unavailable,
+    // a `*` entry of the same package is not consulted.
     pub fn pkg_for_target(&self, pkg: &str, target: &str) -> Option<PackageInfo> {
         match self.pkg.get(pkg) {
             Some(package_target) => match package_target.target.get(target) {
                 Some(package_info) => Some(package_info.clone()),
-                None => match package_target.target.get("*") {
-                    Some(package_info) => Some(package_info.clone()),
-                    None => None,
-                },
+                None => package_target.target.get("*").cloned(),
             },
             None => None,
         }
     }
 
+    // The name a component is published under in this manifest.
+    pub fn resolve_name<'a>(&'a self, name: &'a str) -> &'a str {
+        follow_renames(&self.renames, name)
+    }
+
+    // Why `name` can't be installed on `target`, or `None` when it can.
+    pub fn missing_reason(&self, name: &str, target: &str) -> Option<MissingReason> {
+        let pkg = match self.pkg.get(self.resolve_name(name)) {
+            Some(pkg) => pkg,
+            None => return Some(MissingReason::Absent),
+        };
+        match pkg.target.get(target).or_else(|| pkg.target.get("*")) {
+            None => Some(MissingReason::TargetNotPublished),
+            Some(info) if !info.available => Some(MissingReason::Unavailable),
+            Some(_) => None,
+        }
+    }
+
+    pub fn contains_component(&self, name: &str, target: &str) -> bool {
+        match self.pkg_for_target(self.resolve_name(name), target) {
+            Some(package_info) => package_info.available,
+            None => false,
+        }
+    }
+
+    // Manifests are deliberately not `Ord`: publication date and rust version
+    // can disagree, so callers pick the comparison they mean.
+    pub fn newer_than(&self, other: &Manifest) -> bool {
+        self.date > other.date
+    }
+
+    pub fn rust_version_newer_than(&self, other: &Manifest) -> bool {
+        self.pkg_version("rust") > other.pkg_version("rust")
+    }
+
+    // Whether any package is built for `target` specifically; packages only
+    // published under "*" say nothing about which targets still exist.
+    pub fn has_target(&self, target: &str) -> bool {
+        self.pkg.values().any(|p| p.target.contains_key(target))
+    }
+
+    // Sorted names of the packages installable on `target`, including those
+    // only published under `*`, like `rust-src`.
+    pub fn available_components(&self, target: &str) -> Vec<String> {
+        let mut components: Vec<String> = self
+            .pkg
+            .keys()
+            .filter(|name| {
+                self.pkg_for_target(name, target)
+                    .is_some_and(|info| info.available)
+            })
+            .cloned()
+            .collect();
+        components.sort();
+        components
+    }
+
+    // Every concrete triple `rust-std` is listed for, available or not.
+    pub fn target_list(&self) -> Vec<String> {
+        let mut targets: Vec<String> = match self.pkg.get("rust-std") {
+            Some(std) => std.target.keys().filter(|t| *t != "*").cloned().collect(),
+            None => Vec::new(),
+        };
+        targets.sort();
+        targets
+    }
+
+    // The targets starting with `prefix` (a trailing `*` is allowed), with
+    // whether `rust-std` is available for each.
+    pub fn target_family(&self, prefix: &str) -> Vec<(String, bool)> {
+        let prefix = prefix.trim_end_matches('*');
+        self.target_list()
+            .into_iter()
+            .filter(|target| target.starts_with(prefix))
+            .map(|target| {
+                let available = self.contains_component("rust-std", &target);
+                (target, available)
+            })
+            .collect()
+    }
+
     pub fn pkg_version(&self, name: &str) -> Option<Version> {
         let pkg = self.pkg.get(name)?;
         pkg.version.clone()
     }
+
+    // The `rust` version, but only when it can be installed on `target`; a
+    // broken nightly may list a version it doesn't ship everywhere.
+    pub fn rust_version_for_target(&self, target: &str) -> Option<Version> {
+        if self.contains_component("rust", target) {
+            self.pkg_version("rust")
+        } else {
+            None
+        }
+    }
+
+    // Manifests don't name their channel; the `rust` version does, e.g.
+    // `1.72.0-beta.8`.
+    pub fn channel(&self) -> Option<Channel> {
+        self.pkg_version("rust").map(|version| version.channel)
+    }
+
+    // Unlike the abbreviated hash in the version string, enough to check out
+    // the exact source when bisecting.
+    pub fn pkg_commit(&self, name: &str) -> Option<&str> {
+        self.pkg
+            .get(self.resolve_name(name))?
+            .git_commit_hash
+            .as_deref()
+    }
+
+    // Packages whose version or availability for `target` differs between
+    // the two manifests, sorted by name.
+    pub fn diff(&self, other: &Manifest, target: &str) -> Vec<PackageChange> {
+        let mut names: Vec<&String> = self.pkg.keys().chain(other.pkg.keys()).collect();
+        names.sort();
+        names.dedup();
+        names
+            .into_iter()
+            .map(|name| PackageChange {
+                name: name.clone(),
+                from: self.pkg_state(name, target),
+                to: other.pkg_state(name, target),
+            })
+            .filter(|change| change.from != change.to)
+            .collect()
+    }
+
+    // The version when usable on `target`, otherwise why not.
+    fn pkg_state(&self, name: &str, target: &str) -> String {
+        match (self.pkg_for_target(name, target), self.pkg_version(name)) {
+            (None, _) if !self.pkg.contains_key(name) => "absent".to_string(),
+            (Some(info), Some(version)) if info.available => version.to_string(),
+            (Some(info), None) if info.available => "available".to_string(),
+            _ => "unavailable".to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum MissingReason {
+    // Not in the manifest at all, e.g. dropped from nightlies.
+    Absent,
+    // Built for the target, but marked `available = false`.
+    Unavailable,
+    // In the manifest, but with no build for the target.
+    TargetNotPublished,
+}
+
+impl fmt::Display for MissingReason {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MissingReason::Absent => write!(f, "package absent from manifest"),
+            MissingReason::Unavailable => write!(f, "not available for target"),
+            MissingReason::TargetNotPublished => write!(f, "target not published"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PackageChange {
+    pub name: String,
+    pub from: String,
+    pub to: String,
+}
+
+impl FromStr for Manifest {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Manifest::from_reader(s.as_bytes())
+    }
 }
 
 impl PartialEq for Manifest {
@@ -76,16 +428,51 @@ impl PartialEq for Manifest {
     }
 }
 
+// Renames can chain, e.g. `a` to `b` and later `b` to `c`, so `a` is
+// followed through to `c`. A loop stops at the last name before it repeats.
+// Only this manifest's renames are followed: a hop recorded in some other
+// manifest alone is not known here.
+fn follow_renames<'a>(renames: &'a HashMap<String, Rename>, name: &'a str) -> &'a str {
+    let mut seen = vec![name];
+    let mut resolved = name;
+    while let Some(rename) = renames.get(resolved) {
+        if seen.contains(&rename.to.as_str()) {
+            break;
+        }
+        resolved = &rename.to;
+        seen.push(resolved);
+    }
+    resolved
+}
+
 #[derive(Clone, Debug, Deserialize, Eq)]
 pub struct PackageTargets {
     #[serde(deserialize_with = "version_from_str")]
     pub version: Option<Version>,
+    // The full source commit, which newer manifests publish per package.
+    pub git_commit_hash: Option<String>,
     pub target: HashMap<String, PackageInfo>,
 }
 
+impl PackageTargets {
+    // Sorted concrete triples; the `*` wildcard entry is not a target.
+    pub fn available_targets(&self) -> Vec<String> {
+        let mut targets: Vec<String> = self
+            .target
+            .iter()
+            .filter(|(target, info)| info.available && target.as_str() != "*")
+            .map(|(target, _)| target.clone())
+            .collect();
+        targets.sort();
+        targets
+    }
+}
+
 impl PartialEq for PackageTargets {
     fn eq(&self, other: &PackageTargets) -> bool {
-        self.version == other.version && self.target == other.target
+        self.version == other.version
+            && self.git_commit_hash == other.git_commit_hash
+            && self.target == other.target
     }
 }
 
@@ -98,6 +485,43 @@ pub struct PackageInfo {
     pub xz_hash: Option<String>,
 }
 
+impl PackageInfo {
+    // The tarball in the `prefer`red compression, or the other one when only
+    // that is published.
+    pub fn best_url(&self, prefer: Compression) -> Option<Download> {
+        let xz = match (&self.xz_url, &self.xz_hash) {
+            (Some(url), Some(hash)) => Some(Download {
+                url: url.clone(),
+                hash: hash.clone(),
+                compression: "xz",
+            }),
+            _ => None,
+        };
+        let gz = match (&self.url, &self.hash) {
+            (Some(url), Some(hash)) => Some(Download {
+                url: url.clone(),
+                hash: hash.clone(),
+                compression: "gz",
+            }),
+            _ => None,
+        };
+        match prefer {
+            Compression::Xz => xz.or(gz),
+            Compression::Gz => gz.or(xz),
+        }
+    }
+
+    // `best_url` moved onto `server`, the way rustup honours
+    // `RUSTUP_DIST_SERVER`.
+    pub fn resolved_url(&self, server: &str, prefer: Compression) -> Option<Download> {
+        let mut download = self.best_url(prefer)?;
+        if let Some(path) = download.url.strip_prefix(DIST_SERVER) {
+            download.url = format!("{}{}", server.trim_end_matches('/'), path);
+        }
+        Some(download)
+    }
+}
+
 impl PartialEq for PackageInfo {
     fn eq(&self, other: &PackageInfo) -> bool {
         self.available == other.available
@@ -108,6 +532,37 @@ impl PartialEq for PackageInfo {
     }
 }
 
+// Which tarball to download when a package publishes both; xz is smaller,
+// gz needs no extra tooling.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Compression {
+    #[default]
+    Xz,
+    Gz,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Download {
+    pub url: String,
+    pub hash: String,
+    // "xz" or "gz".
+    pub compression: &'static str,
+}
+
+impl fmt::Display for Download {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} {} {}", self.compression, self.url, self.hash)
+    }
+}
+
+pub fn path_template() -> String {
+    env::var("RUSTUPSCHECK_PATH_TEMPLATE").unwrap_or_else(|_| PATH_TEMPLATE.to_string())
+}
+
+pub fn dist_server() -> String {
+    env::var("RUSTUP_DIST_SERVER").unwrap_or_else(|_| DIST_SERVER.to_string())
+}
+
 #[derive(Clone, Debug, Deserialize, Eq)]
 pub struct Rename {
     pub to: String,
@@ -119,7 +574,8 @@ impl PartialEq for Rename {
     }
 }
 
-#[derive(Clone, Debug, Eq, PartialEq)]
+#[derive(Clone, Debug, Eq, PartialEq, Hash, Serialize)]
+#[serde(rename_all = "lowercase")]
 pub enum Channel {
     Stable,
     Beta,
@@ -127,6 +583,10 @@ pub enum Channel {
 }
 
 impl Channel {
+    pub fn all() -> [Channel; 3] {
+        [Channel::Stable, Channel::Beta, Channel::Nightly]
+    }
+
     fn to_u8(&self) -> u8 {
         match self {
             Channel::Stable => 0,
@@ -136,6 +596,16 @@ impl Channel {
     }
 }
 
+impl fmt::Display for Channel {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Channel::Stable => write!(f, "stable"),
+            Channel::Beta => write!(f, "beta"),
+            Channel::Nightly => write!(f, "nightly"),
+        }
+    }
+}
+
 impl FromStr for Channel {
     type Err = String;
 
@@ -175,13 +645,34 @@ impl FromStr for Commit {
             .trim_matches(|c| c == '(' || c == ')')
             .splitn(2, ' ')
             .collect();
+        // Builds without git info report only a date, e.g. `(2023-06-01)`;
+        // the hash is left as a placeholder.
+        if split.len() == 1 {
+            if let Ok(date) = NaiveDate::parse_from_str(split[0].trim(), "%Y-%m-%d") {
+                return Ok(Commit {
+                    hash: "000000000".to_string(),
+                    date,
+                });
+            }
+        }
+        if split.len() != 2 {
+            return Err(format!("wrong commit: {}", input));
+        }
         Ok(Commit {
             hash: split[0].to_string(),
-            date: NaiveDate::parse_from_str(split[1], "%Y-%m-%d").map_err(|e| e.to_string())?,
+            date: NaiveDate::parse_from_str(split[1].trim(), "%Y-%m-%d")
+                .map_err(|e| e.to_string())?,
         })
     }
 }
 
+impl Commit {
+    // Some builds report an all-zero hash with a real date.
+    pub fn is_placeholder_hash(&self) -> bool {
+        !self.hash.is_empty() && self.hash.chars().all(|c| c == '0')
+    }
+}
+
 impl PartialOrd for Commit {
     fn partial_cmp(&self, other: &Commit) -> Option<Ordering> {
         Some(self.cmp(other))
@@ -200,16 +691,26 @@ impl PartialEq for Commit {
     }
 }
 
+// Only the date, like `eq`: commits differing just in hash must collide.
+impl Hash for Commit {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.date.hash(state);
+    }
+}
+
 #[derive(Clone, Debug, Eq)]
 pub struct Version {
     pub channel: Channel,
     pub version: String,
+    // The `8` of `1.72.0-beta.8`: betas built on the same day differ only
+    // here.
+    pub build: Option<u32>,
     pub commit: Commit,
 }
 
 impl PartialOrd for Version {
     fn partial_cmp(&self, other: &Version) -> Option<Ordering> {
-        Some(self.cmp(&other))
+        Some(self.cmp(other))
     }
 }
 
@@ -218,7 +719,9 @@ impl Ord for Version {
         match self.channel.cmp(&other.channel) {
             Ordering::Greater => Ordering::Greater,
             Ordering::Less => Ordering::Less,
-            Ordering::Equal => match self.version.cmp(&other.version) {
+            Ordering::Equal => match compare_versions(&self.version, &other.version)
+                .then_with(|| self.build.cmp(&other.build))
+            {
                 Ordering::Greater => Ordering::Greater,
                 Ordering::Less => Ordering::Less,
                 Ordering::Equal => match self.commit.cmp(&other.commit) {
@@ -235,10 +738,21 @@ impl PartialEq for Version {
     fn eq(&self, other: &Version) -> bool {
         self.channel == other.channel
             && self.version == other.version
+            && self.build == other.build
             && self.commit.date == other.commit.date
     }
 }
 
+// Hashes exactly the fields `eq` compares.
+impl Hash for Version {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.channel.hash(state);
+        self.version.hash(state);
+        self.build.hash(state);
+        self.commit.hash(state);
+    }
+}
+
 impl fmt::Display for Version {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(
@@ -246,68 +760,219 @@ impl fmt::Display for Version {
             "{} ({} {})",
             self.version,
             self.commit.hash,
-            self.commit.date.format("%Y-%m-%d").to_string()
+            self.commit.date.format("%Y-%m-%d")
         )
     }
 }
 
+// Serialized in the same form `FromStr` reads, e.g.
+// `1.33.0-nightly (9eac38634 2018-12-31)`, so the channel is kept.
+impl<'de> Deserialize<'de> for Version {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let s: String = Deserialize::deserialize(deserializer)?;
+        s.parse().map_err(D::Error::custom)
+    }
+}
+
+impl Serialize for Version {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let channel = match self.channel {
+            Channel::Stable => "",
+            Channel::Beta => "-beta",
+            Channel::Nightly => "-nightly",
+        };
+        let build = match self.build {
+            Some(build) => format!(".{}", build),
+            None => String::new(),
+        };
+        serializer.collect_str(&format_args!(
+            "{}{}{} ({} {})",
+            self.version,
+            channel,
+            build,
+            self.commit.hash,
+            self.commit.date.format("%Y-%m-%d")
+        ))
+    }
+}
+
 impl FromStr for Version {
     type Err = String;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
         let split: Vec<&str> = s.splitn(2, ' ').collect();
+        if split.len() != 2 {
+            return Err(format!("wrong version: {}", s));
+        }
         let (raw_version, commit) = (split[0], split[1]);
-        let split: Vec<&str> = raw_version.split('-').collect();
-        let (version, channel) = if split.len() == 2 {
-            (split[0].to_string(), split[1])
-        } else {
-            (split[0].to_string(), "")
+        let (version, (channel, build)) = match raw_version.split_once('-') {
+            Some((version, channel)) => (version.to_string(), channel_suffix(channel)?),
+            None => (raw_version.to_string(), (Channel::Stable, None)),
         };
         let commit = commit.parse()?;
-        let channel = channel.parse()?;
         Ok(Version {
             channel,
             version,
+            build,
             commit,
         })
     }
 }
 
+impl Version {
+    // The channel as rustup spells it in toolchain names, "stable" included.
+    pub fn channel_str(&self) -> String {
+        self.channel.to_string()
+    }
+
+    // The release series, e.g. `(1, 72)` for every 1.72 nightly, beta and
+    // stable; an error for a version that isn't numeric.
+    pub fn major_minor(&self) -> Result<(u32, u32), String> {
+        let mut parts = self.version.split('.');
+        let mut number = || {
+            parts
+                .next()
+                .and_then(|part| part.parse::<u32>().ok())
+                .ok_or_else(|| format!("wrong version number: {}", self.version))
+        };
+        Ok((number()?, number()?))
+    }
+
+    // Builds the manifest-style "1.33.0-nightly (9eac38634 2018-12-31)" from the
+    // `release`, `commit-hash` and `commit-date` lines of `rustc -vV`.
+    pub fn parse_rustc_verbose(output: &str) -> Option<Version> {
+        let hash = rustc_verbose_field(output, "commit-hash")?;
+        format!(
+            "{} ({} {})",
+            rustc_verbose_field(output, "release")?,
+            hash.get(..9).unwrap_or(hash),
+            rustc_verbose_field(output, "commit-date")?
+        )
+        .parse()
+        .ok()
+    }
+}
+
+// The target triple rustc was built for, from the `host` line of `rustc -vV`.
+pub fn rustc_host(output: &str) -> Option<String> {
+    rustc_verbose_field(output, "host").map(str::to_string)
+}
+
+// Checks only the shape `arch-vendor-os[-env]`, so triples newer than this
+// tool still pass, but typos and toolchain names or dates don't.
+pub fn validate_target(target: &str) -> Result<(), String> {
+    let parts: Vec<&str> = target.split('-').collect();
+    let well_formed = (2..=5).contains(&parts.len())
+        && parts.iter().all(|part| {
+            !part.is_empty()
+                && part
+                    .chars()
+                    .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_' || c == '.')
+        })
+        && parts[0].starts_with(|c: char| c.is_ascii_lowercase())
+        && !["stable", "beta", "nightly"].contains(&parts[0]);
+    if well_formed {
+        Ok(())
+    } else {
+        Err(format!(
+            "Invalid target triple {}: expected arch-vendor-os[-env], \
+             e.g. x86_64-unknown-linux-gnu",
+            target
+        ))
+    }
+}
+
+fn rustc_verbose_field<'a>(output: &'a str, name: &str) -> Option<&'a str> {
+    output
+        .lines()
+        .find_map(|line| line.strip_prefix(name)?.strip_prefix(": "))
+        .map(str::trim)
+}
+
+// Compares dotted release numbers numerically so that `1.9.0 < 1.10.0`,
+// falling back to plain string order for anything non-numeric.
+pub(crate) fn compare_versions(a: &str, b: &str) -> Ordering {
+    let numbers = |s: &str| -> Option<Vec<u64>> { s.split('.').map(|n| n.parse().ok()).collect() };
+    match (numbers(a), numbers(b)) {
+        (Some(x), Some(y)) => x.cmp(&y).then_with(|| a.cmp(b)),
+        _ => a.cmp(b),
+    }
+}
+
 fn u8_from_str<'de, D>(deserializer: D) -> Result<u8, D::Error>
 where
     D: Deserializer<'de>,
 {
     let s: &str = Deserialize::deserialize(deserializer)?;
-    u8::from_str_radix(s, 10).map_err(D::Error::custom)
+    s.parse::<u8>().map_err(D::Error::custom)
+}
+
+// The channel after the `-` of a version; betas carry a build number, e.g.
+// `1.72.0-beta.8`.
+fn channel_suffix(s: &str) -> Result<(Channel, Option<u32>), String> {
+    match s.split_once('.') {
+        Some(("beta", build)) if is_number(build) => build
+            .parse()
+            .map(|build| (Channel::Beta, Some(build)))
+            .map_err(|_| format!("wrong build number: {}", s)),
+        Some(_) => Err(format!("wrong channel: {}", s)),
+        None => Ok((s.parse()?, None)),
+    }
 }
 
+fn is_number(s: &str) -> bool {
+    !s.is_empty() && s.bytes().all(|b| b.is_ascii_digit())
+}
+
+// A bare `major.minor.patch[-channel]`, as packages published without a
+// commit are versioned.
+fn is_release_number(s: &str) -> bool {
+    let (number, channel) = match s.split_once('-') {
+        Some((number, channel)) => (number, Some(channel)),
+        None => (s, None),
+    };
+    let parts: Vec<&str> = number.split('.').collect();
+    parts.len() == 3
+        && parts.iter().all(|part| is_number(part))
+        && match channel {
+            Some(channel) => matches!(
+                channel_suffix(channel),
+                Ok((Channel::Beta, _)) | Ok((Channel::Nightly, _))
+            ),
+            None => true,
+        }
+}
+
+// Package versions may be empty, and newer manifests list some tools without a
+// commit (`0.1.0-nightly`, `0.0.0`, `1.11.0-beta.5`); such packages just have
+// no comparable version. Anything else has to parse.
 fn version_from_str<'de, D>(deserializer: D) -> Result<Option<Version>, D::Error>
 where
     D: Deserializer<'de>,
 {
-    let s: &str = Deserialize::deserialize(deserializer)?;
-    Ok(if s.is_empty() {
-        None
-    } else {
-        Some(s.parse().map_err(D::Error::custom)?)
-    })
-}
-
-fn body(response: &[u8]) -> Result<&str, String> {
-    let pos = response
-        .windows(4)
-        .position(|x| x == b"\r\n\r\n")
-        .ok_or("Not search pattern")?;
-    let body = &response[pos + 4..response.len()];
-    std::str::from_utf8(&body).map_err(|e| e.to_string())
+    let s: String = Deserialize::deserialize(deserializer)?;
+    if s.is_empty() || is_release_number(&s) {
+        return Ok(None);
+    }
+    Version::from_str(&s).map(Some).map_err(D::Error::custom)
 }
 
-#[test]
-fn test_body() {
-    let response = b"HTTP/2.0 200 OK\r\nx-amz-bucket-region: us-west-1\r\nserver: AmazonS3\r\nx-cache: Miss from cloudfront\r\n\r\ntest message";
-    assert_eq!(body(response), Ok("test message"));
-    let response = b"\r\n\r\ntest message";
-    assert_eq!(body(response), Ok("test message"));
-    let response = b"\r\n\r\ntest message\r\n\r\ntest message";
-    assert_eq!(body(response), Ok("test message\r\n\r\ntest message"));
+pub fn expand_path_template(template: &str, date: &str, channel: &str) -> Result<String, String> {
+    for placeholder in &["{date}", "{channel}"] {
+        if !template.contains(placeholder) {
+            return Err(format!(
+                "path template {} does not contain {}",
+                template, placeholder
+            ));
+        }
+    }
+    Ok(template
+        .replace("{date}", date)
+        .replace("{channel}", channel))
 }
@@ -0,0 +1,45 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+pub const PIN_FILE: &str = "rust-toolchain.toml";
+
+// A recommended toolchain in the shape of rustup's `rust-toolchain.toml`.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Pin {
+    pub channel: String,
+    pub components: Vec<String>,
+    pub targets: Vec<String>,
+}
+
+#[derive(Serialize)]
+struct PinFile<'a> {
+    toolchain: &'a Pin,
+}
+
+impl Pin {
+    pub fn to_toml(&self) -> Result<String, String> {
+        toml::to_string(&PinFile { toolchain: self }).map_err(|e| e.to_string())
+    }
+
+    // Refuses to replace an existing file unless forced, and then keeps the
+    // old one next to it as `rust-toolchain.toml.bak`.
+    pub fn write(&self, dir: &Path, force: bool) -> Result<PathBuf, String> {
+        let path = dir.join(PIN_FILE);
+        if path.exists() {
+            if !force {
+                return Err(format!(
+                    "{} already exists; use --force to replace it",
+                    path.display()
+                ));
+            }
+            let backup = dir.join(format!("{}.bak", PIN_FILE));
+            fs::rename(&path, &backup)
+                .map_err(|e| format!("Can't back up {}: {}", path.display(), e))?;
+        }
+        fs::write(&path, self.to_toml()?)
+            .map_err(|e| format!("Can't write {}: {}", path.display(), e))?;
+        Ok(path)
+    }
+}
@@ -0,0 +1,9 @@
+// `use rustupscheck::prelude::*` for the types most callers need. These are
+// the stable API: checking a toolchain and reading the outcome. Everything
+// else, such as `Source`, `FetchConfig` or the `format` helpers, is public
+// for the binary's sake and may still change.
+pub use crate::{
+    check::{check_toolchain, CheckOptions},
+    report::Report,
+    Channel, CheckError, CheckResult, Component, Manifest, Rust, Toolchain, Version,
+};
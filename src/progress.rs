@@ -0,0 +1,55 @@
+use std::{
+    io::{self, Write},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+const FRAMES: &[char] = &['|', '/', '-', '\\'];
+const FRAME_INTERVAL: Duration = Duration::from_millis(100);
+
+// Animates a spinner next to `message` until dropped, then erases the line.
+pub struct Spinner {
+    stop: Arc<AtomicBool>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl Spinner {
+    pub fn start(message: String) -> Spinner {
+        Spinner::start_on(io::stderr(), message)
+    }
+
+    pub fn start_on<W: Write + Send + 'static>(mut out: W, message: String) -> Spinner {
+        let stop = Arc::new(AtomicBool::new(false));
+        let stopped = stop.clone();
+        let handle = thread::spawn(move || {
+            for frame in FRAMES.iter().cycle() {
+                if stopped.load(Ordering::SeqCst) {
+                    break;
+                }
+                let _ = write!(out, "\r{} {}", frame, message);
+                let _ = out.flush();
+                thread::park_timeout(FRAME_INTERVAL);
+            }
+            let _ = write!(out, "\r\x1b[K");
+            let _ = out.flush();
+        });
+        Spinner {
+            stop,
+            handle: Some(handle),
+        }
+    }
+}
+
+impl Drop for Spinner {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::SeqCst);
+        if let Some(handle) = self.handle.take() {
+            handle.thread().unpark();
+            let _ = handle.join();
+        }
+    }
+}
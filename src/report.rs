@@ -0,0 +1,134 @@
+use crate::{
+    args::Format, format, manifest::Download, pin::Pin, CheckResult, ComponentRow, Rationale,
+    Version,
+};
+
+// Everything a check found out, computed once and rendered in any format.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Report {
+    #[serde(skip)]
+    pub banner: String,
+    pub toolchain: String,
+    pub installed: Option<Version>,
+    pub installed_components: Vec<String>,
+    pub candidate: Option<String>,
+    // Set when the candidate's manifest is dated differently than the date
+    // it was fetched for.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub date_note: Option<String>,
+    pub missing: Vec<String>,
+    // Installed components no nightly of the scan had, which most likely
+    // were dropped upstream rather than being briefly broken.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub removed_upstream: Vec<String>,
+    // The component reinstall step for the recommended toolchain, if any.
+    #[serde(skip)]
+    pub component_add: Option<String>,
+    // What `--pin` writes for the recommended toolchain, if any.
+    #[serde(skip)]
+    pub pin: Option<Pin>,
+    // Package tarballs of the recommended toolchain, for `--archive`.
+    #[serde(skip)]
+    pub downloads: Vec<(String, Download)>,
+    // Per-component comparison with the candidate, or the newest nightly
+    // when nothing is complete; shown with `--verbose`.
+    #[serde(skip)]
+    pub components: Vec<ComponentRow>,
+    pub rationale: Rationale,
+    #[serde(flatten)]
+    pub result: CheckResult,
+}
+
+impl Report {
+    // 0 when up to date, 10 when an update or switch is recommended and 20
+    // when no usable nightly was found; 1 and 2 are left for errors.
+    pub fn exit_code(&self) -> i32 {
+        match self.result {
+            CheckResult::UpToDate => 0,
+            CheckResult::Update { .. } | CheckResult::Switch { .. } => 10,
+            CheckResult::NotFound { .. } | CheckResult::TargetUnavailable { .. } => 20,
+        }
+    }
+
+    // Whether there is something to run, as opposed to nothing to do or
+    // nothing that would help.
+    pub fn is_actionable(&self) -> bool {
+        matches!(
+            self.result,
+            CheckResult::Update { .. } | CheckResult::Switch { .. }
+        )
+    }
+
+    // An empty banner, as left by `--no-banner`, takes no line at all.
+    fn with_banner(&self, body: String) -> String {
+        if self.banner.is_empty() {
+            body
+        } else {
+            format!("{}\n{}", self.banner, body)
+        }
+    }
+
+    pub fn render(&self, format: Format) -> String {
+        match format {
+            Format::Text => self.with_banner(format::text(&self.result)),
+            Format::Markdown => self.with_banner(format::markdown(&self.result)),
+            Format::Json => format!(
+                "{}\n",
+                serde_json::to_string_pretty(self).unwrap_or_else(|e| e.to_string())
+            ),
+            // One line, for log ingestion.
+            Format::JsonCompact => format!(
+                "{}\n",
+                serde_json::to_string(self).unwrap_or_else(|e| e.to_string())
+            ),
+            // Through `toml::Value`, which orders plain values before tables
+            // as TOML requires.
+            Format::Toml => toml::Value::try_from(self)
+                .map(|value| value.to_string())
+                .unwrap_or_else(|e| format!("{}\n", e)),
+            Format::JsonLines => format!("{}\n", format::json_line(self)),
+            Format::Summary => format!("{}\n", format::summary(&self.result)),
+            Format::ComponentsAdd => match &self.component_add {
+                Some(line) => format!("{}\n", line),
+                None => String::new(),
+            },
+            Format::ComponentsJson => format::components_json(&self.components),
+            Format::Archive => self
+                .downloads
+                .iter()
+                .map(|(name, download)| format!("{} {}\n", name, download))
+                .collect(),
+        }
+    }
+}
+
+// Reports for several toolchains at once: a single document for the
+// structured formats, one report after another for the rest.
+pub fn render_all(reports: &[Report], format: Format) -> String {
+    match format {
+        Format::Json => format!(
+            "{}\n",
+            serde_json::to_string_pretty(reports).unwrap_or_else(|e| e.to_string())
+        ),
+        Format::JsonCompact => format!(
+            "{}\n",
+            serde_json::to_string(reports).unwrap_or_else(|e| e.to_string())
+        ),
+        Format::Toml => {
+            let mut table = toml::value::Table::new();
+            match toml::Value::try_from(reports) {
+                Ok(value) => {
+                    table.insert("toolchain".to_string(), value);
+                    toml::Value::Table(table).to_string()
+                }
+                Err(e) => format!("{}\n", e),
+            }
+        }
+        Format::Text | Format::Markdown => reports
+            .iter()
+            .map(|report| report.render(format))
+            .collect::<Vec<_>>()
+            .join("\n"),
+        _ => reports.iter().map(|report| report.render(format)).collect(),
+    }
+}
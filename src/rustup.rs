@@ -0,0 +1,104 @@
+use crate::{http, is_custom_name, manifest::compare_versions, split_toolchain_name};
+use std::{cmp::Ordering, collections::BTreeMap, fs, path::Path, process::Command, str::FromStr};
+
+pub const RELEASE_PATH: &str = "/rustup/release-stable.toml";
+
+// The dist server's note on the newest rustup, a much smaller schema than
+// the channel manifests.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct RustupRelease {
+    pub schema_version: String,
+    pub version: String,
+}
+
+impl RustupRelease {
+    // Always from the official server: mirrors carry toolchains, not
+    // rustup's release notes.
+    pub fn latest() -> Result<RustupRelease, String> {
+        http::get_from(&http::Server::default(), RELEASE_PATH)?.parse()
+    }
+}
+
+impl FromStr for RustupRelease {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        toml::from_str(s).map_err(|e| e.to_string())
+    }
+}
+
+// From `rustup 1.27.1 (54dd3d00f 2024-04-24)`.
+pub fn parse_rustup_version(output: &str) -> Option<String> {
+    let line = output.lines().find(|line| line.starts_with("rustup "))?;
+    line.split_whitespace().nth(1).map(str::to_string)
+}
+
+pub fn installed_version() -> Result<String, String> {
+    let output = Command::new("rustup")
+        .arg("--version")
+        .output()
+        .map_err(|e| format!("rustup not found: {}", e))?;
+    parse_rustup_version(&String::from_utf8_lossy(&output.stdout))
+        .ok_or_else(|| "Can't read the version from rustup --version".to_string())
+}
+
+pub fn status(installed: &str, latest: &RustupRelease) -> String {
+    match compare_versions(installed, &latest.version) {
+        Ordering::Less => format!(
+            "rustup {} is available (you have {})",
+            latest.version, installed
+        ),
+        _ => format!("rustup {} is up to date", installed),
+    }
+}
+
+// The parts of `$RUSTUP_HOME/settings.toml` that decide which toolchain
+// rustup runs, for when there is no proxy to set `RUSTUP_TOOLCHAIN`.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+pub struct RustupSettings {
+    pub default_host_triple: Option<String>,
+    pub default_toolchain: Option<String>,
+    // Directory to toolchain, from `rustup override set`.
+    #[serde(default)]
+    pub overrides: BTreeMap<String, String>,
+}
+
+impl FromStr for RustupSettings {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        toml::from_str(s).map_err(|e| e.to_string())
+    }
+}
+
+impl RustupSettings {
+    pub fn read(rustup_home: &Path) -> Result<RustupSettings, String> {
+        let path = rustup_home.join("settings.toml");
+        fs::read_to_string(&path)
+            .map_err(|e| format!("Can't read {}: {}", path.display(), e))?
+            .parse()
+            .map_err(|e| format!("Can't parse {}: {}", path.display(), e))
+    }
+
+    // The override for `dir` or its nearest overridden ancestor, else the
+    // default, as a full toolchain name.
+    pub fn toolchain_for(&self, dir: &Path) -> Option<String> {
+        let name = dir
+            .ancestors()
+            .find_map(|dir| self.overrides.get(dir.to_str()?))
+            .or(self.default_toolchain.as_ref())?;
+        Some(self.with_host(name))
+    }
+
+    // rustup accepts `nightly` or `nightly-2019-01-01` and fills in the
+    // default host; custom names are kept as they are.
+    fn with_host(&self, name: &str) -> String {
+        match &self.default_host_triple {
+            Some(host) if !is_custom_name(name) && split_toolchain_name(name).is_err() => {
+                format!("{}-{}", name, host)
+            }
+            _ => name.to_string(),
+        }
+    }
+}
@@ -0,0 +1,134 @@
+use crate::{
+    cache::Cache,
+    manifest::{FetchConfig, FetchError, Manifest},
+};
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    fmt, fs,
+    path::{Path, PathBuf},
+};
+
+pub trait Source: fmt::Debug {
+    // `Ok(None)` when nothing was published for that date, which is routine
+    // for nightlies; `Err` only when the answer couldn't be obtained or
+    // turned out malformed.
+    fn manifest(&self, date: &str, channel: &str) -> Result<Option<Manifest>, FetchError>;
+
+    // The newest manifest of a channel, which changes over time.
+    fn latest(&self, channel: &str) -> Result<Manifest, FetchError>;
+}
+
+// The dist server, through `Manifest::from_date` with one shared
+// `FetchConfig` for every date of a scan.
+#[derive(Debug, Clone, Default)]
+pub struct Dist {
+    config: FetchConfig,
+    // Published manifests already parsed in this run. Dates with nothing
+    // published yet aren't kept, since they may appear while watching.
+    fetched: RefCell<HashMap<(String, String), Manifest>>,
+}
+
+impl Dist {
+    pub fn new() -> Dist {
+        Dist::default()
+    }
+
+    pub fn cached() -> Dist {
+        Dist::with_config(FetchConfig {
+            cache: Cache::default_dir().map(Cache::new),
+            ..FetchConfig::default()
+        })
+    }
+
+    pub fn with_cache(cache: Cache) -> Dist {
+        Dist::with_config(FetchConfig {
+            cache: Some(cache),
+            ..FetchConfig::default()
+        })
+    }
+
+    pub fn with_config(config: FetchConfig) -> Dist {
+        Dist {
+            config,
+            ..Dist::default()
+        }
+    }
+
+    // Shows a spinner on stderr while a download is in flight.
+    pub fn with_progress(mut self, progress: bool) -> Dist {
+        self.config.progress = progress;
+        self
+    }
+}
+
+impl Source for Dist {
+    fn manifest(&self, date: &str, channel: &str) -> Result<Option<Manifest>, FetchError> {
+        let key = (date.to_string(), channel.to_string());
+        if let Some(manifest) = self.fetched.borrow().get(&key) {
+            return Ok(Some(manifest.clone()));
+        }
+        let manifest = Manifest::from_date(date, channel, &self.config)?;
+        if let Some(manifest) = &manifest {
+            self.fetched.borrow_mut().insert(key, manifest.clone());
+        }
+        Ok(manifest)
+    }
+
+    fn latest(&self, channel: &str) -> Result<Manifest, FetchError> {
+        Manifest::latest_with(channel, &self.config)
+    }
+}
+
+// Reads manifests from a directory of `channel-rust-<channel>-<date>.toml`
+// files, e.g. a pre-synced mirror, without touching the network.
+#[derive(Debug, Clone)]
+pub struct ManifestDir {
+    dir: PathBuf,
+}
+
+impl ManifestDir {
+    pub fn new<P: Into<PathBuf>>(dir: P) -> ManifestDir {
+        ManifestDir { dir: dir.into() }
+    }
+
+    pub fn file_name(date: &str, channel: &str) -> String {
+        format!("channel-rust-{}-{}.toml", channel, date)
+    }
+}
+
+impl Source for ManifestDir {
+    // A date without a file is treated like an unpublished nightly.
+    fn manifest(&self, date: &str, channel: &str) -> Result<Option<Manifest>, FetchError> {
+        let path = self.dir.join(ManifestDir::file_name(date, channel));
+        if !path.is_file() {
+            return Ok(None);
+        }
+        read_manifest(&path).map(Some)
+    }
+
+    // The undated `channel-rust-<channel>.toml` when synced, the newest
+    // dated file otherwise.
+    fn latest(&self, channel: &str) -> Result<Manifest, FetchError> {
+        let undated = self.dir.join(format!("channel-rust-{}.toml", channel));
+        if undated.is_file() {
+            return read_manifest(&undated);
+        }
+        let prefix = format!("channel-rust-{}-", channel);
+        let newest = fs::read_dir(&self.dir)
+            .map_err(|e| format!("Can't list {}: {}", self.dir.display(), e))?
+            .filter_map(|entry| entry.ok()?.file_name().into_string().ok())
+            .filter(|name| name.starts_with(&prefix) && name.ends_with(".toml"))
+            .max()
+            .ok_or_else(|| format!("No {} manifest in {}", channel, self.dir.display()))?;
+        read_manifest(&self.dir.join(newest))
+    }
+}
+
+fn read_manifest(path: &Path) -> Result<Manifest, FetchError> {
+    let contents = fs::read_to_string(path)
+        .map_err(|e| FetchError::Fetch(format!("{}: {}", path.display(), e)))?;
+    contents
+        .parse()
+        .map_err(|e| FetchError::Malformed(format!("{}: {}", path.display(), e)))
+}
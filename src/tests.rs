@@ -1,6 +1,125 @@
 use super::*;
-use crate::manifest::*;
-use std::str::FromStr;
+use crate::{args::*, manifest::*, source::Source};
+use std::{collections::HashMap, str::FromStr};
+
+const TARGET: &str = "x86_64-unknown-linux-gnu";
+
+#[derive(Debug, Clone, Default)]
+struct MockSource {
+    manifests: HashMap<String, Manifest>,
+    latest: HashMap<String, Manifest>,
+    failing: Vec<String>,
+    malformed: Vec<String>,
+}
+
+impl MockSource {
+    fn with(mut self, manifest: Manifest) -> Self {
+        self.manifests
+            .insert(manifest.date.format("%Y-%m-%d").to_string(), manifest);
+        self
+    }
+
+    fn with_latest(mut self, channel: &str, manifest: Manifest) -> Self {
+        self.latest.insert(channel.to_string(), manifest);
+        self
+    }
+
+    // Fetching `date` fails as if the network were down.
+    fn failing(mut self, date: &str) -> Self {
+        self.failing.push(date.to_string());
+        self
+    }
+
+    // `date` is published, but its manifest doesn't parse.
+    fn malformed(mut self, date: &str) -> Self {
+        self.malformed.push(date.to_string());
+        self
+    }
+}
+
+impl Source for MockSource {
+    fn manifest(&self, date: &str, _channel: &str) -> Result<Option<Manifest>, FetchError> {
+        if self.failing.iter().any(|d| d == date) {
+            return Err(FetchError::Fetch(format!("{} unreachable", date)));
+        }
+        if self.malformed.iter().any(|d| d == date) {
+            return Err(FetchError::Malformed(format!("{} is truncated", date)));
+        }
+        Ok(self.manifests.get(date).cloned())
+    }
+
+    fn latest(&self, channel: &str) -> Result<Manifest, FetchError> {
+        self.latest
+            .get(channel)
+            .cloned()
+            .ok_or_else(|| FetchError::Fetch(format!("{} not found", channel)))
+    }
+}
+
+// Builds a manifest for `date` where every package is published for TARGET
+// with the given version and availability.
+fn mock_manifest(date: &str, pkgs: &[(&str, &str, bool)]) -> Manifest {
+    mock_manifest_toml(date, pkgs).parse().unwrap()
+}
+
+fn mock_manifest_toml(date: &str, pkgs: &[(&str, &str, bool)]) -> String {
+    let mut toml = format!("manifest-version = \"2\"\ndate = \"{}\"\n", date);
+    for (name, version, available) in pkgs {
+        toml.push_str(&format!(
+            "[pkg.{0}]\nversion = \"{1}\"\n[pkg.{0}.target.{2}]\navailable = {3}\n",
+            name, version, TARGET, available
+        ));
+    }
+    toml.push_str("[renames]\n");
+    toml
+}
+
+fn unavailable(names: &[&str]) -> Vec<MissingComponent> {
+    names
+        .iter()
+        .map(|name| MissingComponent {
+            name: name.to_string(),
+            reason: MissingReason::Unavailable,
+        })
+        .collect()
+}
+
+// Components end up sorted by name, as `Toolchain::from_dir` leaves them.
+fn mock_toolchain(manifest: Manifest, components: &[&str]) -> Toolchain {
+    let mut components: Vec<Component> = components
+        .iter()
+        .map(|c| Component::from(Some(&manifest), c))
+        .collect();
+    components.sort_by(|a, b| a.name.cmp(&b.name));
+    Toolchain {
+        channel: "nightly".to_string(),
+        target: TARGET.to_string(),
+        components,
+        manifest: Some(manifest),
+        rustc: None,
+    }
+}
+
+fn mock_rust(toolchain: Toolchain, source: MockSource, date: &str, offset: i64) -> Rust {
+    let manifest = source.manifest(date, &toolchain.channel).unwrap();
+    let date = NaiveDate::parse_from_str(date, "%Y-%m-%d").unwrap();
+    Rust {
+        offset,
+        date,
+        // A fresh scan (offset -1) sits on today itself.
+        today: date + Duration::days(offset.max(0)),
+        anchor: Anchor::Local,
+        toolchain: Rc::new(toolchain),
+        manifest,
+        source: Rc::new(source),
+        ignored: Vec::new(),
+        required: Vec::new(),
+        fail_on_missing: false,
+        floor: None,
+        unusable: None,
+        compression: Compression::default(),
+    }
+}
 
 #[test]
 fn test_component() {
@@ -9,7 +128,10 @@ fn test_component() {
         required: false,
         version: Version::from_str("1.31.6 (ae0d89a08 2019-01-12)").ok(),
     };
-    let update = comp.update_info(Version::from_str("1.31.6 (000000000 2019-01-13)").ok());
+    let other = Version::from_str("1.31.6 (000000000 2019-01-13)").unwrap();
+    assert!(other.commit.is_placeholder_hash());
+    assert!(!comp.version.as_ref().unwrap().commit.is_placeholder_hash());
+    let update = comp.update_info(Some(other)).map(|diff| diff.to_string());
     assert_eq!(
         update,
         Some(
@@ -31,6 +153,26 @@ fn test_version() {
     assert!(ver1 > ver3);
     assert!(ver1 == ver4);
     assert!(ver3 > ver2);
+    assert!(Version::from_str("0.1.0-nightly").is_err());
+    assert!(Version::from_str("").is_err());
+    let beta = Version::from_str("1.72.0-beta.8 (a6e5d3fd6 2023-08-04)").unwrap();
+    assert_eq!(beta.channel, Channel::Beta);
+    assert_eq!(beta.version, "1.72.0");
+    assert_eq!(beta.build, Some(8));
+    // Two betas built the same day are told apart by their build number.
+    let next = Version::from_str("1.72.0-beta.9 (4b2a2b0b8 2023-08-04)").unwrap();
+    assert_ne!(beta, next);
+    assert!(beta < next);
+    assert!(
+        Version::from_str("1.72.0-beta.10 (4b2a2b0b8 2023-08-04)").unwrap() > next,
+        "build numbers compare as numbers"
+    );
+    assert!(Version::from_str("1.72.0-beta (4b2a2b0b8 2023-08-04)").unwrap() < beta);
+    assert!(Version::from_str("1.72.0-beta.99999999999 (4b2a2b0b8 2023-08-04)").is_err());
+    let ver = |s: &str| Version::from_str(s).unwrap();
+    assert!(ver("1.9.0 (ae0d89a08 2019-01-13)") < ver("1.10.0 (ae0d89a08 2019-01-13)"));
+    assert!(ver("1.71.1 (ae0d89a08 2019-01-13)") > ver("1.71.0 (ae0d89a08 2019-01-13)"));
+    assert!(ver("1.71.0 (ae0d89a08 2019-01-13)") < ver("1.71.0.1 (ae0d89a08 2019-01-13)"));
 }
 
 #[test]
@@ -47,6 +189,14 @@ fn test_channel() {
     assert!(Channel::Nightly > Channel::Beta);
     assert!(Channel::Stable == Channel::Stable);
     assert!(Channel::Stable < Channel::Nightly);
+    for channel in Channel::all().iter() {
+        assert_eq!(Channel::from_str(&channel.to_string()), Ok(channel.clone()));
+    }
+    assert_eq!(Channel::Stable.to_string(), "stable");
+    let version = |s: &str| Version::from_str(s).unwrap().channel_str();
+    assert_eq!(version("1.31.1 (b6c32da9b 2018-12-18)"), "stable");
+    assert_eq!(version("1.32.0-beta.11 (e64fee6a3 2019-01-04)"), "beta");
+    assert_eq!(version("1.33.0-nightly (9eac38634 2018-12-31)"), "nightly");
 }
 
 #[test]
@@ -55,12 +205,32 @@ fn test_commit() {
     let c2 = Commit::from_str("(12fa34b 2018-12-31)");
     let c3 = Commit::from_str("12fa34a 2018-12-31");
     let c4 = Commit::from_str("12fa34b 2019-01-01");
+    let c5 = Commit::from_str("(00 2019-01-01)");
+    let c6 = Commit::from_str("(0123456789abcdef0123 2019-01-01)");
     assert!(c1.is_err());
+    assert!(Commit::from_str("12fa34b").is_err());
+    assert!(c5.as_ref().unwrap().is_placeholder_hash());
+    assert!(!c6.as_ref().unwrap().is_placeholder_hash());
+    assert!(c4 == c5 && c5 == c6);
     assert!(c2.is_ok());
     assert!(c2 == c3);
     assert!(c3 < c4);
 }
 
+#[test]
+fn test_date_only_commit() {
+    let version = Version::from_str("1.70.0 (2023-06-01)").unwrap();
+    assert_eq!(version.version, "1.70.0");
+    assert_eq!(version.channel, Channel::Stable);
+    assert_eq!(version.commit.date, NaiveDate::from_ymd(2023, 6, 1));
+    assert!(version.commit.is_placeholder_hash());
+    let commit = Commit::from_str("(2023-06-01)").unwrap();
+    assert!(commit.is_placeholder_hash());
+    assert_eq!(commit, version.commit);
+    assert!(Commit::from_str("(2023-13-01)").is_err());
+    assert!(Version::from_str("1.70.0 (12fa34b)").is_err());
+}
+
 #[test]
 fn test_wrong_path() {
     let path = "/dist/01-01-2019/channel-rust-nightly.toml";
@@ -73,16 +243,16 @@ fn test_wrong_path() {
 
 #[test]
 fn test_new_year_manifest() {
-    let manifest_from_date = Manifest::from_date("2019-01-01", "nightly");
+    let manifest_from_date = Manifest::from_date("2019-01-01", "nightly", &FetchConfig::default());
     let path = "/dist/2019-01-01/channel-rust-nightly.toml";
     let optional_manifest = Manifest::from_url(path);
     assert!(optional_manifest.is_ok());
     let manifest = optional_manifest.unwrap();
-    assert_eq!(manifest_from_date.unwrap(), manifest);
+    assert_eq!(manifest_from_date, Ok(Some(manifest.clone())));
     assert_eq!(manifest.manifest_version, 2u8);
     assert_eq!(
         Ok(manifest.date),
-        NaiveDate::parse_from_str(&"2019-01-01", "%Y-%m-%d")
+        NaiveDate::parse_from_str("2019-01-01", "%Y-%m-%d")
     );
     assert_eq!(
         manifest.renames.get("rls").unwrap().to,
@@ -91,9 +261,10 @@ fn test_new_year_manifest() {
     let rust1330 = Version {
         channel: Channel::Nightly,
         version: "1.33.0".to_string(),
+        build: None,
         commit: Commit {
             hash: "9eac38634".to_string(),
-            date: NaiveDate::parse_from_str(&"2018-12-31", "%Y-%m-%d").unwrap(),
+            date: NaiveDate::parse_from_str("2018-12-31", "%Y-%m-%d").unwrap(),
         },
     };
     assert_eq!(manifest.pkg_version("rust"), Some(rust1330));
@@ -102,14 +273,14 @@ fn test_new_year_manifest() {
     assert!(target_info.is_none());
     let target_info = rust_src.target.get("*");
     assert!(target_info.is_some());
+    // The dist server now marks this nightly's rust-src unavailable.
     let target_info = target_info.unwrap();
-    assert_eq!(target_info.available, true);
-    assert_eq!(
-        manifest
+    assert!(!target_info.available);
+    assert!(
+        !manifest
             .pkg_for_target("rust-src", "x86_64-pc-windows-gnu")
             .unwrap()
-            .available,
-        true
+            .available
     )
 }
 
@@ -129,22 +300,23 @@ fn test_parse_version() {
     assert_eq!(version, "1.33.0");
     assert_eq!(channel, "nightly");
     assert_eq!(commit, "(9eac38634 2018-12-31)");
-    let commit = Commit::from_str(&commit).unwrap();
+    let commit = Commit::from_str(commit).unwrap();
     assert_eq!(
         commit,
         Commit {
             hash: "9eac38634".to_string(),
-            date: NaiveDate::parse_from_str(&"2018-12-31", "%Y-%m-%d").unwrap(),
+            date: NaiveDate::parse_from_str("2018-12-31", "%Y-%m-%d").unwrap(),
         }
     );
-    let channel = Channel::from_str(&channel).unwrap();
+    let channel = Channel::from_str(channel).unwrap();
     assert_eq!(channel, Channel::Nightly);
-    let ver = Version::from_str(&s).unwrap();
+    let ver = Version::from_str(s).unwrap();
     assert_eq!(
         ver,
         Version {
             version: version.to_string(),
             channel,
+            build: None,
             commit
         }
     );
@@ -174,3 +346,3718 @@ fn test_parse_active_toolchain() {
     assert_eq!(&components[0], "rust-src");
     assert_eq!(&components[1], "rustfmt");
 }
+
+fn diff(name: &str, from: &str, to: &str) -> ComponentDiff {
+    ComponentDiff {
+        name: name.to_string(),
+        from: Version::from_str(from).unwrap(),
+        to: Version::from_str(to).unwrap(),
+    }
+}
+
+#[test]
+fn test_args_format() {
+    let args = |v: &[&str]| Args::parse(v.iter().map(|s| s.to_string()));
+    assert_eq!(args(&[]).unwrap().format, Format::Text);
+    assert_eq!(
+        args(&["--format=markdown"]).unwrap().format,
+        Format::Markdown
+    );
+    assert_eq!(args(&["--format", "md"]).unwrap().format, Format::Markdown);
+    assert!(args(&["--format=html"]).is_err());
+    assert!(args(&["--format"]).is_err());
+    assert!(args(&["--unknown"]).is_err());
+}
+
+#[test]
+fn test_markdown() {
+    assert_eq!(
+        format::markdown(&CheckResult::UpToDate),
+        "### Current version is up to date\n"
+    );
+    let update = CheckResult::Update {
+        date: "2019-01-13".to_string(),
+        updates: vec![diff(
+            "rustc",
+            "1.33.0-nightly (9eac38634 2018-12-31)",
+            "1.33.0-nightly (2442823ef 2019-01-12)",
+        )],
+    };
+    assert_eq!(
+        format::markdown(&update),
+        "### Use `rustup update` (new version from 2019-01-13)
+
+| component | from | to |
+| --- | --- | --- |
+| rustc | 1.33.0 (9eac38634 2018-12-31) | 1.33.0 (2442823ef 2019-01-12) |
+"
+    );
+    let switch = CheckResult::Switch {
+        channel: "nightly".to_string(),
+        date: "2019-01-10".to_string(),
+        updates: vec![
+            diff(
+                "cargo",
+                "1.33.0-nightly (2cf1f5dda 2018-12-29)",
+                "1.33.0-nightly (907c0febe 2019-01-09)",
+            ),
+            diff(
+                "rustc",
+                "1.33.0-nightly (9eac38634 2018-12-31)",
+                "1.33.0-nightly (d22fa2d87 2019-01-08)",
+            ),
+        ],
+        components: vec!["rls".to_string(), "rust-src".to_string()],
+    };
+    assert_eq!(
+        format::markdown(&switch),
+        "### Use `rustup default nightly-2019-01-10`
+
+Then `rustup component add rls rust-src`
+
+| component | from | to |
+| --- | --- | --- |
+| cargo | 1.33.0 (2cf1f5dda 2018-12-29) | 1.33.0 (907c0febe 2019-01-09) |
+| rustc | 1.33.0 (9eac38634 2018-12-31) | 1.33.0 (d22fa2d87 2019-01-08) |
+"
+    );
+}
+
+fn fixture_path(name: &str) -> String {
+    format!(
+        "{}/tests/fixtures/{}.toml",
+        env!("CARGO_MANIFEST_DIR"),
+        name
+    )
+}
+
+fn fixture(name: &str) -> Manifest {
+    Manifest::from_file(fixture_path(name)).unwrap()
+}
+
+#[test]
+fn test_unknown_fields() {
+    let manifest = fixture("unknown-fields");
+    let info = manifest
+        .pkg_for_target("rust", "x86_64-unknown-linux-gnu")
+        .unwrap();
+    assert!(info.available);
+    assert!(info.xz_url.unwrap().ends_with(".tar.xz"));
+    assert!(manifest.pkg.get("rust-analyzer").unwrap().version.is_none());
+    assert_eq!(manifest.renames.get("rls").unwrap().to, "rls-preview");
+}
+
+#[test]
+fn test_parse_interval() {
+    use std::time::Duration;
+    assert_eq!(parse_interval("90"), Ok(Duration::from_secs(90)));
+    assert_eq!(parse_interval("30s"), Ok(Duration::from_secs(30)));
+    assert_eq!(parse_interval("15m"), Ok(Duration::from_secs(900)));
+    assert_eq!(parse_interval("2h"), Ok(Duration::from_secs(7200)));
+    assert!(parse_interval("0").is_err());
+    assert!(parse_interval("m").is_err());
+    assert!(parse_interval("1d").is_err());
+    let args = Args::parse(vec!["--watch".to_string(), "10m".to_string()]).unwrap();
+    assert_eq!(args.watch, Some(Duration::from_secs(600)));
+}
+
+#[test]
+fn test_cache() {
+    let dir = std::env::temp_dir().join(format!("rustupscheck-cache-{}", std::process::id()));
+    let cache = cache::Cache::new(dir.clone());
+    assert_eq!(cache.get("2019-01-01", "nightly"), None);
+    cache.put("2019-01-01", "nightly", "contents").unwrap();
+    assert_eq!(
+        cache.get("2019-01-01", "nightly"),
+        Some("contents".to_string())
+    );
+    assert_eq!(cache.get("2019-01-01", "beta"), None);
+
+    let no_cache = cache.clone().without_read();
+    assert_eq!(no_cache.get("2019-01-01", "nightly"), None);
+    no_cache.put("2019-01-02", "nightly", "fresh").unwrap();
+    assert_eq!(
+        cache.get("2019-01-02", "nightly"),
+        Some("fresh".to_string())
+    );
+    let read_only = cache.clone().without_write();
+    read_only.put("2019-01-03", "nightly", "dropped").unwrap();
+    assert_eq!(read_only.get("2019-01-03", "nightly"), None);
+    assert_eq!(
+        read_only.get("2019-01-01", "nightly"),
+        Some("contents".to_string())
+    );
+
+    assert_eq!(cache.clear(), Ok(2));
+    assert!(!dir.exists());
+    assert_eq!(cache.clear(), Ok(0));
+}
+
+#[test]
+fn test_path_template() {
+    assert_eq!(
+        expand_path_template(PATH_TEMPLATE, "2019-01-01", "nightly"),
+        Ok("/dist/2019-01-01/channel-rust-nightly.toml".to_string())
+    );
+    assert_eq!(
+        expand_path_template(
+            "/rust/{channel}/{date}/{channel}.toml",
+            "2019-01-01",
+            "beta"
+        ),
+        Ok("/rust/beta/2019-01-01/beta.toml".to_string())
+    );
+    assert!(expand_path_template("/dist/{date}/channel-rust.toml", "2019-01-01", "beta").is_err());
+    assert!(
+        expand_path_template("/dist/channel-rust-{channel}.toml", "2019-01-01", "beta").is_err()
+    );
+    assert!(expand_path_template("", "2019-01-01", "beta").is_err());
+}
+
+#[test]
+fn test_cache_source() {
+    let dir = std::env::temp_dir().join(format!("rustupscheck-source-{}", std::process::id()));
+    let official = cache::Cache::new(dir.clone());
+    let mirror = official
+        .clone()
+        .for_source("https://mirror.internal/rust{date}");
+    let other = official
+        .clone()
+        .for_source("https://mirror.internal/rust{channel}");
+    official.put("2019-01-01", "nightly", "official").unwrap();
+    mirror.put("2019-01-01", "nightly", "mirror").unwrap();
+    assert_eq!(
+        official.get("2019-01-01", "nightly"),
+        Some("official".to_string())
+    );
+    assert_eq!(
+        mirror.get("2019-01-01", "nightly"),
+        Some("mirror".to_string())
+    );
+    assert_eq!(other.get("2019-01-01", "nightly"), None);
+    mirror.put_missing("2019-01-02", "nightly").unwrap();
+    assert!(!official.is_missing("2019-01-02", "nightly"));
+    assert_eq!(official.clear(), Ok(3));
+}
+
+#[test]
+fn test_is_up_to_date() {
+    let nightly = |date: &str, version: &str| {
+        mock_manifest(
+            date,
+            &[
+                ("rust", version, true),
+                ("rustc", version, true),
+                ("cargo", version, true),
+            ],
+        )
+    };
+    let installed = nightly("2019-01-02", "1.33.0-nightly (9eac38634 2019-01-01)");
+    let source = MockSource::default()
+        .with(installed.clone())
+        .with(nightly(
+            "2019-01-03",
+            "1.33.0-nightly (c2d381d39 2019-01-02)",
+        ))
+        .with(nightly(
+            "2019-01-04",
+            "1.34.0-nightly (b43986184 2019-01-03)",
+        ));
+    let toolchain = mock_toolchain(installed, &["rustc", "cargo"]);
+    for (date, up_to_date) in &[
+        ("2019-01-02", true),
+        ("2019-01-03", false),
+        ("2019-01-04", false),
+    ] {
+        let rust = mock_rust(toolchain.clone(), source.clone(), date, 1);
+        assert_eq!(rust.is_up_to_date(), *up_to_date, "{}", date);
+        match rust.check_result() {
+            Some(CheckResult::UpToDate) => assert!(up_to_date),
+            Some(CheckResult::Switch { updates, .. }) => assert_eq!(updates.len(), 2),
+            result => panic!("unexpected {:?}", result),
+        }
+    }
+}
+
+#[test]
+fn test_anchor() {
+    use chrono::{FixedOffset, Utc};
+    // 01:00 on Jan 2nd in UTC+14 is still Jan 1st in UTC.
+    let kiritimati = FixedOffset::east(14 * 3600)
+        .ymd(2019, 1, 2)
+        .and_hms(1, 0, 0);
+    assert_eq!(
+        Anchor::Local.date(&kiritimati),
+        NaiveDate::from_ymd(2019, 1, 2)
+    );
+    assert_eq!(
+        Anchor::Utc.date(&kiritimati),
+        NaiveDate::from_ymd(2019, 1, 1)
+    );
+    // 23:00 on Feb 28th in UTC-5 of a leap year is already Feb 29th in UTC.
+    let new_york = FixedOffset::west(5 * 3600)
+        .ymd(2020, 2, 28)
+        .and_hms(23, 0, 0);
+    assert_eq!(
+        Anchor::Local.date(&new_york),
+        NaiveDate::from_ymd(2020, 2, 28)
+    );
+    assert_eq!(
+        Anchor::Utc.date(&new_york),
+        NaiveDate::from_ymd(2020, 2, 29)
+    );
+    let utc = Utc.ymd(2019, 1, 1).and_hms(12, 0, 0);
+    assert_eq!(Anchor::Local.date(&utc), Anchor::Utc.date(&utc));
+
+    let installed = mock_manifest(
+        "2020-02-28",
+        &[("rust", "1.43.0-nightly (0eb878d2a 2020-02-28)", true)],
+    );
+    let source = MockSource::default().with(installed.clone());
+    let mut rust = mock_rust(mock_toolchain(installed, &[]), source, "2020-02-29", -1);
+    rust.today = Anchor::Utc.date(&new_york);
+    let dates: Vec<String> = rust.take(2).map(|r| r.date_str()).collect();
+    assert_eq!(dates, vec!["2020-02-29", "2020-02-28"]);
+    let args = Args::parse(vec!["--utc".to_string()]).unwrap();
+    assert_eq!(args.anchor, Anchor::Utc);
+}
+
+#[test]
+fn test_available_targets() {
+    let manifest = fixture("targets");
+    assert_eq!(
+        manifest.pkg.get("rust-std").unwrap().available_targets(),
+        vec![
+            "aarch64-apple-ios",
+            "wasm32-unknown-unknown",
+            "x86_64-unknown-linux-gnu"
+        ]
+    );
+    assert!(manifest
+        .pkg
+        .get("rust-src")
+        .unwrap()
+        .available_targets()
+        .is_empty());
+}
+
+#[test]
+fn test_json_lines() {
+    let version = "1.33.0-nightly (9eac38634 2018-12-31)";
+    let installed = mock_manifest(
+        "2018-12-31",
+        &[
+            ("rust", "1.33.0-nightly (a8a2a887d 2018-12-30)", true),
+            ("rustfmt", "1.0.1-nightly (be135599 2018-12-30)", true),
+        ],
+    );
+    let source = MockSource::default()
+        .with(mock_manifest(
+            "2019-01-03",
+            &[
+                ("rust", "1.33.0-nightly (2442823ef 2019-01-02)", true),
+                ("rustfmt", "1.0.1-nightly (be135599 2019-01-02)", false),
+            ],
+        ))
+        .with(mock_manifest(
+            "2019-01-01",
+            &[
+                ("rust", version, true),
+                ("rustfmt", "1.0.1-nightly (be135599 2018-12-31)", true),
+            ],
+        ));
+    let rust = mock_rust(
+        mock_toolchain(installed, &["rustfmt"]),
+        source,
+        "2019-01-03",
+        -1,
+    );
+    let mut lines = Vec::new();
+    let report = rust
+        .report_with(30, &mut |r| lines.push(format::json_line(&r.probe())))
+        .unwrap();
+    lines.push(report.render(Format::JsonLines).trim_end().to_string());
+    assert_eq!(
+        lines,
+        vec![
+            r#"{"date":"2019-01-03","found":true,"missing":["rustfmt"],"version":"1.33.0-nightly (2442823ef 2019-01-02)"}"#,
+            r#"{"date":"2019-01-02","found":false,"missing":[],"version":null}"#,
+            r#"{"date":"2019-01-01","found":true,"missing":[],"version":"1.33.0-nightly (9eac38634 2018-12-31)"}"#,
+            r#"{"toolchain":"nightly-x86_64-unknown-linux-gnu","installed":"1.33.0-nightly (a8a2a887d 2018-12-30)","installed_components":["rustfmt"],"candidate":"2019-01-01","missing":[],"rationale":{"kind":"newest-incomplete","newest":"2019-01-03","missing":["rustfmt"],"date":"2019-01-01","days_back":2},"result":"switch","channel":"nightly","date":"2019-01-01","updates":[{"name":"rustfmt","from":"1.0.1-nightly (be135599 2018-12-30)","to":"1.0.1-nightly (be135599 2018-12-31)"}],"components":["rustfmt"]}"#,
+        ]
+    );
+    for line in &lines {
+        assert!(serde_json::from_str::<serde_json::Value>(line).is_ok());
+    }
+}
+
+#[test]
+fn test_trace() {
+    let installed = mock_manifest(
+        "2019-01-01",
+        &[
+            ("rust", "1.33.0-nightly (9eac38634 2018-12-31)", true),
+            ("miri", "0.1.0-nightly (d61d4e3 2018-12-31)", true),
+        ],
+    );
+    let source = MockSource::default()
+        .with(mock_manifest(
+            "2019-01-04",
+            &[
+                ("rust", "1.33.0-nightly (f381a9625 2019-01-03)", true),
+                ("miri", "0.1.0-nightly (d61d4e3 2019-01-03)", false),
+            ],
+        ))
+        .with(mock_manifest(
+            "2019-01-02",
+            &[("rust", "1.33.0-nightly (c2d381d39 2019-01-01)", true)],
+        ))
+        .with(installed.clone());
+    let rust = mock_rust(
+        mock_toolchain(installed, &["miri"]),
+        source,
+        "2019-01-04",
+        -1,
+    );
+    let (found, trace) = rust.latest_complete_with_trace(30);
+    assert_eq!(found.unwrap().unwrap().date_str(), "2019-01-01");
+    let outcomes: Vec<(&str, bool, Vec<&str>)> = trace
+        .iter()
+        .map(|p| {
+            (
+                p.date.as_str(),
+                p.found,
+                p.missing.iter().map(String::as_str).collect(),
+            )
+        })
+        .collect();
+    assert_eq!(
+        outcomes,
+        vec![
+            ("2019-01-04", true, vec!["miri"]),
+            ("2019-01-03", false, vec![]),
+            ("2019-01-02", true, vec!["miri"]),
+            ("2019-01-01", true, vec![]),
+        ]
+    );
+    assert_eq!(
+        trace.iter().map(format::explain).collect::<Vec<_>>(),
+        vec![
+            "2019-01-04: missing miri",
+            "2019-01-03: not published",
+            "2019-01-02: missing miri",
+            "2019-01-01: complete",
+        ]
+    );
+
+    let (found, trace) = rust.latest_complete_with_trace(2);
+    assert!(found.unwrap().is_none());
+    assert_eq!(trace.len(), 2);
+    assert_eq!(
+        rust.to_report(2).unwrap().result,
+        CheckResult::NotFound {
+            days: 2,
+            newest: Some("2019-01-04".to_string()),
+            missing: unavailable(&["miri"]),
+        }
+    );
+}
+
+#[test]
+fn test_installed_date() {
+    let installed = |date: &str, version: &str| {
+        mock_toolchain(mock_manifest(date, &[("rustc", version, true)]), &[])
+    };
+    let toolchain = installed("2019-01-01", "1.33.0-nightly (9eac38634 2018-12-31)");
+    assert_eq!(
+        toolchain.installed_date(),
+        Some(NaiveDate::from_ymd(2019, 1, 1))
+    );
+    assert_eq!(
+        toolchain.pkg_version("rustc").unwrap().commit.date,
+        NaiveDate::from_ymd(2018, 12, 31)
+    );
+    assert!(toolchain.date_mismatch().is_none());
+    assert!(
+        installed("2019-01-01", "1.33.0-nightly (9eac38634 2019-01-05)")
+            .date_mismatch()
+            .is_some()
+    );
+    assert!(
+        installed("2019-01-20", "1.33.0-nightly (9eac38634 2018-12-31)")
+            .date_mismatch()
+            .is_some()
+    );
+    let mut stable = installed("2019-01-17", "1.32.0 (9fda7c223 2019-01-16)");
+    stable.channel = "stable".to_string();
+    stable.manifest.as_mut().unwrap().date = NaiveDate::from_ymd(2019, 2, 28);
+    assert!(stable.date_mismatch().is_none());
+}
+
+#[test]
+fn test_stable_point_release() {
+    let stable = |date: &str, version: &str| {
+        mock_manifest(date, &[("rust", version, true), ("rustc", version, true)])
+    };
+    let installed = stable("2023-06-01", "1.70.0 (90c541806 2023-05-31)");
+    let mut toolchain = mock_toolchain(installed.clone(), &["rustc"]);
+    toolchain.channel = "stable".to_string();
+    for (latest, update) in &[
+        (installed, None),
+        (
+            stable("2023-07-13", "1.71.0 (8ede3aae2 2023-07-12)"),
+            Some("1.71.0"),
+        ),
+        (
+            stable("2023-08-03", "1.71.1 (eb26296b5 2023-08-03)"),
+            Some("1.71.1"),
+        ),
+        (stable("2023-05-31", "1.9.0 (e4e55a7c4 2016-05-24)"), None),
+    ] {
+        let source = MockSource::default().with_latest("stable", latest.clone());
+        let rust = mock_rust(toolchain.clone(), source, "2023-08-05", -1);
+        match (rust.check_stable().unwrap(), update) {
+            (CheckResult::UpToDate, None) => (),
+            (CheckResult::Update { date, updates }, Some(version)) => {
+                assert_eq!(date, latest.date.format("%Y-%m-%d").to_string());
+                assert_eq!(updates[0].to.version, *version);
+            }
+            (result, _) => panic!("unexpected {:?} for {:?}", result, update),
+        }
+    }
+}
+
+#[test]
+fn test_contains_component() {
+    let mut manifest = mock_manifest(
+        "2019-01-01",
+        &[
+            ("rustc", "1.33.0-nightly (9eac38634 2018-12-31)", true),
+            ("rls-preview", "1.33.0-nightly (9eac38634 2018-12-31)", true),
+            ("miri", "0.1.0-nightly (d61d4e3 2018-12-31)", false),
+        ],
+    );
+    manifest.renames.insert(
+        "rls".to_string(),
+        Rename {
+            to: "rls-preview".to_string(),
+        },
+    );
+    assert!(manifest.contains_component("rustc", TARGET));
+    assert!(manifest.contains_component("rls", TARGET));
+    assert!(manifest.contains_component("rls-preview", TARGET));
+    assert!(!manifest.contains_component("miri", TARGET));
+    assert!(!manifest.contains_component("clippy", TARGET));
+    assert!(!manifest.contains_component("rustc", "x86_64-pc-windows-gnu"));
+}
+
+#[test]
+fn test_skip_docs() {
+    let version = "1.33.0-nightly (9eac38634 2018-12-31)";
+    let installed = mock_manifest(
+        "2019-01-01",
+        &[
+            ("rust", "1.33.0-nightly (a8a2a887d 2018-12-30)", true),
+            ("rust-src", "1.33.0-nightly (a8a2a887d 2018-12-30)", true),
+            ("rust-docs", "1.33.0-nightly (a8a2a887d 2018-12-30)", true),
+        ],
+    );
+    let source = MockSource::default().with(mock_manifest(
+        "2019-01-02",
+        &[
+            ("rust", version, true),
+            ("rust-src", version, true),
+            ("rust-docs", version, false),
+        ],
+    ));
+    let mut rust = mock_rust(
+        mock_toolchain(installed, &["rust-src", "rust-docs"]),
+        source,
+        "2019-01-02",
+        0,
+    );
+    assert_eq!(rust.missing_components(), vec!["rust-docs"]);
+    assert!(!rust.is_complete());
+
+    rust.set_ignored(DOCS_COMPONENTS.iter().map(|c| c.to_string()).collect());
+    assert!(rust.missing_components().is_empty());
+    assert!(rust.is_complete());
+    match rust.check_result() {
+        Some(CheckResult::Update { updates, .. }) => {
+            let names: Vec<&str> = updates.iter().map(|u| u.name.as_str()).collect();
+            assert_eq!(names, vec!["rust-src"]);
+        }
+        result => panic!("unexpected {:?}", result),
+    }
+    let args = Args::parse(vec![
+        "--skip-docs".to_string(),
+        "--ignore=miri,clippy".to_string(),
+        "--ignore".to_string(),
+        "rls".to_string(),
+    ])
+    .unwrap();
+    assert!(args.skip_docs);
+    assert_eq!(args.ignore, vec!["miri", "clippy", "rls"]);
+}
+
+#[test]
+fn test_report() {
+    let version = "1.33.0-nightly (9eac38634 2018-12-31)";
+    let installed = mock_manifest(
+        "2019-01-01",
+        &[
+            ("rust", version, true),
+            ("rustc", version, true),
+            ("rust-src", version, true),
+        ],
+    );
+    let source = MockSource::default().with(installed.clone());
+    let rust = mock_rust(
+        mock_toolchain(installed, &["rustc", "rust-src"]),
+        source,
+        "2019-01-01",
+        -1,
+    );
+    let report = rust.to_report(30).unwrap();
+    assert_eq!(report.result, CheckResult::UpToDate);
+    assert_eq!(report.exit_code(), 0);
+    assert_eq!(report.candidate, Some("2019-01-01".to_string()));
+    let legacy = format!(
+        "{}\n{}\n",
+        rust.toolchain.info(),
+        "Current version is up to date"
+    );
+    assert_eq!(report.render(Format::Text), legacy);
+    assert_eq!(
+        report.render(Format::Text),
+        "Installed: nightly-x86_64-unknown-linux-gnu 1.33.0 (9eac38634 2018-12-31)
+With component: rust-src
+Current version is up to date
+"
+    );
+    assert_eq!(report.render(Format::Summary), "up to date\n");
+    let json: serde_json::Value = serde_json::from_str(&report.render(Format::Json)).unwrap();
+    assert_eq!(json["result"], "up-to-date");
+    assert_eq!(json["installed"], version);
+    assert_eq!(json["installed_components"][0], "rust-src");
+}
+
+#[test]
+fn test_json_compact() {
+    let version = "1.33.0-nightly (9eac38634 2019-01-01)";
+    let manifest = mock_manifest("2019-01-02", &[("rust", version, true)]);
+    let source = MockSource::default().with(manifest.clone());
+    let rust = mock_rust(mock_toolchain(manifest, &[]), source, "2019-01-02", -1);
+    let report = rust.to_report(1).unwrap();
+
+    let pretty = report.render(Format::Json);
+    let compact = report.render(Format::JsonCompact);
+    assert!(pretty.lines().count() > 1);
+    assert_eq!(compact.lines().count(), 1);
+    let parse = |s: &str| serde_json::from_str::<serde_json::Value>(s).unwrap();
+    assert_eq!(parse(&pretty), parse(&compact));
+
+    let reports = vec![report.clone(), report];
+    let all = report::render_all(&reports, Format::JsonCompact);
+    assert_eq!(all.lines().count(), 1);
+    assert_eq!(
+        parse(&all),
+        parse(&report::render_all(&reports, Format::Json))
+    );
+
+    let format = |arg: &str| Args::parse(vec![arg.to_string()]).unwrap().format;
+    assert_eq!(format("--json-compact"), Format::JsonCompact);
+    assert_eq!(format("--json-pretty"), Format::Json);
+    assert_eq!(format("--json"), Format::Json);
+    assert_eq!(format("--format=json-compact"), Format::JsonCompact);
+}
+
+#[test]
+fn test_prelude() {
+    use crate::prelude::*;
+    let manifest: Manifest = fixture("downloads");
+    let version: Option<Version> = manifest.pkg_version("rust");
+    assert_eq!(version.map(|v| v.channel), Some(Channel::Nightly));
+    let options = CheckOptions {
+        source: Some(Rc::new(MockSource::default())),
+        ..CheckOptions::default()
+    };
+    let result: Result<Report, CheckError> = check_toolchain(Some("stage1"), &options);
+    assert!(matches!(result, Err(CheckError::Toolchain(_))));
+}
+
+#[test]
+fn test_bom() {
+    let path = format!(
+        "{}/tests/fixtures/unknown-fields.toml",
+        env!("CARGO_MANIFEST_DIR")
+    );
+    let clean = std::fs::read_to_string(path).unwrap();
+    let manifest = Manifest::from_str(&clean).unwrap();
+    for prefix in &["\u{feff}", "\u{feff}\r\n\n", "  \n\t"] {
+        let prefixed = format!("{}{}", prefix, clean);
+        assert_eq!(Manifest::from_str(&prefixed), Ok(manifest.clone()));
+    }
+}
+
+#[test]
+fn test_all_channels() {
+    let release = |date: &str, version: &str| mock_manifest(date, &[("rust", version, true)]);
+    let source = MockSource::default()
+        .with_latest(
+            "stable",
+            release("2023-08-03", "1.71.1 (eb26296b5 2023-08-03)"),
+        )
+        .with_latest(
+            "beta",
+            release("2023-08-05", "1.72.0-beta.8 (a6e5d3fd6 2023-08-04)"),
+        )
+        .with_latest(
+            "nightly",
+            release("2023-08-06", "1.73.0-nightly (f88a8b71c 2023-08-05)"),
+        );
+    let installed = |channel: &Channel| match channel {
+        Channel::Stable => Ok(Some(release("2023-07-13", "1.71.0 (8ede3aae2 2023-07-12)"))),
+        Channel::Beta => Ok(None),
+        Channel::Nightly => Ok(Some(release(
+            "2023-08-06",
+            "1.73.0-nightly (f88a8b71c 2023-08-05)",
+        ))),
+    };
+    let statuses = all_channels(&source, &installed);
+    assert_eq!(
+        statuses
+            .iter()
+            .map(|s| s.channel.clone())
+            .collect::<Vec<_>>(),
+        Channel::all().to_vec()
+    );
+    assert_eq!(
+        statuses
+            .iter()
+            .map(ChannelStatus::has_update)
+            .collect::<Vec<_>>(),
+        vec![true, false, false]
+    );
+    assert_eq!(
+        statuses.iter().map(format::channel_status).collect::<Vec<_>>(),
+        vec![
+            "stable: 1.71.1 (eb26296b5 2023-08-03) is available (installed 1.71.0 (8ede3aae2 2023-07-12))",
+            "beta: not installed (latest 1.72.0 (a6e5d3fd6 2023-08-04))",
+            "nightly: up to date (1.73.0 (f88a8b71c 2023-08-05))",
+        ]
+    );
+    assert!(statuses.iter().all(|s| s.error.is_none()));
+
+    // Each channel's failure shows on its own line; the others still report.
+    let source = MockSource::default()
+        .with_latest(
+            "stable",
+            release("2023-08-03", "1.71.1 (eb26296b5 2023-08-03)"),
+        )
+        .with_latest(
+            "nightly",
+            mock_manifest(
+                "2023-08-06",
+                &[("cargo", "0.74.0-nightly (7e9de3f4e 2023-08-04)", true)],
+            ),
+        );
+    let installed = |channel: &Channel| match channel {
+        Channel::Stable => Err("Can't read stable: permission denied".to_string()),
+        _ => Ok(None),
+    };
+    let statuses = all_channels(&source, &installed);
+    assert_eq!(statuses[1].latest, None);
+    assert_eq!(
+        statuses
+            .iter()
+            .map(format::channel_status)
+            .collect::<Vec<_>>(),
+        vec![
+            "stable: check failed: installed toolchain: Can't read stable: permission denied",
+            "beta: check failed: latest release: beta not found",
+            "nightly: latest release not found",
+        ]
+    );
+}
+
+#[test]
+fn test_from_reader() {
+    use std::io::Cursor;
+    let contents = std::fs::read(fixture_path("targets")).unwrap();
+    let manifest = Manifest::from_reader(Cursor::new(contents.clone())).unwrap();
+    assert_eq!(manifest, fixture("targets"));
+    let v1 = String::from_utf8(contents)
+        .unwrap()
+        .replace("manifest-version = \"2\"", "manifest-version = \"1\"");
+    assert_eq!(
+        Manifest::from_reader(Cursor::new(v1.into_bytes())),
+        Err("unsupported manifest version 1".to_string())
+    );
+    assert!(Manifest::from_reader(Cursor::new(vec![0xff, 0xfe])).is_err());
+    assert!(Manifest::from_file(fixture_path("missing")).is_err());
+}
+
+#[test]
+fn test_target_unavailable() {
+    let manifest = fixture("targets");
+    assert!(manifest.has_target(TARGET));
+    assert!(manifest.has_target("sparc64-unknown-linux-gnu"));
+    assert!(!manifest.has_target("i686-unknown-haiku"));
+
+    let mut toolchain = mock_toolchain(manifest.clone(), &["rust-std", "rust-src"]);
+    toolchain.target = "i686-unknown-haiku".to_string();
+    let rust = mock_rust(
+        toolchain,
+        MockSource::default().with(manifest),
+        "2019-01-01",
+        -1,
+    );
+    let report = rust
+        .report_with(3, &mut |r| {
+            let probe = r.probe();
+            if probe.found {
+                assert!(!probe.target_available);
+                assert!(probe.missing.is_empty());
+                assert_eq!(format::explain(&probe), "2019-01-01: target not published");
+            }
+        })
+        .unwrap();
+    assert_eq!(
+        report.result,
+        CheckResult::TargetUnavailable {
+            target: "i686-unknown-haiku".to_string(),
+            channel: "nightly".to_string(),
+            date: "2019-01-01".to_string(),
+        }
+    );
+    assert!(report.missing.is_empty());
+    assert_eq!(report.exit_code(), 20);
+    assert_eq!(
+        report.render(Format::Summary),
+        "target i686-unknown-haiku is not available on 2019-01-01's nightly\n"
+    );
+}
+
+#[test]
+fn test_components_add_only() {
+    let installed = mock_manifest(
+        "2018-12-31",
+        &[
+            ("rust", "1.33.0-nightly (a8a2a887d 2018-12-30)", true),
+            ("rustc", "1.33.0-nightly (a8a2a887d 2018-12-30)", true),
+            ("rustfmt", "1.0.1-nightly (be135599 2018-12-30)", true),
+            ("rls-preview", "1.31.6-nightly (ae0d89a08 2018-12-30)", true),
+            ("clippy", "0.0.212-nightly (1b89724b 2018-12-30)", true),
+        ],
+    );
+    let mut candidate = mock_manifest(
+        "2019-01-01",
+        &[
+            ("rust", "1.33.0-nightly (9eac38634 2018-12-31)", true),
+            ("rustc", "1.33.0-nightly (9eac38634 2018-12-31)", true),
+            ("rustfmt", "1.0.1-nightly (be135599 2018-12-31)", true),
+            ("rls", "1.31.6-nightly (ae0d89a08 2018-12-31)", true),
+            ("clippy", "0.0.212-nightly (1b89724b 2018-12-31)", true),
+        ],
+    );
+    candidate.renames.insert(
+        "rls-preview".to_string(),
+        Rename {
+            to: "rls".to_string(),
+        },
+    );
+    let rust = mock_rust(
+        mock_toolchain(installed, &["rustc", "rustfmt", "rls-preview", "clippy"]),
+        MockSource::default().with(candidate),
+        "2019-01-03",
+        -1,
+    );
+    assert_eq!(
+        Args::parse(vec!["--components-add-only".to_string()])
+            .unwrap()
+            .format,
+        Format::ComponentsAdd
+    );
+    let report = rust.to_report(5).unwrap();
+    assert_eq!(
+        report.render(Format::ComponentsAdd),
+        "rustup component add --toolchain nightly-2019-01-01-x86_64-unknown-linux-gnu \
+         clippy rls rustfmt\n"
+    );
+    assert_eq!(format::component_add("nightly", &[]), None);
+}
+
+#[test]
+fn test_missing_local_manifest() {
+    let dir = std::env::temp_dir().join(format!("rustupscheck-toolchain-{}", std::process::id()));
+    std::fs::create_dir_all(dir.join("lib/rustlib")).unwrap();
+    std::fs::write(
+        dir.join(COMPONENTS_FILE),
+        format!("cargo-{0}\nrustc-{0}\nrustfmt-{0}\nrust-src\n", TARGET),
+    )
+    .unwrap();
+    let toolchain = Toolchain::from_dir("nightly".to_string(), TARGET.to_string(), &dir).unwrap();
+    std::fs::remove_dir_all(&dir).unwrap();
+    assert!(toolchain.manifest.is_none());
+    assert_eq!(toolchain.component_list(), vec!["rust-src", "rustfmt"]);
+    assert!(toolchain.components.iter().all(|c| c.version.is_none()));
+    assert_eq!(toolchain.pkg_version("rustfmt"), None);
+    assert_eq!(
+        toolchain.info().lines().last(),
+        Some("With components: rust-src, rustfmt")
+    );
+
+    let verbose = "rustc 1.33.0-nightly (9eac38634 2018-12-31)
+binary: rustc
+commit-hash: 9eac38634cd6e8e4b5c2f1a1f7e0c3c1a0c8f6d2
+commit-date: 2018-12-31
+host: x86_64-unknown-linux-gnu
+release: 1.33.0-nightly
+LLVM version: 8.0
+";
+    let rustc = Version::parse_rustc_verbose(verbose);
+    assert_eq!(rustc, "1.33.0-nightly (9eac38634 2018-12-31)".parse().ok());
+    assert_eq!(Version::parse_rustc_verbose("rustc 1.33.0-nightly\n"), None);
+    let toolchain = Toolchain { rustc, ..toolchain };
+    assert_eq!(toolchain.pkg_version("rust"), toolchain.rustc);
+    assert_eq!(
+        toolchain.installed_date(),
+        Some(NaiveDate::from_ymd(2018, 12, 31))
+    );
+    assert!(toolchain
+        .info()
+        .starts_with("Installed: nightly-x86_64-unknown-linux-gnu 1.33.0 (9eac38634 2018-12-31)"));
+}
+
+#[test]
+fn test_malformed_local_manifest() {
+    let dir = std::env::temp_dir().join(format!("rustupscheck-malformed-{}", std::process::id()));
+    std::fs::create_dir_all(dir.join("lib/rustlib")).unwrap();
+    std::fs::write(dir.join(COMPONENTS_FILE), format!("rustc-{}\n", TARGET)).unwrap();
+    std::fs::write(dir.join(MANIFEST_FILE), "manifest-version = \"2\"\n[pkg").unwrap();
+    let result = Toolchain::from_dir("nightly".to_string(), TARGET.to_string(), &dir);
+    std::fs::remove_dir_all(&dir).unwrap();
+    // Not mistaken for an install without a manifest.
+    let error = result.unwrap_err();
+    assert!(
+        error.starts_with(&format!("Can't read {}", dir.join(MANIFEST_FILE).display())),
+        "{}",
+        error
+    );
+}
+
+#[test]
+fn test_newer_than() {
+    let version = "1.33.0-nightly (9eac38634 2018-12-31)";
+    let base = mock_manifest("2019-01-01", &[("rust", version, true)]);
+    let later = mock_manifest("2019-01-02", &[("rust", version, true)]);
+    assert!(later.newer_than(&base));
+    assert!(!base.newer_than(&later));
+    assert!(!base.newer_than(&base));
+    assert!(!later.rust_version_newer_than(&base));
+
+    let rebuilt = mock_manifest(
+        "2019-01-01",
+        &[("rust", "1.33.0-nightly (2442823ef 2019-01-01)", true)],
+    );
+    assert!(rebuilt.rust_version_newer_than(&base));
+    assert!(!base.rust_version_newer_than(&rebuilt));
+    assert!(!rebuilt.newer_than(&base));
+}
+
+#[test]
+fn test_output() {
+    let args = Args::parse(vec!["--output".to_string(), "report.json".to_string()]).unwrap();
+    assert_eq!(args.output, Some(std::path::PathBuf::from("report.json")));
+    let path = std::env::temp_dir().join(format!("rustupscheck-output-{}", std::process::id()));
+    write_output(Some(&path), "first\n").unwrap();
+    write_output(Some(&path), "up to date\n").unwrap();
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), "up to date\n");
+    std::fs::remove_file(&path).unwrap();
+    let missing = path.join("report.txt");
+    assert!(write_output(Some(&missing), "up to date\n")
+        .unwrap_err()
+        .starts_with("Can't write "));
+}
+
+#[test]
+fn test_clock_skew() {
+    let latest = mock_manifest(
+        "2019-01-01",
+        &[("rust", "1.33.0-nightly (9eac38634 2018-12-31)", true)],
+    );
+    let source = MockSource::default().with_latest("nightly", latest.clone());
+    let at = |today: &str| {
+        mock_rust(
+            mock_toolchain(latest.clone(), &[]),
+            source.clone(),
+            today,
+            -1,
+        )
+    };
+    assert_eq!(at("2019-01-01").clock_skew(), None);
+    assert_eq!(at("2019-01-03").clock_skew(), None);
+    assert_eq!(
+        at("2019-01-10").clock_skew(),
+        Some(
+            "System date 2019-01-10 is 9 days past the latest nightly (2019-01-01); \
+             the clock may be wrong"
+                .to_string()
+        )
+    );
+    let offline = mock_rust(
+        mock_toolchain(latest.clone(), &[]),
+        MockSource::default(),
+        "2019-01-10",
+        -1,
+    );
+    assert_eq!(offline.clock_skew(), None);
+}
+
+#[test]
+fn test_check_toolchain() {
+    let home = std::env::temp_dir().join(format!("rustupscheck-home-{}", std::process::id()));
+    let today = Anchor::Local.today();
+    let day = |n: i64| (today - Duration::days(n)).format("%Y-%m-%d").to_string();
+    // A nightly published `n` days ago, built from a commit of the day before.
+    let nightly = |n: i64, hash: &str, rustfmt: bool| {
+        let rust = format!("1.33.0-nightly ({} {})", hash, day(n + 1));
+        let fmt = format!("1.0.1-nightly (be135599 {})", day(n + 1));
+        mock_manifest_toml(
+            &day(n),
+            &[
+                ("rust", &rust, true),
+                ("rustc", &rust, true),
+                ("rustfmt", &fmt, rustfmt),
+            ],
+        )
+    };
+    let installed = nightly(3, "9eac38634", true);
+    for name in &["nightly", "stable"] {
+        let dir = home.join("toolchains").join(format!("{}-{}", name, TARGET));
+        std::fs::create_dir_all(dir.join("lib/rustlib")).unwrap();
+        std::fs::write(dir.join(MANIFEST_FILE), &installed).unwrap();
+        std::fs::write(
+            dir.join(COMPONENTS_FILE),
+            format!("rustc-{0}\nrustfmt-{0}\n", TARGET),
+        )
+        .unwrap();
+    }
+    let source = |manifests: &[String]| {
+        manifests.iter().fold(MockSource::default(), |source, m| {
+            source.with(m.parse().unwrap())
+        })
+    };
+    let check = |name: &str, source: MockSource| {
+        let opts = CheckOptions {
+            rustup_home: Some(home.clone()),
+            source: Some(Rc::new(source)),
+            max_days: 5,
+            ..CheckOptions::default()
+        };
+        check_toolchain(Some(&format!("{}-{}", name, TARGET)), &opts).map(|r| r.result)
+    };
+
+    let rebuilt = installed.replace(&day(3), &day(0));
+    assert_eq!(
+        check("nightly", source(&[rebuilt])),
+        Ok(CheckResult::UpToDate)
+    );
+
+    match check("nightly", source(&[nightly(0, "2442823ef", true)])) {
+        Ok(CheckResult::Update { date, updates }) => {
+            assert_eq!(date, day(0));
+            assert_eq!(updates.len(), 2);
+        }
+        other => panic!("unexpected {:?}", other),
+    }
+
+    let switch = source(&[
+        nightly(0, "2442823ef", false),
+        nightly(1, "c2d381d39", true),
+    ]);
+    match check("nightly", switch) {
+        Ok(CheckResult::Switch {
+            date, components, ..
+        }) => {
+            assert_eq!(date, day(1));
+            assert_eq!(components, vec!["rustfmt"]);
+        }
+        other => panic!("unexpected {:?}", other),
+    }
+
+    assert_eq!(
+        check("nightly", MockSource::default()),
+        Ok(CheckResult::NotFound {
+            days: 5,
+            newest: None,
+            missing: Vec::new(),
+        })
+    );
+    assert_eq!(
+        check("stable", MockSource::default()),
+        Err(CheckError::Fetch("stable not found".to_string()))
+    );
+    assert!(matches!(
+        check("beta", MockSource::default()),
+        Err(CheckError::Toolchain(_))
+    ));
+    assert_eq!(
+        check_toolchain(Some("nightly"), &CheckOptions::default()),
+        Err(CheckError::Toolchain(
+            "Toolchain nightly has no target".to_string()
+        ))
+    );
+    std::fs::remove_dir_all(&home).unwrap();
+}
+
+#[test]
+fn test_component_only_update() {
+    let rust = "1.33.0-nightly (9eac38634 2018-12-31)";
+    let nightly = |date: &str, clippy: &str| {
+        mock_manifest(
+            date,
+            &[
+                ("rust", rust, true),
+                ("rustc", rust, true),
+                ("clippy", clippy, true),
+            ],
+        )
+    };
+    let installed = nightly("2019-01-01", "0.0.212-nightly (1b89724b 2018-12-30)");
+    let rebuilt = nightly("2019-01-02", "0.0.212-nightly (2e26fdc2 2019-01-01)");
+    let toolchain = mock_toolchain(installed.clone(), &["rustc", "clippy"]);
+
+    let same = mock_rust(
+        toolchain.clone(),
+        MockSource::default().with(installed),
+        "2019-01-01",
+        0,
+    );
+    assert!(same.is_up_to_date());
+
+    let rust = mock_rust(
+        toolchain,
+        MockSource::default().with(rebuilt),
+        "2019-01-02",
+        0,
+    );
+    assert!(!rust.is_up_to_date());
+    match rust.check_result() {
+        Some(CheckResult::Update { updates, .. }) => {
+            assert_eq!(
+                updates.iter().map(|d| d.name.as_str()).collect::<Vec<_>>(),
+                vec!["clippy"]
+            );
+        }
+        result => panic!("unexpected {:?}", result),
+    }
+}
+
+#[test]
+fn test_target_from_rustc() {
+    let args = Args::parse(vec!["--target-from-rustc".to_string()]).unwrap();
+    assert!(args.target_from_rustc);
+    let verbose = "rustc 1.32.0 (9fda7c223 2019-01-16)
+binary: rustc
+commit-hash: 9fda7c2237db910e41d6a712e9a2139b352e558b
+commit-date: 2019-01-16
+host: x86_64-pc-windows-msvc
+release: 1.32.0
+LLVM version: 8.0
+";
+    assert_eq!(
+        rustc_host(verbose),
+        Some("x86_64-pc-windows-msvc".to_string())
+    );
+    assert_eq!(rustc_host("release: 1.32.0\n"), None);
+
+    let dir = std::env::temp_dir().join(format!("rustupscheck-rustc-{}", std::process::id()));
+    std::fs::create_dir_all(dir.join("lib/rustlib")).unwrap();
+    std::fs::write(
+        dir.join(COMPONENTS_FILE),
+        "rustc-x86_64-pc-windows-msvc\nclippy-x86_64-pc-windows-msvc\n",
+    )
+    .unwrap();
+    let mut requested = String::new();
+    let toolchain = Toolchain::from_rustc_verbose(verbose, |name| {
+        requested = name.to_string();
+        Ok(dir.clone())
+    })
+    .unwrap();
+    std::fs::remove_dir_all(&dir).unwrap();
+    assert_eq!(requested, "stable-x86_64-pc-windows-msvc");
+    assert_eq!(toolchain.channel, "stable");
+    assert_eq!(toolchain.target, "x86_64-pc-windows-msvc");
+    assert_eq!(toolchain.component_list(), vec!["clippy"]);
+    assert_eq!(
+        toolchain.pkg_version("rust"),
+        "1.32.0 (9fda7c223 2019-01-16)".parse().ok()
+    );
+    assert!(Toolchain::from_rustc_verbose("release: 1.32.0\n", |_| Ok(dir.clone())).is_err());
+}
+
+#[test]
+fn test_sorted_components() {
+    let version = "1.33.0-nightly (9eac38634 2018-12-31)";
+    let manifest = mock_manifest(
+        "2019-01-01",
+        &[
+            ("rust", version, true),
+            ("rustfmt", "1.0.1-nightly (be135599 2018-12-31)", true),
+            ("clippy", "0.0.212-nightly (1b89724b 2018-12-31)", true),
+            ("miri", "0.1.0-nightly (d61d4e3 2018-12-31)", true),
+        ],
+    );
+    let order = |components: &[&str]| {
+        let toolchain = mock_toolchain(manifest.clone(), components);
+        let rust = mock_rust(
+            toolchain.clone(),
+            MockSource::default().with(manifest.clone()),
+            "2019-01-01",
+            1,
+        );
+        (
+            toolchain.component_list(),
+            toolchain.info(),
+            rust.add_components(),
+        )
+    };
+    let sorted = order(&["clippy", "miri", "rustfmt"]);
+    assert_eq!(sorted.0, vec!["clippy", "miri", "rustfmt"]);
+    assert_eq!(order(&["rustfmt", "clippy", "miri"]), sorted);
+    assert_eq!(order(&["miri", "rustfmt", "clippy"]), sorted);
+    assert!(sorted.1.ends_with("With components: clippy, miri, rustfmt"));
+}
+
+#[test]
+fn test_scan_iter() {
+    let version = "1.33.0-nightly (9eac38634 2018-12-31)";
+    let installed = mock_manifest("2019-01-01", &[("rust", version, true)]);
+    let source = MockSource::default()
+        .with(installed.clone())
+        .with(mock_manifest(
+            "2019-01-03",
+            &[("rust", "1.33.0-nightly (2442823ef 2019-01-02)", true)],
+        ));
+    let rust = mock_rust(
+        mock_toolchain(installed.clone(), &[]),
+        source.clone(),
+        "2019-01-04",
+        -1,
+    );
+    let probes: Vec<(String, bool)> = rust
+        .scan_iter(4)
+        .map(|r| r.map(|r| (r.date_str(), r.manifest.is_some())).unwrap())
+        .collect();
+    assert_eq!(
+        probes,
+        vec![
+            ("2019-01-04".to_string(), false),
+            ("2019-01-03".to_string(), true),
+            ("2019-01-02".to_string(), false),
+            ("2019-01-01".to_string(), true),
+        ]
+    );
+    // The iterator is bounded and works on a snapshot.
+    assert_eq!(rust.scan_iter(0).count(), 0);
+    assert_eq!(rust.date_str(), "2019-01-04");
+    assert_eq!(rust.offset, -1);
+    // Snapshots share the installed toolchain instead of copying it.
+    assert!(rust
+        .scan_iter(2)
+        .all(|r| Rc::ptr_eq(&r.unwrap().toolchain, &rust.toolchain)));
+    assert!(rust
+        .clone()
+        .take(2)
+        .all(|r| Rc::ptr_eq(&r.toolchain, &rust.toolchain)));
+
+    let broken = mock_rust(
+        mock_toolchain(installed, &[]),
+        source.failing("2019-01-03"),
+        "2019-01-04",
+        -1,
+    );
+    let results: Vec<Result<String, CheckError>> = broken
+        .scan_iter(3)
+        .map(|r| r.map(|r| r.date_str()))
+        .collect();
+    assert_eq!(
+        results,
+        vec![
+            Ok("2019-01-04".to_string()),
+            Err(CheckError::Fetch("2019-01-03 unreachable".to_string())),
+            Ok("2019-01-02".to_string()),
+        ]
+    );
+    assert_eq!(
+        broken.to_report(3).unwrap_err(),
+        CheckError::Fetch("2019-01-03 unreachable".to_string())
+    );
+}
+
+#[test]
+fn test_rust_unavailable() {
+    let installed = mock_manifest(
+        "2019-01-01",
+        &[
+            ("rust", "1.33.0-nightly (9eac38634 2018-12-31)", true),
+            ("rustfmt", "1.0.1-nightly (be135599 2018-12-31)", true),
+        ],
+    );
+    let broken = mock_manifest(
+        "2019-01-03",
+        &[
+            ("rust", "1.33.0-nightly (2442823ef 2019-01-02)", false),
+            ("rustfmt", "1.0.1-nightly (be135599 2019-01-02)", true),
+        ],
+    );
+    assert!(broken.pkg_version("rust").is_some());
+    let rust = mock_rust(
+        mock_toolchain(installed.clone(), &["rustfmt"]),
+        MockSource::default().with(broken).with(installed),
+        "2019-01-03",
+        -1,
+    );
+    let (found, trace) = rust.latest_complete_with_trace(3);
+    assert_eq!(trace[0].missing, vec!["rust"]);
+    assert_eq!(found.unwrap().unwrap().date_str(), "2019-01-01");
+}
+
+#[test]
+fn test_spinner() {
+    use std::{
+        io::Write,
+        sync::{Arc, Mutex},
+    };
+
+    #[derive(Clone, Default)]
+    struct Shared(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Shared {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    let out = Shared::default();
+    let spinner = progress::Spinner::start_on(out.clone(), "Fetching nightly".to_string());
+    std::thread::sleep(std::time::Duration::from_millis(150));
+    drop(spinner);
+    let written = String::from_utf8(out.0.lock().unwrap().clone()).unwrap();
+    assert!(written.starts_with("\r| Fetching nightly"));
+    assert!(written.ends_with("\r\x1b[K"));
+
+    let args = |v: &[&str]| Args::parse(v.iter().map(|s| s.to_string())).unwrap();
+    assert!(args(&[]).show_progress(true));
+    assert!(!args(&[]).show_progress(false));
+    assert!(!args(&["--no-progress"]).show_progress(true));
+    assert!(!args(&["--json"]).show_progress(true));
+    assert!(!args(&["--summary"]).show_progress(true));
+}
+
+#[test]
+fn test_deserialize_version() {
+    #[derive(Deserialize)]
+    struct Pinned {
+        version: Version,
+    }
+    let pinned: Pinned =
+        toml::from_str("version = \"1.33.0-nightly (9eac38634 2018-12-31)\"").unwrap();
+    assert_eq!(
+        pinned.version,
+        Version::from_str("1.33.0-nightly (9eac38634 2018-12-31)").unwrap()
+    );
+    assert!(toml::from_str::<Pinned>("version = \"0.1.0-nightly\"").is_err());
+    assert!(toml::from_str::<Pinned>("version = \"\"").is_err());
+
+    let json = serde_json::to_string(&pinned.version).unwrap();
+    assert_eq!(
+        serde_json::from_str::<Version>(&json).unwrap(),
+        pinned.version
+    );
+    let beta = Version::from_str("1.72.0-beta.8 (a6e5d3fd6 2023-08-04)").unwrap();
+    assert_eq!(
+        serde_json::to_string(&beta).unwrap(),
+        "\"1.72.0-beta.8 (a6e5d3fd6 2023-08-04)\""
+    );
+
+    // In a manifest, only versions without a commit count as absent.
+    let body = std::fs::read_to_string(fixture_path("downloads")).unwrap();
+    let clippy = "version = \"0.0.212 (2e26fdc2 2019-01-01)\"";
+    assert!(body.contains(clippy));
+    let with = |version: &str| {
+        Manifest::from_str(&body.replace(clippy, &format!("version = \"{}\"", version)))
+    };
+    let manifest = with("0.1.101-nightly").unwrap();
+    assert!(manifest.pkg["clippy-preview"].version.is_none());
+    for commitless in &["0.0.0", "0.0.0-beta.5", "1.11.0-beta.5", "0.1.0-nightly"] {
+        let manifest = with(commitless).unwrap();
+        assert!(
+            manifest.pkg["clippy-preview"].version.is_none(),
+            "{}",
+            commitless
+        );
+    }
+    // Anything else that isn't a full version is an error, not "absent".
+    for garbage in &[
+        "abc",
+        "1.11",
+        "1.11.0.1",
+        "1.11.x",
+        "1.11.0-",
+        "1.11.0-stable",
+        "1.11.0-weekly",
+        "1.11.0-beta.",
+        "1.11.0-beta.x",
+        "1.11.0-nightly.5",
+    ] {
+        assert!(with(garbage).is_err(), "{}", garbage);
+    }
+    assert!(with("0.0.212 (2e26fdc2 2019-13-01)").is_err());
+    assert!(with("0.0.212-weekly (2e26fdc2 2019-01-01)").is_err());
+}
+
+#[test]
+fn test_all_incomplete() {
+    let installed = mock_manifest(
+        "2019-01-01",
+        &[
+            ("rust", "1.33.0-nightly (9eac38634 2018-12-31)", true),
+            ("miri", "0.1.0-nightly (d61d4e3 2018-12-31)", true),
+            ("clippy", "0.0.212-nightly (1b89724b 2018-12-31)", true),
+        ],
+    );
+    let nightly = |date: &str, clippy: bool| {
+        mock_manifest(
+            date,
+            &[
+                ("rust", "1.33.0-nightly (2442823ef 2019-01-02)", true),
+                ("miri", "0.1.0-nightly (d61d4e3 2019-01-02)", false),
+                ("clippy", "0.0.212-nightly (1b89724b 2019-01-02)", clippy),
+            ],
+        )
+    };
+    let source = MockSource::default()
+        .with(nightly("2019-01-05", false))
+        .with(nightly("2019-01-04", true))
+        .with(nightly("2019-01-02", true));
+    let rust = mock_rust(
+        mock_toolchain(installed, &["miri", "clippy"]),
+        source,
+        "2019-01-05",
+        -1,
+    );
+    let report = rust.to_report(4).unwrap();
+    assert_eq!(
+        report.result,
+        CheckResult::NotFound {
+            days: 4,
+            newest: Some("2019-01-05".to_string()),
+            missing: unavailable(&["clippy", "miri"]),
+        }
+    );
+    assert_eq!(report.exit_code(), 20);
+    assert_eq!(
+        format::text(&report.result),
+        "No complete nightly found in the last 4 days
+Newest nightly 2019-01-05 is missing: clippy, miri
+Remove them with \"rustup component remove clippy miri\" or wait for a newer nightly
+"
+    );
+    assert_eq!(
+        format::summary(&report.result),
+        "no complete nightly in the last 4 days (2019-01-05 missing clippy, miri)"
+    );
+    let broken = CheckResult::NotFound {
+        days: 1,
+        newest: Some("2019-01-05".to_string()),
+        missing: unavailable(&["rust"]),
+    };
+    assert!(format::markdown(&broken).ends_with("\n\nWait for a newer nightly\n"));
+}
+
+#[test]
+fn test_pin() {
+    let installed = mock_manifest(
+        "2019-01-01",
+        &[
+            ("rust", "1.33.0-nightly (9eac38634 2018-12-31)", true),
+            ("rustfmt", "1.0.1-nightly (be135599 2018-12-31)", true),
+            ("clippy", "0.0.212-nightly (1b89724b 2018-12-31)", true),
+        ],
+    );
+    let source = MockSource::default()
+        .with(mock_manifest(
+            "2019-01-03",
+            &[
+                ("rust", "1.33.0-nightly (2442823ef 2019-01-02)", true),
+                ("rustfmt", "1.0.1-nightly (be135599 2019-01-02)", false),
+                ("clippy", "0.0.212-nightly (1b89724b 2019-01-02)", true),
+            ],
+        ))
+        .with(mock_manifest(
+            "2019-01-02",
+            &[
+                ("rust", "1.33.0-nightly (c2d381d39 2019-01-01)", true),
+                ("rustfmt", "1.0.1-nightly (be135599 2019-01-01)", true),
+                ("clippy", "0.0.212-nightly (1b89724b 2019-01-01)", true),
+            ],
+        ));
+    let rust = mock_rust(
+        mock_toolchain(installed, &["rustfmt", "clippy"]),
+        source,
+        "2019-01-03",
+        -1,
+    );
+    let pin = rust.to_report(3).unwrap().pin.unwrap();
+    let expected = "[toolchain]
+channel = \"nightly-2019-01-02\"
+components = [\"clippy\", \"rustfmt\"]
+targets = [\"x86_64-unknown-linux-gnu\"]
+";
+    assert_eq!(pin.to_toml().unwrap(), expected);
+
+    let dir = std::env::temp_dir().join(format!("rustupscheck-pin-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(
+        dir.join(pin::PIN_FILE),
+        "[toolchain]\nchannel = \"stable\"\n",
+    )
+    .unwrap();
+    assert!(pin.write(&dir, false).is_err());
+    let path = pin.write(&dir, true).unwrap();
+    assert_eq!(std::fs::read_to_string(path).unwrap(), expected);
+    assert_eq!(
+        std::fs::read_to_string(dir.join("rust-toolchain.toml.bak")).unwrap(),
+        "[toolchain]\nchannel = \"stable\"\n"
+    );
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_components_with_versions() {
+    let dir = std::env::temp_dir().join(format!("rustupscheck-inventory-{}", std::process::id()));
+    std::fs::create_dir_all(dir.join("lib/rustlib")).unwrap();
+    std::fs::copy(fixture_path("targets"), dir.join(MANIFEST_FILE)).unwrap();
+    std::fs::write(
+        dir.join(COMPONENTS_FILE),
+        format!("rust-std-{0}\nrust-src\nrustfmt-{0}\n", TARGET),
+    )
+    .unwrap();
+    let toolchain = Toolchain::from_dir("nightly".to_string(), TARGET.to_string(), &dir).unwrap();
+    std::fs::remove_dir_all(&dir).unwrap();
+    let version: Option<Version> = "1.33.0-nightly (9eac38634 2018-12-31)".parse().ok();
+    assert_eq!(
+        toolchain.components_with_versions(),
+        vec![
+            ("rust-src".to_string(), version.clone()),
+            ("rust-std".to_string(), version),
+            ("rustfmt".to_string(), None),
+        ]
+    );
+    let info = toolchain.info_verbose();
+    let lines: Vec<&str> = info.lines().skip(1).collect();
+    assert_eq!(
+        lines,
+        vec![
+            "With components:",
+            "  rust-src 1.33.0 (9eac38634 2018-12-31)",
+            "  rust-std 1.33.0 (9eac38634 2018-12-31)",
+            "  rustfmt (unknown version)",
+        ]
+    );
+    assert_eq!(
+        toolchain.info().lines().last(),
+        Some("With components: rust-src, rust-std, rustfmt")
+    );
+    assert!(Args::parse(vec!["--verbose".to_string()])
+        .unwrap()
+        .verbose());
+}
+
+#[test]
+fn test_missing_cache() {
+    let dir = std::env::temp_dir().join(format!("rustupscheck-missing-{}", std::process::id()));
+    let cache = cache::Cache::new(dir.clone());
+    assert!(!cache.is_missing("2019-01-01", "nightly"));
+    cache.put_missing("2019-01-01", "nightly").unwrap();
+    assert!(cache.is_missing("2019-01-01", "nightly"));
+    assert!(!cache.is_missing("2019-01-01", "beta"));
+    assert!(!cache
+        .clone()
+        .without_read()
+        .is_missing("2019-01-01", "nightly"));
+    let expired = cache
+        .clone()
+        .with_missing_ttl(std::time::Duration::from_secs(0));
+    assert!(!expired.is_missing("2019-01-01", "nightly"));
+
+    // 2019-01-01 was published, so only the remembered miss can answer None
+    // here, and it does so without going to the network.
+    let source = Dist::with_cache(cache.clone());
+    assert_eq!(source.manifest("2019-01-01", "nightly"), Ok(None));
+    assert_eq!(cache.clear(), Ok(1));
+}
+
+#[test]
+fn test_recommended_command() {
+    let nightly = |date: &str, version: &str| {
+        mock_manifest(
+            date,
+            &[
+                ("rust", version, true),
+                ("rustc", version, true),
+                ("cargo", version, true),
+                ("rust-src", version, true),
+            ],
+        )
+    };
+    let installed = nightly("2019-01-02", "1.33.0-nightly (9eac38634 2019-01-01)");
+    let source = MockSource::default().with(installed.clone()).with(nightly(
+        "2019-01-03",
+        "1.33.0-nightly (c2d381d39 2019-01-02)",
+    ));
+    let toolchain = mock_toolchain(installed, &["rustc", "cargo"]);
+
+    let rust = mock_rust(toolchain.clone(), source.clone(), "2019-01-02", 1);
+    assert_eq!(rust.recommended_command(), None);
+    let rust = mock_rust(toolchain.clone(), source.clone(), "2019-01-03", 0);
+    assert_eq!(
+        rust.recommended_command(),
+        Some("rustup update".to_string())
+    );
+    let rust = mock_rust(toolchain.clone(), source.clone(), "2019-01-03", 1);
+    assert_eq!(
+        rust.recommended_command(),
+        Some("rustup default nightly-2019-01-03".to_string())
+    );
+
+    let toolchain = Toolchain {
+        components: vec![Component::from(toolchain.manifest.as_ref(), "rust-src")],
+        ..toolchain
+    };
+    let rust = mock_rust(toolchain, source, "2019-01-03", 1);
+    assert_eq!(
+        rust.recommended_command(),
+        Some("rustup default nightly-2019-01-03\nrustup component add rust-src".to_string())
+    );
+    assert_eq!(
+        format::text(&rust.check_result().unwrap()).lines().last(),
+        Some("     \"rustup component add rust-src\"")
+    );
+}
+
+#[test]
+fn test_validate_target() {
+    for target in &[
+        "x86_64-unknown-linux-gnu",
+        "wasm32-unknown-unknown",
+        "aarch64-apple-ios-macabi",
+        "thumbv7em-none-eabihf",
+        "x86_64-fortanix-unknown-sgx",
+        "wasm32-wasip1",
+        "riscv64gc-unknown-linux-gnu",
+    ] {
+        assert_eq!(validate_target(target), Ok(()), "{}", target);
+    }
+    for target in &[
+        "",
+        "nightly",
+        "2023-06-01",
+        "2023-06-01-x86_64-unknown-linux-gnu",
+        "nightly-x86_64-unknown-linux-gnu",
+        "x86_64--linux-gnu",
+        "x86_64-unknown-linux-gnu-",
+        "X86_64-Unknown-Linux-Gnu",
+        "x86_64 unknown linux",
+    ] {
+        assert!(validate_target(target).is_err(), "{}", target);
+    }
+    assert_eq!(
+        validate_target("nightly"),
+        Err(
+            "Invalid target triple nightly: expected arch-vendor-os[-env], \
+             e.g. x86_64-unknown-linux-gnu"
+                .to_string()
+        )
+    );
+    assert_eq!(
+        split_toolchain_name("nightly-2019-01-01-x86_64-unknown-linux-gnu"),
+        Ok((
+            "nightly".to_string(),
+            "x86_64-unknown-linux-gnu".to_string()
+        ))
+    );
+    assert!(split_toolchain_name("nightly-x86-64-unknown-linux-gnu-extra-part").is_err());
+}
+
+#[test]
+fn test_archive() {
+    let manifest = fixture("downloads");
+    let source = MockSource::default().with(manifest.clone());
+    let toolchain = mock_toolchain(manifest, &["rustc", "rust-src", "clippy", "rls-preview"]);
+    let rust = mock_rust(toolchain, source, "2019-01-02", 0);
+    let dist = "https://static.rust-lang.org/dist/2019-01-02";
+    let lines: Vec<String> = rust
+        .downloads(DIST_SERVER)
+        .iter()
+        .map(|(name, download)| format!("{} {}", name, download))
+        .collect();
+    assert_eq!(
+        lines,
+        vec![
+            format!("rust xz {}/rust-nightly-{}.tar.xz 2222", dist, TARGET),
+            format!(
+                "clippy-preview xz {}/clippy-nightly-{}.tar.xz 5555",
+                dist, TARGET
+            ),
+            format!("rust-src gz {}/rust-src-nightly.tar.gz 4444", dist),
+            format!("rustc xz {}/rustc-nightly-{}.tar.xz 3333", dist, TARGET),
+        ]
+    );
+
+    let mirrored = rust.downloads("https://mirror.example/rust/");
+    assert_eq!(
+        mirrored[0].1.url,
+        format!(
+            "https://mirror.example/rust/dist/2019-01-02/rust-nightly-{}.tar.xz",
+            TARGET
+        )
+    );
+    assert_eq!(
+        Args::parse(vec!["--archive".to_string()]).unwrap().format,
+        Format::Archive
+    );
+}
+
+#[test]
+fn test_component_versions() {
+    let manifest = fixture("downloads");
+    let source = MockSource::default().with(manifest.clone());
+    let toolchain = mock_toolchain(manifest, &["rustc", "clippy", "rls-preview", "rust-src"]);
+    let rust = mock_rust(toolchain, source, "2019-01-03", -1);
+
+    let position = rust.at_date(NaiveDate::from_ymd(2019, 1, 2)).unwrap();
+    let versions = position.component_versions().unwrap();
+    assert_eq!(
+        format::component_versions(&position.date_str(), &versions),
+        "Component versions on 2019-01-02's nightly
+clippy       0.0.212 (2e26fdc2 2019-01-01)
+rls-preview  (unavailable)
+rust-src     1.33.0 (c2d381d39 2019-01-02)
+rustc        1.33.0 (c2d381d39 2019-01-02)
+"
+    );
+    // Newer manifests publish some versions without a commit.
+    let unparsed = ComponentVersion {
+        name: "clippy-preview".to_string(),
+        available: true,
+        version: None,
+    };
+    assert_eq!(
+        format::component_versions("2026-10-14", &[unparsed]),
+        "Component versions on 2026-10-14's nightly\nclippy-preview  (unknown version)\n"
+    );
+    // Nothing published, nothing to list.
+    let today = rust.at_date(NaiveDate::from_ymd(2019, 1, 3)).unwrap();
+    assert_eq!(today.component_versions(), None);
+    assert_eq!(
+        rust.latest_published(3).unwrap().unwrap().date_str(),
+        "2019-01-02"
+    );
+
+    let parse = |arg: &str| Args::parse(vec![arg.to_string()]).map(|a| a.component_versions);
+    assert_eq!(parse("--component-versions"), Ok(Some(None)));
+    assert_eq!(
+        parse("--component-versions=2019-01-02"),
+        Ok(Some(Some(NaiveDate::from_ymd(2019, 1, 2))))
+    );
+    assert!(parse("--component-versions=yesterday").is_err());
+}
+
+#[test]
+fn test_prefer_compression() {
+    let manifest = fixture("downloads");
+    let dist = "https://static.rust-lang.org/dist/2019-01-02";
+    let url = |name: &str, prefer: Compression| {
+        let download = manifest
+            .pkg_for_target(name, TARGET)
+            .unwrap()
+            .best_url(prefer)
+            .unwrap();
+        format!("{} {}", download.compression, download.url)
+    };
+    // Both published: the preference decides.
+    assert_eq!(
+        url("rust", Compression::Xz),
+        format!("xz {}/rust-nightly-{}.tar.xz", dist, TARGET)
+    );
+    assert_eq!(
+        url("rust", Compression::Gz),
+        format!("gz {}/rust-nightly-{}.tar.gz", dist, TARGET)
+    );
+    // Only one published: that one either way.
+    assert_eq!(
+        url("rustc", Compression::Gz),
+        format!("xz {}/rustc-nightly-{}.tar.xz", dist, TARGET)
+    );
+    assert_eq!(
+        url("rust-src", Compression::Xz),
+        format!("gz {}/rust-src-nightly.tar.gz", dist)
+    );
+
+    let source = MockSource::default().with(manifest.clone());
+    let toolchain = mock_toolchain(manifest, &["rustc"]);
+    let mut rust = mock_rust(toolchain, source, "2019-01-02", 0);
+    rust.set_compression(Compression::Gz);
+    assert_eq!(rust.downloads(DIST_SERVER)[0].1.compression, "gz");
+    let parse = |arg: &str| Args::parse(vec![arg.to_string()]).unwrap().compression;
+    assert_eq!(parse("--prefer-gz"), Compression::Gz);
+    assert_eq!(parse("--prefer-xz"), Compression::Xz);
+}
+
+#[test]
+fn test_inconsistencies() {
+    let dir = std::env::temp_dir().join(format!("rustupscheck-local-{}", std::process::id()));
+    std::fs::create_dir_all(dir.join("lib/rustlib")).unwrap();
+    std::fs::copy(fixture_path("downloads"), dir.join(MANIFEST_FILE)).unwrap();
+    std::fs::write(
+        dir.join(COMPONENTS_FILE),
+        format!("rustc-{0}\nclippy-{0}\nrust-src\nmiri-{0}\n", TARGET),
+    )
+    .unwrap();
+    let toolchain = Toolchain::from_dir("nightly".to_string(), TARGET.to_string(), &dir).unwrap();
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    let same = Version::from_str("1.33.0-nightly (c2d381d39 2019-01-02)").unwrap();
+    assert_eq!(
+        toolchain.inconsistencies(Some(&same)),
+        vec!["miri is installed but not in the manifest"]
+    );
+    let other = Version::from_str("1.33.0-nightly (9eac38634 2018-12-31)").unwrap();
+    assert_eq!(
+        toolchain.inconsistencies(Some(&other)),
+        vec![
+            "miri is installed but not in the manifest",
+            "rustc reports 1.33.0 (9eac38634 2018-12-31) but the manifest lists \
+             1.33.0 (c2d381d39 2019-01-02)",
+        ]
+    );
+    let without = Toolchain {
+        manifest: None,
+        ..toolchain
+    };
+    assert_eq!(
+        without.inconsistencies(None),
+        vec!["No local manifest to compare with"]
+    );
+    assert!(
+        Args::parse(vec!["--compare-installed-manifest".to_string()])
+            .unwrap()
+            .compare_installed_manifest
+    );
+}
+
+#[test]
+fn test_empty_components_file() {
+    let dir = std::env::temp_dir().join(format!("rustupscheck-empty-{}", std::process::id()));
+    std::fs::create_dir_all(dir.join("lib/rustlib")).unwrap();
+    std::fs::copy(fixture_path("downloads"), dir.join(MANIFEST_FILE)).unwrap();
+    std::fs::write(dir.join(COMPONENTS_FILE), "").unwrap();
+    let toolchain = Toolchain::from_dir("nightly".to_string(), TARGET.to_string(), &dir).unwrap();
+    std::fs::remove_dir_all(&dir).unwrap();
+    assert!(toolchain.components.is_empty());
+    assert_eq!(
+        toolchain.no_components(),
+        Some("No components detected; only the base toolchain will be compared".to_string())
+    );
+    assert_eq!(toolchain.info().lines().last(), Some("With no components"));
+
+    // Only `rust` itself decides: the same build is up to date, a newer one
+    // is an update with nothing to list.
+    let installed = toolchain.manifest.clone().unwrap();
+    let newer = mock_manifest(
+        "2019-01-03",
+        &[("rust", "1.33.0-nightly (9eac38634 2019-01-03)", true)],
+    );
+    let source = MockSource::default().with(installed).with(newer);
+    let rust = mock_rust(toolchain.clone(), source.clone(), "2019-01-02", 1);
+    assert_eq!(rust.check_result(), Some(CheckResult::UpToDate));
+    let rust = mock_rust(toolchain, source, "2019-01-03", 0);
+    assert_eq!(
+        rust.check_result(),
+        Some(CheckResult::Update {
+            date: "2019-01-03".to_string(),
+            updates: Vec::new(),
+        })
+    );
+    assert!(!format::text(&rust.check_result().unwrap()).contains("Update components"));
+    assert_eq!(
+        mock_toolchain(fixture("downloads"), &["rustc"]).no_components(),
+        None
+    );
+}
+
+#[test]
+fn test_required_components() {
+    let version = "1.33.0-nightly (9eac38634 2019-01-01)";
+    let manifest = mock_manifest(
+        "2019-01-02",
+        &[
+            ("rust", version, true),
+            ("rustc", version, true),
+            ("rustfmt", version, false),
+            ("clippy", version, true),
+            ("miri", version, false),
+        ],
+    );
+    let source = MockSource::default().with(manifest.clone());
+    let mut rust = mock_rust(
+        mock_toolchain(manifest, &["rustc", "rustfmt"]),
+        source,
+        "2019-01-02",
+        1,
+    );
+    assert_eq!(rust.missing_components(), vec!["rustfmt"]);
+
+    // The installed rustfmt no longer matters, only what is asked for.
+    rust.set_required(vec!["clippy".to_string()]);
+    assert!(rust.missing_components().is_empty());
+    assert!(rust.is_complete());
+    rust.set_required(vec!["clippy".to_string(), "miri".to_string()]);
+    assert_eq!(rust.missing_components(), vec!["miri"]);
+    rust.set_ignored(vec!["miri".to_string()]);
+    assert!(rust.is_complete());
+
+    let args = Args::parse(vec!["--require".to_string(), "clippy,miri".to_string()]).unwrap();
+    assert_eq!(args.require, vec!["clippy", "miri"]);
+}
+
+#[test]
+fn test_manifest_diff() {
+    let from = mock_manifest(
+        "2019-01-01",
+        &[
+            ("rustc", "1.33.0-nightly (9eac38634 2018-12-31)", true),
+            ("clippy", "0.0.212 (2e26fdc2 2018-12-30)", true),
+            ("miri", "0.1.0 (ffa9d5b5 2018-12-30)", false),
+            ("rls", "1.31.7 (0d6d0c3b 2018-12-30)", true),
+        ],
+    );
+    let to = mock_manifest(
+        "2019-01-02",
+        &[
+            ("rustc", "1.33.0-nightly (c2d381d39 2019-01-01)", true),
+            ("clippy", "0.0.212 (2e26fdc2 2018-12-30)", false),
+            ("miri", "0.1.0 (ffa9d5b5 2018-12-30)", true),
+            ("rustfmt", "1.0.1 (be135599 2018-12-10)", true),
+        ],
+    );
+    let changes = from.diff(&to, TARGET);
+    assert_eq!(
+        changes.iter().map(|c| c.name.as_str()).collect::<Vec<_>>(),
+        vec!["clippy", "miri", "rls", "rustc", "rustfmt"]
+    );
+    assert!(from.diff(&from, TARGET).is_empty());
+    assert_eq!(
+        format::manifest_diff("2019-01-01", "2019-01-02", &changes),
+        "component  2019-01-01                     2019-01-02
+clippy     0.0.212 (2e26fdc2 2018-12-30)  unavailable
+miri       unavailable                    0.1.0 (ffa9d5b5 2018-12-30)
+rls        1.31.7 (0d6d0c3b 2018-12-30)   absent
+rustc      1.33.0 (9eac38634 2018-12-31)  1.33.0 (c2d381d39 2019-01-01)
+rustfmt    absent                         1.0.1 (be135599 2018-12-10)
+"
+    );
+    assert_eq!(
+        format::manifest_diff("2019-01-01", "2019-01-01", &[]),
+        "No changes between 2019-01-01 and 2019-01-01\n"
+    );
+
+    let args = Args::parse(
+        ["--diff-dates", "2019-01-01", "2019-01-02"]
+            .iter()
+            .map(|s| s.to_string()),
+    )
+    .unwrap();
+    assert_eq!(
+        args.diff_dates,
+        Some(("2019-01-01".to_string(), "2019-01-02".to_string()))
+    );
+}
+
+#[test]
+fn test_manifest_diff_order() {
+    let names: Vec<String> = (0..40)
+        .map(|i| format!("pkg-{:02}", (i * 7) % 40))
+        .collect();
+    let manifest = |date: &str, version: &str, names: &[String]| {
+        let pkgs: Vec<(&str, &str, bool)> =
+            names.iter().map(|n| (n.as_str(), version, true)).collect();
+        mock_manifest(date, &pkgs)
+    };
+    let mut reversed = names.clone();
+    reversed.reverse();
+    let from = manifest("2019-01-01", "1.0.0 (aaaaaaaaa 2019-01-01)", &names);
+    let to = manifest("2019-01-02", "1.0.1 (bbbbbbbbb 2019-01-02)", &names);
+    let to_reversed = manifest("2019-01-02", "1.0.1 (bbbbbbbbb 2019-01-02)", &reversed);
+
+    // The same packages in any order, and a fresh HashMap each time, give
+    // the same sorted output.
+    let changes = from.diff(&to, TARGET);
+    let listed: Vec<&str> = changes.iter().map(|c| c.name.as_str()).collect();
+    let mut sorted = listed.clone();
+    sorted.sort();
+    assert_eq!(listed.len(), 40);
+    assert_eq!(listed, sorted);
+    for _ in 0..5 {
+        assert_eq!(from.diff(&to_reversed, TARGET), changes);
+        assert_eq!(from.clone().diff(&to.clone(), TARGET), changes);
+    }
+}
+
+// Talks to static.rust-lang.org; run with `cargo test -- --ignored`.
+#[test]
+#[ignore]
+fn test_manifest_diff_network() {
+    let fetch = |date: &str| {
+        Manifest::from_date(date, "nightly", &FetchConfig::default())
+            .unwrap()
+            .unwrap()
+    };
+    let (from, to) = (fetch("2019-01-01"), fetch("2019-01-02"));
+    let changes = from.diff(&to, TARGET);
+    assert!(changes.iter().any(|c| c.name == "rustc"));
+}
+
+#[test]
+fn test_missing_components_detailed() {
+    let version = "1.33.0-nightly (9eac38634 2019-01-01)";
+    let mut toml = mock_manifest_toml(
+        "2019-01-02",
+        &[
+            ("rust", version, true),
+            ("rustc", version, true),
+            ("clippy", version, false),
+        ],
+    );
+    toml.push_str(&format!(
+        "[pkg.miri]\nversion = \"{}\"\n[pkg.miri.target.aarch64-apple-ios]\navailable = true\n",
+        version
+    ));
+    let manifest: Manifest = toml.parse().unwrap();
+    assert_eq!(manifest.missing_reason("rustc", TARGET), None);
+    let source = MockSource::default().with(manifest.clone());
+    let rust = mock_rust(
+        mock_toolchain(manifest, &["rustc", "clippy", "miri", "rls"]),
+        source,
+        "2019-01-02",
+        -1,
+    );
+    let reasons: Vec<(String, MissingReason)> = rust
+        .missing_components_detailed()
+        .into_iter()
+        .map(|m| (m.name, m.reason))
+        .collect();
+    assert_eq!(
+        reasons,
+        vec![
+            ("clippy".to_string(), MissingReason::Unavailable),
+            ("miri".to_string(), MissingReason::TargetNotPublished),
+            ("rls".to_string(), MissingReason::Absent),
+        ]
+    );
+    assert_eq!(rust.missing_components(), vec!["clippy", "miri", "rls"]);
+
+    let result = rust.to_report(1).unwrap().result;
+    assert_eq!(
+        format::text(&result),
+        "No complete nightly found in the last 1 days
+Newest nightly 2019-01-02 is missing: clippy, miri, rls
+Remove them with \"rustup component remove clippy miri rls\" or wait for a newer nightly
+rls: package absent from manifest, a newer nightly is unlikely to help
+miri: target not published, a newer nightly is unlikely to help
+"
+    );
+    assert_eq!(
+        serde_json::to_value(&result).unwrap()["missing"],
+        serde_json::json!(["clippy", "miri", "rls"])
+    );
+}
+
+#[test]
+fn test_explicit_rustup_home() {
+    let home = std::env::temp_dir().join(format!("rustupscheck-home-{}", std::process::id()));
+    let name = format!("nightly-{}", TARGET);
+    let dir = home.join("toolchains").join(&name);
+    std::fs::create_dir_all(dir.join("lib/rustlib")).unwrap();
+    std::fs::copy(fixture_path("downloads"), dir.join(MANIFEST_FILE)).unwrap();
+    std::fs::write(
+        dir.join(COMPONENTS_FILE),
+        format!("rustc-{0}\nrust-src\nclippy-preview-{0}\n", TARGET),
+    )
+    .unwrap();
+    let toolchain = Toolchain::named(&name, Some(&home));
+    let missing = Toolchain::named(&format!("beta-{}", TARGET), Some(&home));
+    std::fs::remove_dir_all(&home).unwrap();
+    let toolchain = toolchain.unwrap();
+    assert_eq!(toolchain.channel, "nightly");
+    assert_eq!(toolchain.target, TARGET);
+    assert_eq!(
+        toolchain.component_list(),
+        vec!["clippy-preview", "rust-src"]
+    );
+    assert_eq!(
+        toolchain.manifest.map(|m| m.date),
+        Some(NaiveDate::from_ymd(2019, 1, 2))
+    );
+    assert!(missing.is_err());
+
+    let args = Args::parse(vec!["--rustup-home".to_string(), "/opt/rustup".to_string()]).unwrap();
+    assert_eq!(args.rustup_home, Some(PathBuf::from("/opt/rustup")));
+}
+
+#[test]
+fn test_min_components() {
+    let toolchain = mock_toolchain(fixture("downloads"), &["rustc", "rust-src"]);
+    assert_eq!(toolchain.check_min_components(2), Ok(()));
+    assert_eq!(
+        toolchain.check_min_components(5),
+        Err("Expected at least 5 components but detected 2; detection may be broken".to_string())
+    );
+    let args = Args::parse(vec!["--min-components=3".to_string()]).unwrap();
+    assert_eq!(args.min_components, Some(3));
+    assert!(Args::parse(vec!["--min-components=many".to_string()]).is_err());
+}
+
+#[test]
+fn test_version_hash() {
+    use std::collections::HashSet;
+    let a = Version::from_str("1.33.0-nightly (9eac38634 2018-12-31)").unwrap();
+    // Same date, different hash: equal, so the set must keep only one.
+    let b = Version::from_str("1.33.0-nightly (0000000aa 2018-12-31)").unwrap();
+    let c = Version::from_str("1.33.0-nightly (9eac38634 2019-01-01)").unwrap();
+    let d = Version::from_str("1.33.0-beta (9eac38634 2018-12-31)").unwrap();
+    assert_eq!(a, b);
+    let set: HashSet<Version> = vec![a.clone(), b, c.clone(), d.clone()]
+        .into_iter()
+        .collect();
+    assert_eq!(set.len(), 3);
+    assert!(set.contains(&a) && set.contains(&c) && set.contains(&d));
+
+    let commits: HashSet<Commit> = vec![a.commit.clone(), c.commit.clone(), d.commit]
+        .into_iter()
+        .collect();
+    assert_eq!(commits.len(), 2);
+    let channels: HashSet<Channel> = Channel::all()
+        .iter()
+        .chain(Channel::all().iter())
+        .cloned()
+        .collect();
+    assert_eq!(channels.len(), 3);
+}
+
+#[test]
+fn test_availability_history() {
+    let version = "1.33.0-nightly (9eac38634 2019-01-01)";
+    let nightly = |date: &str, miri: bool| {
+        mock_manifest(date, &[("rust", version, true), ("miri", version, miri)])
+    };
+    let source = MockSource::default()
+        .with(nightly("2019-01-05", false))
+        .with(nightly("2019-01-04", true))
+        .with(nightly("2019-01-02", false))
+        .with(nightly("2019-01-01", true));
+    let rust = mock_rust(
+        mock_toolchain(nightly("2019-01-01", true), &["miri"]),
+        source,
+        "2019-01-05",
+        -1,
+    );
+    let history = rust.availability("miri", 5).unwrap();
+    assert_eq!(
+        history,
+        vec![
+            ("2019-01-05".to_string(), Some(false)),
+            ("2019-01-04".to_string(), Some(true)),
+            ("2019-01-03".to_string(), None),
+            ("2019-01-02".to_string(), Some(false)),
+            ("2019-01-01".to_string(), Some(true)),
+        ]
+    );
+    assert_eq!(
+        format::availability("miri", &history),
+        "miri was available on 2 of 4 published nightlies
+2019-01-05  unavailable
+2019-01-04  available
+2019-01-03  not published
+2019-01-02  unavailable
+2019-01-01  available
+"
+    );
+    let args = Args::parse(vec!["--explain-missing=miri".to_string()]).unwrap();
+    assert_eq!(args.explain_missing, Some("miri".to_string()));
+}
+
+#[test]
+fn test_custom_toolchain_name() {
+    assert_eq!(
+        split_toolchain_name("nightly-x86_64-unknown-linux-gnu"),
+        Ok((
+            "nightly".to_string(),
+            "x86_64-unknown-linux-gnu".to_string()
+        ))
+    );
+    assert_eq!(
+        split_toolchain_name("1.70.0-x86_64-unknown-linux-gnu"),
+        Ok(("1.70.0".to_string(), "x86_64-unknown-linux-gnu".to_string()))
+    );
+    assert_eq!(
+        split_toolchain_name("x86_64-unknown-linux-gnu"),
+        Err(
+            "Update checking not supported for custom toolchain x86_64-unknown-linux-gnu"
+                .to_string()
+        )
+    );
+    assert!(split_toolchain_name("my-stage1-build").is_err());
+    assert!(split_toolchain_name("1.-x86_64-unknown-linux-gnu").is_err());
+}
+
+#[test]
+fn test_rustup_release() {
+    let contents = std::fs::read_to_string(fixture_path("rustup-release")).unwrap();
+    let latest: rustup::RustupRelease = contents.parse().unwrap();
+    assert_eq!(latest.schema_version, "1");
+    assert_eq!(latest.version, "1.28.2");
+    assert!("version = 1".parse::<rustup::RustupRelease>().is_err());
+
+    let installed = rustup::parse_rustup_version(
+        "rustup 1.27.1 (54dd3d00f 2024-04-24)\ninfo: This is the version for the rustup toolchain manager\n",
+    );
+    assert_eq!(installed, Some("1.27.1".to_string()));
+    assert_eq!(rustup::parse_rustup_version("cargo 1.75.0"), None);
+    assert_eq!(
+        rustup::status("1.27.1", &latest),
+        "rustup 1.28.2 is available (you have 1.27.1)"
+    );
+    assert_eq!(
+        rustup::status("1.28.2", &latest),
+        "rustup 1.28.2 is up to date"
+    );
+    assert_eq!(
+        rustup::status("1.100.0", &latest),
+        "rustup 1.100.0 is up to date"
+    );
+}
+
+#[test]
+fn test_rustup_settings() {
+    use std::path::Path;
+    let dir = Path::new(&fixture_path("settings"))
+        .parent()
+        .unwrap()
+        .to_path_buf();
+    let settings = rustup::RustupSettings::read(&dir).unwrap();
+    assert_eq!(settings.default_host_triple.as_deref(), Some(TARGET));
+    assert_eq!(settings.default_toolchain.as_deref(), Some("nightly"));
+    assert_eq!(settings.overrides.len(), 3);
+
+    let toolchain = |dir: &str| settings.toolchain_for(Path::new(dir));
+    // The default, completed with the host.
+    assert_eq!(toolchain("/tmp"), Some(format!("nightly-{}", TARGET)));
+    // The nearest override wins, for subdirectories too.
+    assert_eq!(
+        toolchain("/home/user/project/src"),
+        Some(format!("stable-{}", TARGET))
+    );
+    assert_eq!(
+        toolchain("/home/user/project/old"),
+        Some(format!("nightly-2019-01-01-{}", TARGET))
+    );
+    assert_eq!(toolchain("/home/user/stage"), Some("stage1".to_string()));
+
+    // A fresh install may have no default yet.
+    let empty: rustup::RustupSettings = "version = \"12\"".parse().unwrap();
+    assert_eq!(empty.toolchain_for(Path::new("/tmp")), None);
+    assert!(rustup::RustupSettings::read(Path::new("/nonexistent")).is_err());
+}
+
+#[test]
+fn test_no_banner() {
+    let version = "1.33.0-nightly (9eac38634 2019-01-01)";
+    let manifest = mock_manifest(
+        "2019-01-02",
+        &[("rust", version, true), ("rustc", version, true)],
+    );
+    let source = MockSource::default().with(manifest.clone());
+    let rust = mock_rust(
+        mock_toolchain(manifest, &["rustc"]),
+        source,
+        "2019-01-02",
+        -1,
+    );
+    let mut report = rust.to_report(1).unwrap();
+    let banner = report.banner.clone();
+    assert!(report.render(Format::Text).starts_with(&banner));
+    for format in &[Format::Json, Format::JsonLines, Format::Summary] {
+        assert!(!report.render(*format).contains(&banner), "{:?}", format);
+    }
+
+    report.banner.clear();
+    assert_eq!(
+        report.render(Format::Text),
+        "Current version is up to date\n"
+    );
+    assert_eq!(
+        report.render(Format::Markdown),
+        "### Current version is up to date\n"
+    );
+
+    let args = |v: &[&str]| Args::parse(v.iter().map(|s| s.to_string())).unwrap();
+    assert!(args(&[]).show_banner());
+    assert!(!args(&["--no-banner"]).show_banner());
+    assert!(!args(&["--json"]).show_banner());
+    assert!(!args(&["--summary"]).show_banner());
+}
+
+#[test]
+fn test_pkg_for_target_prefers_exact() {
+    let manifest: Manifest = format!(
+        "manifest-version = \"2\"
+date = \"2019-01-02\"
+[pkg.rust-src]
+version = \"1.33.0-nightly (9eac38634 2019-01-01)\"
+[pkg.rust-src.target.{}]
+available = false
+[pkg.rust-src.target.\"*\"]
+available = true
+[renames]
+",
+        TARGET
+    )
+    .parse()
+    .unwrap();
+    assert_eq!(
+        manifest
+            .pkg_for_target("rust-src", TARGET)
+            .map(|p| p.available),
+        Some(false)
+    );
+    assert!(!manifest.contains_component("rust-src", TARGET));
+    assert_eq!(
+        manifest.missing_reason("rust-src", TARGET),
+        Some(MissingReason::Unavailable)
+    );
+    // Targets without their own entry still fall back to `*`.
+    assert!(manifest.contains_component("rust-src", "wasm32-unknown-unknown"));
+}
+
+#[test]
+fn test_toml_format() {
+    let installed = "1.33.0-nightly (9eac38634 2019-01-01)";
+    let newer = "1.33.0-nightly (c2d381d39 2019-01-02)";
+    let nightly = |date: &str, version: &str| {
+        mock_manifest(date, &[("rust", version, true), ("rustc", version, true)])
+    };
+    let source = MockSource::default()
+        .with(nightly("2019-01-02", installed))
+        .with(nightly("2019-01-03", newer));
+    let toolchain = mock_toolchain(nightly("2019-01-02", installed), &["rustc"]);
+
+    let rust = mock_rust(toolchain.clone(), source.clone(), "2019-01-02", -1);
+    assert_eq!(
+        rust.to_report(1).unwrap().render(Format::Toml),
+        r#"candidate = "2019-01-02"
+installed = "1.33.0-nightly (9eac38634 2019-01-01)"
+installed_components = []
+missing = []
+result = "up-to-date"
+toolchain = "nightly-x86_64-unknown-linux-gnu"
+
+[rationale]
+date = "2019-01-02"
+kind = "newest-complete"
+"#
+    );
+    let rust = mock_rust(toolchain, source, "2019-01-03", -1);
+    assert_eq!(
+        rust.to_report(1).unwrap().render(Format::Toml),
+        r#"candidate = "2019-01-03"
+date = "2019-01-03"
+installed = "1.33.0-nightly (9eac38634 2019-01-01)"
+installed_components = []
+missing = []
+result = "update"
+toolchain = "nightly-x86_64-unknown-linux-gnu"
+
+[[updates]]
+from = "1.33.0-nightly (9eac38634 2019-01-01)"
+name = "rustc"
+to = "1.33.0-nightly (c2d381d39 2019-01-02)"
+
+[rationale]
+date = "2019-01-03"
+kind = "newest-complete"
+"#
+    );
+    assert_eq!(Format::from_str("toml"), Ok(Format::Toml));
+}
+
+#[test]
+fn test_target_family() {
+    let manifest = fixture("wasm-family");
+    assert_eq!(manifest.target_list().len(), 5);
+    let family = manifest.target_family("wasm32-*");
+    assert_eq!(
+        family,
+        vec![
+            ("wasm32-unknown-emscripten".to_string(), false),
+            ("wasm32-unknown-unknown".to_string(), true),
+            ("wasm32-wasi".to_string(), true),
+        ]
+    );
+    assert_eq!(manifest.target_family("wasm32"), family);
+
+    let source = MockSource::default().with_latest("nightly", manifest.clone());
+    let rust = mock_rust(mock_toolchain(manifest, &[]), source, "2019-01-01", -1);
+    let (date, family) = rust.target_family("wasm32").unwrap();
+    assert_eq!(
+        format::target_family("wasm32", &date.to_string(), &family),
+        "2 of 3 wasm32 targets have rust-std on 2019-01-01
+wasm32-unknown-emscripten  unavailable
+wasm32-unknown-unknown     available
+wasm32-wasi                available
+Unavailable: wasm32-unknown-emscripten
+"
+    );
+    assert_eq!(
+        format::target_family("riscv", "2019-01-01", &[]),
+        "No targets matching riscv on 2019-01-01's nightly\n"
+    );
+}
+
+#[test]
+fn test_today_unpublished() {
+    let installed = "1.33.0-nightly (9eac38634 2019-01-01)";
+    let newer = "1.33.0-nightly (c2d381d39 2019-01-02)";
+    let nightly = |date: &str, version: &str| {
+        mock_manifest(date, &[("rust", version, true), ("rustc", version, true)])
+    };
+    let source = MockSource::default()
+        .with(nightly("2019-01-02", installed))
+        .with(nightly("2019-01-03", newer));
+    let toolchain = mock_toolchain(nightly("2019-01-02", installed), &["rustc"]);
+    // Today, 2019-01-04, has no nightly yet.
+    let rust = mock_rust(toolchain, source, "2019-01-04", -1);
+    assert_eq!(
+        rust.latest_published(3).unwrap().map(|r| r.date_str()),
+        Some("2019-01-03".to_string())
+    );
+    let report = rust.to_report(3).unwrap();
+    assert_eq!(report.candidate, Some("2019-01-03".to_string()));
+    match report.result {
+        CheckResult::Update { date, .. } => assert_eq!(date, "2019-01-03"),
+        result => panic!("unexpected {:?}", result),
+    }
+    assert!(rust.latest_published(0).unwrap().is_none());
+}
+
+#[test]
+fn test_component_table() {
+    let old = "1.33.0-nightly (9eac38634 2019-01-01)";
+    let new = "1.33.0-nightly (c2d381d39 2019-01-02)";
+    let installed = mock_manifest(
+        "2019-01-02",
+        &[
+            ("rust", old, true),
+            ("rustc", old, true),
+            ("rustfmt", "1.0.1 (be135599 2018-12-10)", true),
+            ("clippy", "0.0.212 (2e26fdc2 2018-12-30)", true),
+            ("rls", "1.31.7 (0d6d0c3b 2018-12-30)", true),
+        ],
+    );
+    let candidate = mock_manifest(
+        "2019-01-03",
+        &[
+            ("rust", new, true),
+            ("rustc", new, true),
+            ("rustfmt", "1.0.1 (be135599 2018-12-10)", true),
+            ("clippy", "0.0.212 (2e26fdc2 2018-12-30)", false),
+            ("miri", "0.1.0 (ffa9d5b5 2019-01-02)", true),
+        ],
+    );
+    let source = MockSource::default().with(candidate);
+    let mut rust = mock_rust(
+        mock_toolchain(installed, &["rustc", "rustfmt", "clippy", "rls"]),
+        source,
+        "2019-01-03",
+        1,
+    );
+    rust.set_ignored(vec!["rls".to_string()]);
+    rust.set_required(vec![
+        "rustc".to_string(),
+        "rustfmt".to_string(),
+        "clippy".to_string(),
+        "miri".to_string(),
+    ]);
+    let rows = rust.component_table();
+    assert_eq!(
+        format::component_table(&rows, 200),
+        "component  installed                      candidate                      status
+clippy     0.0.212 (2e26fdc2 2018-12-30)  0.0.212 (2e26fdc2 2018-12-30)  missing: not available for target
+miri       -                              0.1.0 (ffa9d5b5 2019-01-02)    add
+rls        1.31.7 (0d6d0c3b 2018-12-30)   -                              ignored
+rustc      1.33.0 (9eac38634 2019-01-01)  1.33.0 (c2d381d39 2019-01-02)  update
+rustfmt    1.0.1 (be135599 2018-12-10)    1.0.1 (be135599 2018-12-10)    up-to-date
+"
+    );
+    assert_eq!(
+        format::component_table(&rows, 80),
+        "component  installed  candidate  status
+clippy     0.0.212    0.0.212    missing: not available for target
+miri       -          0.1.0      add
+rls        1.31.7     -          ignored
+rustc      1.33.0     1.33.0     update
+rustfmt    1.0.1      1.0.1      up-to-date
+"
+    );
+}
+
+#[test]
+fn test_no_required_distinction() {
+    let version = "1.33.0-nightly (9eac38634 2019-01-01)";
+    let manifest = mock_manifest(
+        "2019-01-02",
+        &[
+            ("rust", version, true),
+            ("rustc", version, true),
+            ("cargo", version, true),
+            ("rustfmt", version, true),
+        ],
+    );
+    let toolchain = mock_toolchain(manifest.clone(), &["rustc", "cargo", "rustfmt"]);
+    assert_eq!(toolchain.component_list(), vec!["rustfmt"]);
+    let toolchain = toolchain.without_required();
+    assert_eq!(
+        toolchain.component_list(),
+        vec!["cargo", "rustc", "rustfmt"]
+    );
+    assert_eq!(
+        toolchain.info().lines().last(),
+        Some("With components: cargo, rustc, rustfmt")
+    );
+    let source = MockSource::default().with(manifest);
+    let rust = mock_rust(toolchain, source, "2019-01-02", 1);
+    assert_eq!(rust.add_components(), vec!["cargo", "rustc", "rustfmt"]);
+    assert!(
+        Args::parse(vec!["--no-required-distinction".to_string()])
+            .unwrap()
+            .no_required_distinction
+    );
+}
+
+#[test]
+fn test_target_default_only() {
+    let version = "1.33.0-nightly (9eac38634 2019-01-01)";
+    let manifest = mock_manifest(
+        "2019-01-02",
+        &[
+            ("rust", version, true),
+            ("rustc", version, true),
+            ("cargo", version, true),
+            ("rust-std", version, true),
+        ],
+    );
+    let toolchain = mock_toolchain(
+        manifest.clone(),
+        &[
+            "rustc",
+            "cargo",
+            "rust-std",
+            "rust-std-wasm32-unknown-unknown",
+            "rust-std-aarch64-unknown-linux-gnu",
+        ],
+    );
+    assert_eq!(
+        toolchain.extra_std_targets(),
+        vec!["aarch64-unknown-linux-gnu", "wasm32-unknown-unknown"]
+    );
+    let source = MockSource::default().with(manifest);
+    let mut rust = mock_rust(toolchain, source, "2019-01-02", 1);
+    assert!(!rust.is_complete());
+    assert_eq!(
+        rust.ignore_extra_targets(),
+        vec!["aarch64-unknown-linux-gnu", "wasm32-unknown-unknown"]
+    );
+    assert!(rust.is_complete());
+    assert_eq!(rust.add_components(), vec!["rust-std"]);
+    assert!(
+        Args::parse(vec!["--target-default-only".to_string()])
+            .unwrap()
+            .target_default_only
+    );
+}
+
+#[test]
+fn test_installed_std_targets() {
+    let version = "1.33.0-nightly (9eac38634 2019-01-01)";
+    // Every nightly has the host std; the cross targets come and go.
+    let nightly = |date: &str, cross: &[(&str, bool)]| -> Manifest {
+        let mut toml = mock_manifest_toml(
+            date,
+            &[
+                ("rust", version, true),
+                ("rustc", version, true),
+                ("rust-std", version, true),
+            ],
+        );
+        for (target, available) in cross {
+            toml = toml.replace(
+                "[renames]",
+                &format!(
+                    "[pkg.rust-std.target.{}]\navailable = {}\n[renames]",
+                    target, available
+                ),
+            );
+        }
+        toml.parse().unwrap()
+    };
+    let wasm = "wasm32-unknown-unknown";
+    let arm = "aarch64-unknown-linux-gnu";
+    let source = MockSource::default()
+        .with(nightly("2019-01-02", &[(wasm, true), (arm, true)]))
+        .with(nightly("2019-01-03", &[(wasm, true), (arm, false)]))
+        .with(nightly("2019-01-04", &[(wasm, true)]));
+    let toolchain = mock_toolchain(
+        nightly("2019-01-01", &[(wasm, true), (arm, true)]),
+        &[
+            "rustc",
+            "rust-std",
+            "rust-std-wasm32-unknown-unknown",
+            "rust-std-aarch64-unknown-linux-gnu",
+        ],
+    );
+    let installed = Version::from_str(version).ok();
+    assert!(toolchain.components.iter().all(|c| c.version == installed));
+    let rust = mock_rust(toolchain, source, "2019-01-04", -1);
+
+    // Only a nightly with std for every installed target will do.
+    let probes: Vec<(String, Vec<MissingComponent>)> = rust
+        .scan_iter(3)
+        .map(|r| {
+            let r = r.unwrap();
+            (r.date_str(), r.missing_components_detailed())
+        })
+        .collect();
+    assert_eq!(
+        probes,
+        vec![
+            (
+                "2019-01-04".to_string(),
+                vec![MissingComponent {
+                    name: "rust-std-aarch64-unknown-linux-gnu".to_string(),
+                    reason: MissingReason::TargetNotPublished,
+                }]
+            ),
+            (
+                "2019-01-03".to_string(),
+                unavailable(&["rust-std-aarch64-unknown-linux-gnu"])
+            ),
+            ("2019-01-02".to_string(), vec![]),
+        ]
+    );
+    let report = rust.to_report(3).unwrap();
+    assert_eq!(report.candidate, Some("2019-01-02".to_string()));
+
+    let mut rust = rust;
+    rust.ignore_extra_targets();
+    assert_eq!(
+        rust.to_report(3).unwrap().candidate,
+        Some("2019-01-04".to_string())
+    );
+}
+
+#[test]
+fn test_all_toolchains() {
+    let home = std::env::temp_dir().join(format!("rustupscheck-all-{}", std::process::id()));
+    for channel in &["nightly", "beta"] {
+        let dir = home
+            .join("toolchains")
+            .join(format!("{}-{}", channel, TARGET));
+        std::fs::create_dir_all(dir.join("lib/rustlib")).unwrap();
+        std::fs::copy(fixture_path("downloads"), dir.join(MANIFEST_FILE)).unwrap();
+        std::fs::write(dir.join(COMPONENTS_FILE), format!("rustc-{}\n", TARGET)).unwrap();
+    }
+    std::fs::create_dir_all(home.join("toolchains/my-build")).unwrap();
+    std::fs::write(home.join("toolchains/notes.txt"), "").unwrap();
+    let toolchains = Toolchain::installed_in(Some(&home));
+    std::fs::remove_dir_all(&home).unwrap();
+
+    let toolchains = toolchains.unwrap();
+    let names: Vec<&str> = toolchains.iter().map(|(name, _)| name.as_str()).collect();
+    let beta = format!("beta-{}", TARGET);
+    let nightly = format!("nightly-{}", TARGET);
+    assert_eq!(names, vec![beta.as_str(), "my-build", nightly.as_str()]);
+    assert_eq!(
+        toolchains[0].1.as_ref().map(|t| t.channel.as_str()),
+        Ok("beta")
+    );
+    assert_eq!(toolchains[1].1.as_ref().map(|t| t.is_custom()), Ok(true));
+    assert_eq!(
+        toolchains[2].1.as_ref().map(|t| t.component_list()),
+        Ok(vec![])
+    );
+    assert!(Toolchain::installed_in(Some(&home)).is_err());
+
+    let version = "1.33.0-nightly (9eac38634 2019-01-01)";
+    let manifest = mock_manifest(
+        "2019-01-02",
+        &[("rust", version, true), ("rustc", version, true)],
+    );
+    let source = MockSource::default().with(manifest.clone());
+    let rust = mock_rust(
+        mock_toolchain(manifest, &["rustc"]),
+        source,
+        "2019-01-02",
+        -1,
+    );
+    let mut report = rust.to_report(1).unwrap();
+    report.banner.clear();
+    let reports = vec![report.clone(), report];
+    let json: serde_json::Value =
+        serde_json::from_str(&report::render_all(&reports, Format::Json)).unwrap();
+    assert_eq!(json.as_array().map(|a| a.len()), Some(2));
+    let toml: toml::Value = report::render_all(&reports, Format::Toml).parse().unwrap();
+    assert_eq!(toml["toolchain"].as_array().map(|a| a.len()), Some(2));
+    assert_eq!(
+        report::render_all(&reports, Format::Text),
+        "Current version is up to date\n\nCurrent version is up to date\n"
+    );
+    assert!(
+        Args::parse(vec!["--all-toolchains".to_string()])
+            .unwrap()
+            .all_toolchains
+    );
+}
+
+#[test]
+fn test_fail_on_missing() {
+    let version = "1.33.0-nightly (9eac38634 2019-01-01)";
+    let source = MockSource::default()
+        .with(mock_manifest(
+            "2019-01-02",
+            &[
+                ("rust", version, true),
+                ("rustc", version, true),
+                ("rls", version, true),
+            ],
+        ))
+        .with(mock_manifest(
+            "2019-01-03",
+            &[
+                ("rust", version, true),
+                ("rustc", version, true),
+                ("rls", version, false),
+            ],
+        ));
+    let toolchain = mock_toolchain(fixture("downloads"), &["rustc", "rls"]);
+    let mut rust = mock_rust(toolchain, source, "2019-01-04", -1);
+
+    let report = rust.to_report(5).unwrap();
+    assert_eq!(report.candidate, Some("2019-01-02".to_string()));
+    assert_ne!(report.exit_code(), 20);
+
+    rust.set_fail_on_missing(true);
+    let report = rust.to_report(5).unwrap();
+    assert_eq!(report.candidate, None);
+    assert_eq!(report.exit_code(), 20);
+    assert_eq!(
+        format::summary(&report.result),
+        "no complete nightly in the last 2 days (2019-01-03 missing rls)"
+    );
+    assert!(
+        Args::parse(vec!["--fail-on-missing".to_string()])
+            .unwrap()
+            .fail_on_missing
+    );
+}
+
+#[test]
+fn test_pkg_commit() {
+    let manifest = fixture("commit-hash");
+    let hash = "c2d381d39b6cf0d2dbdbac0e3e2e5afc3b7f6fbb";
+    assert_eq!(manifest.pkg_commit("rustc"), Some(hash));
+    assert_eq!(manifest.pkg_commit("rustfmt"), Some(hash));
+    assert_eq!(manifest.pkg_commit("rust-src"), None);
+    assert_eq!(manifest.pkg_commit("miri"), None);
+    // Manifests without the field still parse.
+    assert_eq!(fixture("downloads").pkg_commit("rustc"), None);
+}
+
+#[test]
+fn test_as_of_latest_complete() {
+    let old = "1.33.0-nightly (9eac38634 2019-01-01)";
+    let newer = "1.33.0-nightly (c2d381d39 2019-01-02)";
+    let newest = "1.33.0-nightly (d1b4a1e7e 2019-01-03)";
+    let nightly = |date: &str, version: &str, rls: bool| {
+        mock_manifest(
+            date,
+            &[
+                ("rust", version, true),
+                ("rustc", version, true),
+                ("rls", version, rls),
+            ],
+        )
+    };
+    let installed =
+        |version: &str| mock_toolchain(nightly("2019-01-01", version, true), &["rustc"]);
+
+    let source = MockSource::default().with(nightly("2019-01-02", newer, true));
+    let rust = mock_rust(installed(newer), source, "2019-01-02", -1);
+    assert_eq!(
+        rust.as_of_latest_complete(3).unwrap(),
+        Recommendation {
+            date: Some(NaiveDate::from_ymd(2019, 1, 2)),
+            updates: vec![],
+            missing: vec![],
+            command: None,
+        }
+    );
+
+    let source = MockSource::default()
+        .with(nightly("2019-01-02", newer, true))
+        .with(nightly("2019-01-03", newest, true));
+    let rust = mock_rust(installed(old), source, "2019-01-03", -1);
+    let recommendation = rust.as_of_latest_complete(3).unwrap();
+    assert_eq!(recommendation.date, Some(NaiveDate::from_ymd(2019, 1, 3)));
+    assert_eq!(
+        recommendation
+            .updates
+            .iter()
+            .map(|u| u.to_string())
+            .collect::<Vec<_>>(),
+        vec!["rustc - from 1.33.0 (9eac38634 2019-01-01) to 1.33.0 (d1b4a1e7e 2019-01-03)"]
+    );
+    assert_eq!(recommendation.command, Some("rustup update".to_string()));
+
+    let source = MockSource::default()
+        .with(nightly("2019-01-02", newer, true))
+        .with(nightly("2019-01-03", newest, false));
+    let toolchain = mock_toolchain(nightly("2019-01-01", old, true), &["rustc", "rls"]);
+    let rust = mock_rust(toolchain, source, "2019-01-03", -1);
+    let recommendation = rust.as_of_latest_complete(3).unwrap();
+    assert_eq!(recommendation.date, Some(NaiveDate::from_ymd(2019, 1, 2)));
+    assert!(recommendation.missing.is_empty());
+    assert_eq!(
+        recommendation.command,
+        Some("rustup default nightly-2019-01-02\nrustup component add rls".to_string())
+    );
+
+    let recommendation = rust.as_of_latest_complete(1).unwrap();
+    assert_eq!(recommendation.date, None);
+    assert_eq!(recommendation.missing, vec!["rls"]);
+    assert_eq!(recommendation.command, None);
+}
+
+#[test]
+fn test_rust_version_for_target() {
+    let manifest = fixture("rust-targets");
+    let version = Version::from_str("1.33.0-nightly (c2d381d39 2019-01-02)").ok();
+    assert_eq!(manifest.rust_version_for_target(TARGET), version);
+    assert_eq!(manifest.pkg_version("rust"), version);
+    assert_eq!(
+        manifest.rust_version_for_target("aarch64-unknown-linux-gnu"),
+        None
+    );
+    assert_eq!(
+        manifest.rust_version_for_target("wasm32-unknown-unknown"),
+        None
+    );
+
+    // A version that can't be installed is nothing to update to.
+    let installed = mock_manifest(
+        "2019-01-01",
+        &[("rust", "1.33.0-nightly (9eac38634 2019-01-01)", true)],
+    );
+    let mut toolchain = mock_toolchain(installed, &[]);
+    toolchain.target = "aarch64-unknown-linux-gnu".to_string();
+    let source = MockSource::default().with(manifest);
+    let rust = mock_rust(toolchain, source, "2019-01-02", 0);
+    assert!(rust.is_up_to_date());
+}
+
+#[test]
+fn test_available_components_wildcard() {
+    let manifest = fixture("targets");
+    assert_eq!(
+        manifest.available_components(TARGET),
+        vec!["rust-src", "rust-std"]
+    );
+    // `rust-src` is only published under `*`, which covers any target.
+    assert!(!manifest.has_target("riscv64gc-unknown-none-elf"));
+    assert_eq!(
+        manifest.available_components("riscv64gc-unknown-none-elf"),
+        vec!["rust-src"]
+    );
+}
+
+#[test]
+fn test_removed_upstream() {
+    let version = "1.33.0-nightly (9eac38634 2019-01-01)";
+    let nightly = |date: &str, rls: bool| {
+        mock_manifest(
+            date,
+            &[
+                ("rust", version, true),
+                ("rustc", version, true),
+                ("rls", version, rls),
+                ("miri", version, false),
+            ],
+        )
+    };
+    let source = MockSource::default()
+        .with(nightly("2019-01-02", true))
+        .with(nightly("2019-01-03", false))
+        .with(nightly("2019-01-04", true));
+    let toolchain = mock_toolchain(nightly("2019-01-01", true), &["rustc", "rls", "miri"]);
+    let rust = mock_rust(toolchain, source, "2019-01-04", -1);
+
+    let report = rust.to_report(3).unwrap();
+    assert_eq!(report.exit_code(), 20);
+    assert_eq!(report.removed_upstream, vec!["miri"]);
+    assert_eq!(
+        format::removed_upstream(&report.removed_upstream),
+        Some(
+            "Note: miri is missing from every nightly checked and likely removed upstream\n\
+             Use: \"rustup component remove miri\"\n"
+                .to_string()
+        )
+    );
+    // A single broken nightly isn't enough to tell.
+    assert!(rust.to_report(1).unwrap().removed_upstream.is_empty());
+    assert_eq!(format::removed_upstream(&[]), None);
+}
+
+#[test]
+fn test_candidate_manifest_date() {
+    let version = "1.33.0-nightly (9eac38634 2019-01-01)";
+    let manifest = mock_manifest("2019-01-02", &[("rust", version, true)]);
+    let toolchain = mock_toolchain(manifest.clone(), &[]);
+    let mut source = MockSource::default().with(manifest.clone());
+    // The dist server answers for 2019-01-03 with the previous manifest.
+    source.manifests.insert("2019-01-03".to_string(), manifest);
+
+    let rust = mock_rust(toolchain.clone(), source.clone(), "2019-01-03", -1);
+    assert_eq!(
+        rust.candidate_manifest_date(),
+        Some(NaiveDate::from_ymd(2019, 1, 2))
+    );
+    assert_eq!(
+        rust.date_note(),
+        Some("requested 2019-01-03, newest complete 2019-01-02".to_string())
+    );
+    let report = rust.to_report(1).unwrap();
+    assert_eq!(report.date_note, rust.date_note());
+
+    let rust = mock_rust(toolchain, source, "2019-01-02", 0);
+    assert_eq!(rust.date_note(), None);
+    assert!(rust.to_report(1).unwrap().date_note.is_none());
+}
+
+#[test]
+fn test_rename_chain() {
+    let manifest = fixture("rename-chain");
+    assert_eq!(manifest.resolve_name("clippy-preview"), "clippy");
+    assert_eq!(manifest.resolve_name("clippy-next"), "clippy");
+    assert_eq!(manifest.resolve_name("clippy"), "clippy");
+    assert!(manifest.contains_component("clippy-preview", TARGET));
+    assert_eq!(manifest.missing_reason("clippy-preview", TARGET), None);
+    // A loop ends instead of spinning.
+    assert_eq!(manifest.resolve_name("rustfmt"), "rustfmt-preview");
+
+    let source = MockSource::default().with(manifest.clone());
+    let toolchain = mock_toolchain(manifest, &["clippy-preview"]);
+    let rust = mock_rust(toolchain, source, "2019-01-02", 0);
+    assert!(rust.missing_components().is_empty());
+    assert_eq!(rust.add_components(), vec!["clippy"]);
+}
+
+#[test]
+fn test_rationale() {
+    let version = "1.33.0-nightly (9eac38634 2019-01-01)";
+    let nightly = |date: &str, rls: bool| {
+        mock_manifest(
+            date,
+            &[
+                ("rust", version, true),
+                ("rustc", version, true),
+                ("rls", version, rls),
+            ],
+        )
+    };
+    let source = MockSource::default()
+        .with(nightly("2019-01-01", true))
+        .with(nightly("2019-01-03", false))
+        .with(nightly("2019-01-04", false));
+    let toolchain = mock_toolchain(nightly("2019-01-01", true), &["rustc", "rls"]);
+    let rationale = |rust: &Rust, days: usize| rust.to_report(days).unwrap().rationale;
+
+    let rust = mock_rust(toolchain.clone(), source.clone(), "2019-01-01", -1);
+    assert_eq!(
+        rationale(&rust, 3),
+        Rationale::NewestComplete {
+            date: "2019-01-01".to_string()
+        }
+    );
+
+    let mut rust = mock_rust(toolchain.clone(), source.clone(), "2019-01-04", -1);
+    let fell_back = rationale(&rust, 10);
+    assert_eq!(
+        fell_back,
+        Rationale::NewestIncomplete {
+            newest: "2019-01-04".to_string(),
+            missing: vec!["rls".to_string()],
+            date: "2019-01-01".to_string(),
+            days_back: 3,
+        }
+    );
+    assert_eq!(
+        fell_back.to_string(),
+        "2019-01-04 is missing rls; newest complete is 2019-01-01, 3 days back"
+    );
+    assert_eq!(rationale(&rust, 2), Rationale::NoneComplete { days: 2 });
+    rust.set_floor(NaiveDate::from_ymd_opt(2019, 1, 2));
+    assert_eq!(rationale(&rust, 10), Rationale::NothingSinceInstalled);
+
+    let mut toolchain = toolchain;
+    toolchain.target = "i686-unknown-haiku".to_string();
+    let rust = mock_rust(toolchain, source, "2019-01-04", -1);
+    assert_eq!(
+        rationale(&rust, 3),
+        Rationale::TargetDropped {
+            date: "2019-01-04".to_string()
+        }
+    );
+}
+
+#[test]
+fn test_since_installed() {
+    let version = "1.33.0-nightly (9eac38634 2019-01-01)";
+    let nightly = |date: &str, rls: bool| {
+        mock_manifest(
+            date,
+            &[
+                ("rust", version, true),
+                ("rustc", version, true),
+                ("rls", version, rls),
+            ],
+        )
+    };
+    let source = MockSource::default()
+        .with(nightly("2019-01-01", true))
+        .with(nightly("2019-01-02", true))
+        .with(nightly("2019-01-03", false));
+    let toolchain = mock_toolchain(nightly("2019-01-02", true), &["rustc", "rls"]);
+    let mut rust = mock_rust(toolchain, source, "2019-01-04", -1);
+    rust.set_floor(rust.toolchain().installed_date());
+
+    let mut probed = vec![];
+    let report = rust
+        .report_with(10, &mut |r| probed.push(r.date_str()))
+        .unwrap();
+    assert_eq!(report.result, CheckResult::UpToDate);
+    assert_eq!(report.candidate, Some("2019-01-02".to_string()));
+    assert_eq!(probed, vec!["2019-01-04", "2019-01-03", "2019-01-02"]);
+
+    // Nothing complete since the installed nightly: still nothing to do, and
+    // older nightlies are never fetched.
+    rust.set_required(vec!["rustc".to_string(), "miri".to_string()]);
+    let mut probed = vec![];
+    let report = rust
+        .report_with(10, &mut |r| probed.push(r.date_str()))
+        .unwrap();
+    assert_eq!(report.result, CheckResult::UpToDate);
+    assert_eq!(report.candidate, None);
+    assert_eq!(probed.len(), 3);
+    assert!(
+        Args::parse(vec!["--since-installed".to_string()])
+            .unwrap()
+            .since_installed
+    );
+}
+
+#[test]
+fn test_manifest_dir() {
+    let dir = std::env::temp_dir().join(format!("rustupscheck-mirror-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let newer = "1.33.0-nightly (c2d381d39 2019-01-02)";
+    let write = |date: &str, rls: bool| {
+        let manifest = format!(
+            "manifest-version = \"2\"
+date = \"{0}\"
+[pkg.rust]
+version = \"{1}\"
+[pkg.rust.target.{2}]
+available = true
+[pkg.rustc]
+version = \"{1}\"
+[pkg.rustc.target.{2}]
+available = true
+[pkg.rls]
+version = \"{1}\"
+[pkg.rls.target.{2}]
+available = {3}
+[renames]
+",
+            date, newer, TARGET, rls
+        );
+        std::fs::write(
+            dir.join(source::ManifestDir::file_name(date, "nightly")),
+            manifest,
+        )
+        .unwrap();
+    };
+    write("2019-01-02", true);
+    write("2019-01-04", false);
+    let source = source::ManifestDir::new(&dir);
+    let toolchain = mock_toolchain(fixture("downloads"), &["rustc", "rls"]);
+    let rust = Rust {
+        source: Rc::new(source.clone()),
+        ..mock_rust(toolchain, MockSource::default(), "2019-01-05", -1)
+    };
+    let mut probed = vec![];
+    let scanned = Rust::scan(&rust, 5, &mut |r: &Rust| {
+        probed.push((r.date_str(), r.manifest.is_some()))
+    });
+    let latest = source.latest("nightly");
+    let beta = source.latest("beta");
+    std::fs::write(dir.join("channel-rust-stable.toml"), "manifest-version = ").unwrap();
+    let stable = source.latest("stable");
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    assert_eq!(
+        scanned.unwrap().map(|r| r.date_str()),
+        Some("2019-01-02".to_string())
+    );
+    let probed: Vec<(&str, bool)> = probed.iter().map(|(d, f)| (d.as_str(), *f)).collect();
+    assert_eq!(
+        probed,
+        vec![
+            ("2019-01-05", false),
+            ("2019-01-04", true),
+            ("2019-01-03", false),
+            ("2019-01-02", true),
+        ]
+    );
+    assert_eq!(latest.map(|m| m.date), Ok(NaiveDate::from_ymd(2019, 1, 4)));
+    assert!(matches!(beta, Err(FetchError::Fetch(_))));
+    assert!(matches!(stable, Err(FetchError::Malformed(_))));
+    assert_eq!(
+        Args::parse(vec!["--manifest-dir=/srv/mirror".to_string()])
+            .unwrap()
+            .manifest_dir,
+        Some(PathBuf::from("/srv/mirror"))
+    );
+}
+
+#[test]
+fn test_major_minor() {
+    let ver = |s: &str| Version::from_str(s).unwrap();
+    assert_eq!(
+        ver("1.72.0 (a6e5d3fd6 2023-08-04)").major_minor(),
+        Ok((1, 72))
+    );
+    assert_eq!(
+        ver("1.72.0-beta.8 (a6e5d3fd6 2023-08-04)").major_minor(),
+        Ok((1, 72))
+    );
+    let odd = |version: &str| Version {
+        version: version.to_string(),
+        ..ver("1.72.0 (a6e5d3fd6 2023-08-04)")
+    };
+    assert_eq!(
+        odd("1.x.0").major_minor(),
+        Err("wrong version number: 1.x.0".to_string())
+    );
+    assert!(odd("1").major_minor().is_err());
+    assert!(odd("").major_minor().is_err());
+    assert_eq!(odd("2.0").major_minor(), Ok((2, 0)));
+
+    assert_eq!(
+        format::release_gap(
+            &ver("1.70.0 (a6e5d3fd6 2023-06-01)"),
+            &ver("1.72.0 (a6e5d3fd6 2023-08-04)")
+        ),
+        Some("You're 2 releases behind (on 1.70 series, newest is 1.72)\n".to_string())
+    );
+    assert_eq!(
+        format::release_gap(
+            &ver("1.71.0 (a6e5d3fd6 2023-06-01)"),
+            &ver("1.72.0 (a6e5d3fd6 2023-08-04)")
+        ),
+        Some("You're 1 release behind (on 1.71 series, newest is 1.72)\n".to_string())
+    );
+    assert_eq!(
+        format::release_gap(
+            &ver("1.72.0 (a6e5d3fd6 2023-06-01)"),
+            &ver("1.72.0 (a6e5d3fd6 2023-08-04)")
+        ),
+        None
+    );
+    assert_eq!(
+        format::release_gap(&odd("1.x.0"), &ver("1.72.0 (a6e5d3fd6 2023-08-04)")),
+        None
+    );
+}
+
+#[test]
+fn test_fetch_config() {
+    use std::cell::RefCell;
+    let dir = std::env::temp_dir().join(format!("rustupscheck-fetch-{}", std::process::id()));
+    let body = std::fs::read_to_string(fixture_path("downloads")).unwrap();
+    let requests = Rc::new(RefCell::new(Vec::<String>::new()));
+    let failures = Rc::new(RefCell::new(0));
+    let config = {
+        let (requests, failures) = (requests.clone(), failures.clone());
+        FetchConfig {
+            cache: Some(cache::Cache::new(dir.clone())),
+            retries: 2,
+            retry_delay: std::time::Duration::from_millis(0),
+            progress: false,
+            transport: Rc::new(move |path: &str| {
+                requests.borrow_mut().push(path.to_string());
+                if *failures.borrow() > 0 {
+                    *failures.borrow_mut() -= 1;
+                    return Err("connection reset".to_string());
+                }
+                match path {
+                    "/dist/2019-01-02/channel-rust-nightly.toml" => Ok(Some(body.clone())),
+                    _ => Ok(None),
+                }
+            }),
+        }
+    };
+    let fetch = |date: &str| Manifest::from_date(date, "nightly", &config);
+
+    // A cache miss goes to the network, and the answer is cached.
+    let manifest = fetch("2019-01-02").unwrap().unwrap();
+    assert_eq!(manifest.date, NaiveDate::from_ymd(2019, 1, 2));
+    assert_eq!(requests.borrow().len(), 1);
+    assert_eq!(fetch("2019-01-02"), Ok(Some(manifest)));
+    assert_eq!(requests.borrow().len(), 1);
+
+    // So is a 404.
+    assert_eq!(fetch("2019-01-03"), Ok(None));
+    assert_eq!(fetch("2019-01-03"), Ok(None));
+    assert_eq!(requests.borrow().len(), 2);
+
+    // Failed downloads are retried, up to `retries` times.
+    *failures.borrow_mut() = 2;
+    assert_eq!(fetch("2019-01-04"), Ok(None));
+    assert_eq!(requests.borrow().len(), 5);
+    *failures.borrow_mut() = 3;
+    assert_eq!(
+        fetch("2019-01-05"),
+        Err(FetchError::Fetch("connection reset".to_string()))
+    );
+    assert_eq!(requests.borrow().len(), 8);
+
+    let source = Dist::with_config(config.clone());
+    assert!(source.manifest("2019-01-02", "nightly").unwrap().is_some());
+    assert_eq!(requests.borrow().len(), 8);
+    config.cache.unwrap().clear().unwrap();
+}
+
+#[test]
+fn test_recent_miss_not_cached() {
+    use std::cell::RefCell;
+    let dir = std::env::temp_dir().join(format!("rustupscheck-recent-{}", std::process::id()));
+    let requests = Rc::new(RefCell::new(0));
+    let config = {
+        let requests = requests.clone();
+        FetchConfig {
+            cache: Some(cache::Cache::new(dir.clone())),
+            transport: Rc::new(move |_: &str| {
+                *requests.borrow_mut() += 1;
+                Ok(None)
+            }),
+            ..FetchConfig::default()
+        }
+    };
+    let fetch = |date: NaiveDate| {
+        Manifest::from_date(&date.format("%Y-%m-%d").to_string(), "nightly", &config)
+    };
+    let today = Anchor::Utc.today();
+
+    // Tonight's nightly may still come out, so every run asks again.
+    assert_eq!(fetch(today), Ok(None));
+    assert_eq!(fetch(today), Ok(None));
+    assert_eq!(*requests.borrow(), 2);
+    assert_eq!(fetch(today - Duration::days(1)), Ok(None));
+    assert_eq!(fetch(today - Duration::days(1)), Ok(None));
+    assert_eq!(*requests.borrow(), 4);
+
+    // An older gap is remembered.
+    assert_eq!(fetch(today - Duration::days(10)), Ok(None));
+    assert_eq!(fetch(today - Duration::days(10)), Ok(None));
+    assert_eq!(*requests.borrow(), 5);
+    assert_eq!(config.cache.unwrap().clear(), Ok(1));
+}
+
+#[test]
+fn test_dist_memoizes_manifests() {
+    use std::cell::RefCell;
+    let body = std::fs::read_to_string(fixture_path("downloads")).unwrap();
+    let requests = Rc::new(RefCell::new(Vec::<String>::new()));
+    let source = {
+        let requests = requests.clone();
+        Dist::with_config(FetchConfig {
+            cache: None,
+            transport: Rc::new(move |path: &str| {
+                requests.borrow_mut().push(path.to_string());
+                match path {
+                    "/dist/2019-01-02/channel-rust-nightly.toml" => Ok(Some(body.clone())),
+                    _ => Ok(None),
+                }
+            }),
+            ..FetchConfig::default()
+        })
+    };
+
+    // Without a disk cache, a repeated date is still parsed only once.
+    let manifest = source.manifest("2019-01-02", "nightly").unwrap();
+    assert!(manifest.is_some());
+    assert_eq!(source.manifest("2019-01-02", "nightly").unwrap(), manifest);
+    assert_eq!(requests.borrow().len(), 1);
+
+    // Other dates and channels are fetched on their own.
+    assert_eq!(source.manifest("2019-01-02", "beta"), Ok(None));
+    assert_eq!(requests.borrow().len(), 2);
+
+    // An unpublished date is asked again, it may show up later.
+    assert_eq!(source.manifest("2019-01-03", "nightly"), Ok(None));
+    assert_eq!(source.manifest("2019-01-03", "nightly"), Ok(None));
+    assert_eq!(requests.borrow().len(), 4);
+}
+
+#[test]
+fn test_release_train() {
+    let ver = |s: &str| Version::from_str(s).unwrap();
+    let stable = ver("1.71.0 (8ede3aae2 2023-07-12)");
+    let stable_date = NaiveDate::from_ymd(2023, 7, 13);
+    let today = NaiveDate::from_ymd(2023, 8, 4);
+    let train =
+        |installed: &str| format::release_train(&ver(installed), &stable, stable_date, today);
+    assert_eq!(
+        train("1.72.0-beta.8 (a6e5d3fd6 2023-08-04)"),
+        Some("beta 1.72 will become stable in ~3 weeks (around 2023-08-24)".to_string())
+    );
+    assert_eq!(
+        train("1.73.0-nightly (8131b9774 2023-08-02)"),
+        Some("nightly 1.73 will become stable in ~9 weeks (around 2023-10-05)".to_string())
+    );
+    assert_eq!(
+        train("1.71.0 (8ede3aae2 2023-07-12)"),
+        Some("stable 1.72 is due in ~3 weeks (around 2023-08-24)".to_string())
+    );
+    assert_eq!(train("1.70.0 (90c541806 2023-05-31)"), None);
+
+    // A stale stable date still points at the next release ahead.
+    let later = format::release_train(
+        &ver("1.72.0-beta.8 (a6e5d3fd6 2023-08-04)"),
+        &stable,
+        stable_date,
+        NaiveDate::from_ymd(2023, 8, 30),
+    );
+    assert_eq!(
+        later,
+        Some("beta 1.72 will become stable in ~6 weeks (around 2023-10-05)".to_string())
+    );
+
+    let toolchain = mock_toolchain(
+        mock_manifest(
+            "2023-08-04",
+            &[("rust", "1.72.0-beta.8 (a6e5d3fd6 2023-08-04)", true)],
+        ),
+        &[],
+    );
+    let source = MockSource::default().with_latest(
+        "stable",
+        mock_manifest(
+            "2023-07-13",
+            &[("rust", "1.71.0 (8ede3aae2 2023-07-12)", true)],
+        ),
+    );
+    let rust = mock_rust(toolchain, source, "2023-08-04", -1);
+    assert_eq!(
+        rust.release_train(),
+        Ok(Some(
+            "beta 1.72 will become stable in ~3 weeks (around 2023-08-24)".to_string()
+        ))
+    );
+}
+
+#[test]
+fn test_components_json() {
+    let version = |v: &str| Version::from_str(v).ok();
+    let row = |name: &str, installed: &str, candidate: &str, status| ComponentRow {
+        name: name.to_string(),
+        installed: version(installed),
+        candidate: version(candidate),
+        status,
+    };
+    let old = "1.33.0-nightly (9eac38634 2019-01-01)";
+    let new = "1.33.0-nightly (c2d381d39 2019-01-02)";
+    let rows = vec![
+        row(
+            "clippy",
+            old,
+            old,
+            ComponentStatus::Missing(MissingReason::Unavailable),
+        ),
+        row("miri", "", new, ComponentStatus::Add),
+        row("rustc", old, new, ComponentStatus::Update),
+    ];
+    let json: serde_json::Value = serde_json::from_str(&format::components_json(&rows)).unwrap();
+    assert_eq!(
+        json,
+        serde_json::json!([
+            {
+                "name": "clippy",
+                "installed": "1.33.0-nightly (9eac38634 2019-01-01)",
+                "candidate": "1.33.0-nightly (9eac38634 2019-01-01)",
+                "status": "missing",
+                "reason": "unavailable"
+            },
+            {
+                "name": "miri",
+                "installed": null,
+                "candidate": "1.33.0-nightly (c2d381d39 2019-01-02)",
+                "status": "add"
+            },
+            {
+                "name": "rustc",
+                "installed": "1.33.0-nightly (9eac38634 2019-01-01)",
+                "candidate": "1.33.0-nightly (c2d381d39 2019-01-02)",
+                "status": "update"
+            }
+        ])
+    );
+    assert_eq!(
+        Args::parse(vec!["--output-components-as-json".to_string()])
+            .unwrap()
+            .format,
+        Format::ComponentsJson
+    );
+}
+
+#[test]
+fn test_malformed_manifest() {
+    let version = "1.33.0-nightly (9eac38634 2019-01-01)";
+    let manifest = mock_manifest(
+        "2019-01-02",
+        &[("rust", version, true), ("rustc", version, true)],
+    );
+    let source = MockSource::default()
+        .with(manifest.clone())
+        .malformed("2019-01-03");
+    let rust = mock_rust(
+        mock_toolchain(manifest, &["rustc"]),
+        source.clone(),
+        "2019-01-04",
+        -1,
+    );
+    let mut probes = vec![];
+    let report = rust
+        .report_with(5, &mut |r| probes.push(r.probe()))
+        .unwrap();
+    assert_eq!(report.candidate, Some("2019-01-02".to_string()));
+    assert_eq!(
+        probes.iter().map(format::explain).collect::<Vec<_>>(),
+        vec![
+            "2019-01-04: not published",
+            "2019-01-03: unusable, 2019-01-03 is truncated",
+            "2019-01-02: complete",
+        ]
+    );
+    assert_eq!(
+        serde_json::to_value(&probes[1]).unwrap()["unusable"],
+        "2019-01-03 is truncated"
+    );
+    assert!(serde_json::to_value(&probes[0])
+        .unwrap()
+        .get("unusable")
+        .is_none());
+
+    // Network failures still end the scan.
+    let rust = Rust {
+        source: Rc::new(source.failing("2019-01-04")),
+        ..rust
+    };
+    assert_eq!(
+        rust.to_report(5).map(|r| r.candidate),
+        Err(CheckError::Fetch("2019-01-04 unreachable".to_string()))
+    );
+}
+
+#[test]
+fn test_today_fetch_error() {
+    let today = Anchor::Local.today().format("%Y-%m-%d").to_string();
+    let version = "1.33.0-nightly (9eac38634 2019-01-01)";
+    let manifest = mock_manifest(&today, &[("rust", version, true), ("rustc", version, true)]);
+    let toolchain = mock_toolchain(manifest.clone(), &["rustc"]);
+    let unreachable = Err(CheckError::Fetch(format!("{} unreachable", today)));
+
+    // A failed download isn't mistaken for today not being published yet.
+    let failing = MockSource::default().failing(&today);
+    assert_eq!(
+        Rust::for_toolchain(toolchain.clone(), Rc::new(failing.clone()), Anchor::Local)
+            .map(|r| r.manifest),
+        unreachable.clone()
+    );
+
+    let source = MockSource::default().with(manifest.clone());
+    let mut rust = Rust::for_toolchain(toolchain.clone(), Rc::new(source), Anchor::Local).unwrap();
+    assert_eq!(rust.manifest, Some(manifest));
+    rust.source = Rc::new(failing);
+    assert_eq!(
+        rust.refresh_with(toolchain.clone()),
+        unreachable.map(|_| ())
+    );
+    assert_eq!(rust.manifest, None);
+
+    // A malformed manifest leaves today unusable, as it does in a scan.
+    let malformed = MockSource::default().malformed(&today);
+    let rust = Rust::for_toolchain(toolchain, Rc::new(malformed), Anchor::Local).unwrap();
+    assert_eq!(rust.manifest, None);
+    assert_eq!(rust.unusable, Some(format!("{} is truncated", today)));
+}
+
+#[test]
+fn test_is_custom() {
+    let home = std::path::Path::new("/nonexistent-rustup-home");
+    for name in &["stage1", "my-build", "x86_64-unknown-linux-gnu"] {
+        let toolchain = Toolchain::named(name, Some(home)).unwrap();
+        assert!(toolchain.is_custom(), "{}", name);
+        assert_eq!(
+            toolchain.custom_note(),
+            Some(format!(
+                "Update checking not supported for custom toolchain {}",
+                name
+            ))
+        );
+    }
+    for channel in &["nightly", "beta", "stable", "1.70.0"] {
+        let toolchain = mock_toolchain(fixture("downloads"), &["rustc"]);
+        let toolchain = Toolchain {
+            channel: channel.to_string(),
+            ..toolchain
+        };
+        assert!(!toolchain.is_custom(), "{}", channel);
+        assert_eq!(toolchain.custom_note(), None);
+    }
+    // Standard names still have to exist on disk.
+    assert!(Toolchain::named(&format!("nightly-{}", TARGET), Some(home)).is_err());
+    assert_eq!(
+        check_toolchain(Some("stage1"), &CheckOptions::default()).map(|r| r.toolchain),
+        Err(CheckError::Toolchain(
+            "Update checking not supported for custom toolchain stage1".to_string()
+        ))
+    );
+}
+
+#[test]
+fn test_verbosity_levels() {
+    let args = |v: &[&str]| Args::parse(v.iter().map(|s| s.to_string())).unwrap();
+    let quiet = args(&["--json"]);
+    assert_eq!(quiet.verbosity, 0);
+    assert!(!quiet.verbose());
+    assert!(!quiet.show_trace());
+    assert!(!quiet.show_progress(true));
+
+    let verbose = args(&["--json", "-v"]);
+    assert_eq!(verbose.verbosity, 1);
+    assert!(verbose.verbose());
+    assert!(!verbose.show_trace());
+    assert!(verbose.show_progress(true));
+    assert!(!verbose.show_progress(false));
+    assert!(!args(&["--json", "-v", "--no-progress"]).show_progress(true));
+
+    for trace in &[args(&["-vv"]), args(&["-v", "--verbose"])] {
+        assert_eq!(trace.verbosity, 2);
+        assert!(trace.verbose());
+        assert!(trace.show_trace());
+    }
+    assert!(args(&["--explain"]).show_trace());
+    assert!(!args(&["--explain"]).verbose());
+}
+
+#[test]
+fn test_manifest_channel() {
+    assert_eq!(fixture("downloads").channel(), Some(Channel::Nightly));
+    assert_eq!(fixture("stable").channel(), Some(Channel::Stable));
+    let beta = mock_manifest(
+        "2023-08-04",
+        &[("rust", "1.72.0-beta.8 (a6e5d3fd6 2023-08-04)", true)],
+    );
+    assert_eq!(beta.channel(), Some(Channel::Beta));
+    let no_rust = mock_manifest(
+        "2023-08-04",
+        &[("rustc", "1.72.0 (5680fa18f 2023-08-23)", true)],
+    );
+    assert_eq!(no_rust.channel(), None);
+}
+
+#[test]
+fn test_only_if_actionable() {
+    let old = "1.33.0-nightly (9eac38634 2019-01-01)";
+    let newer = "1.33.0-nightly (c2d381d39 2019-01-02)";
+    let newest = "1.33.0-nightly (d1b4a1e7e 2019-01-03)";
+    let nightly = |date: &str, version: &str, rls: bool| {
+        mock_manifest(
+            date,
+            &[
+                ("rust", version, true),
+                ("rustc", version, true),
+                ("rls", version, rls),
+            ],
+        )
+    };
+    let installed = || mock_toolchain(nightly("2019-01-01", old, true), &["rustc", "rls"]);
+    let check = |source: MockSource, max_days: usize| {
+        let rust = mock_rust(installed(), source, "2019-01-03", -1);
+        let report = rust.to_report(max_days).unwrap();
+        (report.is_actionable(), report.exit_code())
+    };
+
+    let up_to_date = MockSource::default().with(nightly("2019-01-03", old, true));
+    assert_eq!(check(up_to_date, 3), (false, 0));
+    let update = MockSource::default().with(nightly("2019-01-03", newest, true));
+    assert_eq!(check(update, 3), (true, 10));
+    let switch = MockSource::default()
+        .with(nightly("2019-01-02", newer, true))
+        .with(nightly("2019-01-03", newest, false));
+    assert_eq!(check(switch.clone(), 3), (true, 10));
+    // Nothing to run when no nightly is complete, so nothing to say either.
+    assert_eq!(check(switch, 1), (false, 20));
+
+    let args = Args::parse(vec!["--only-if-actionable".to_string()]).unwrap();
+    assert!(args.only_if_actionable);
+    assert!(!args.exit_code);
+}
+
+#[test]
+fn test_fetch_outcomes() {
+    let version = "1.33.0-nightly (9eac38634 2019-01-01)";
+    let manifest = mock_manifest("2019-01-01", &[("rust", version, true)]);
+    let source = MockSource::default()
+        .with(manifest.clone())
+        .failing("2019-01-04")
+        .malformed("2019-01-03");
+    let rust = mock_rust(
+        mock_toolchain(manifest.clone(), &[]),
+        source,
+        "2019-01-05",
+        -1,
+    );
+    let outcomes: Vec<FetchOutcome> = rust.outcomes(5).collect();
+    let date = |d: u32| NaiveDate::from_ymd(2019, 1, d);
+    assert_eq!(
+        outcomes,
+        vec![
+            FetchOutcome {
+                date: date(5),
+                manifest: None,
+                error: None,
+            },
+            FetchOutcome {
+                date: date(4),
+                manifest: None,
+                error: Some(CheckError::Fetch("2019-01-04 unreachable".to_string())),
+            },
+            FetchOutcome {
+                date: date(3),
+                manifest: None,
+                error: Some(CheckError::Malformed("2019-01-03 is truncated".to_string())),
+            },
+            FetchOutcome {
+                date: date(2),
+                manifest: None,
+                error: None,
+            },
+            FetchOutcome {
+                date: date(1),
+                manifest: Some(manifest),
+                error: None,
+            },
+        ]
+    );
+    let published: Vec<bool> = outcomes.iter().map(FetchOutcome::is_published).collect();
+    assert_eq!(published, vec![false, false, false, false, true]);
+    let unpublished: Vec<bool> = outcomes.iter().map(FetchOutcome::is_unpublished).collect();
+    assert_eq!(unpublished, vec![true, false, false, true, false]);
+}
+
+#[test]
+fn test_stable_alternative() {
+    let nightly = "1.34.0-nightly (9eac38634 2019-01-01)";
+    let stable = "1.32.0 (9fda7c223 2019-01-16)";
+    let broken = |date: &str| {
+        mock_manifest(
+            date,
+            &[
+                ("rust", nightly, true),
+                ("rustc", nightly, true),
+                ("rls", nightly, false),
+            ],
+        )
+    };
+    let source = MockSource::default()
+        .with(broken("2019-01-01"))
+        .with(broken("2019-01-02"))
+        .with_latest(
+            "stable",
+            mock_manifest(
+                "2019-01-17",
+                &[
+                    ("rust", stable, true),
+                    ("rustc", stable, true),
+                    ("rls", stable, true),
+                ],
+            ),
+        );
+    let toolchain = mock_toolchain(broken("2019-01-01"), &["rustc", "rls"]);
+    let rust = mock_rust(toolchain, source.clone(), "2019-01-02", -1);
+    assert_eq!(rust.to_report(2).unwrap().exit_code(), 20);
+
+    let alternative = rust.stable_alternative().unwrap().unwrap();
+    assert_eq!(alternative.toolchain().channel, "stable");
+    assert_eq!(
+        format::stable_alternative(
+            "nightly",
+            alternative.manifest_pkg_version("rust").as_ref(),
+            &alternative.add_components()
+        ),
+        "Alternative: stable 1.32.0 has every component you need
+Note: leaving nightly gives up features only it has
+Use: \"rustup default stable\"
+     \"rustup component add rls\"
+"
+    );
+
+    // Stable without the component is no way out.
+    let mut rust = rust;
+    rust.set_required(vec!["miri".to_string()]);
+    assert!(rust.stable_alternative().unwrap().is_none());
+    assert!(
+        Args::parse(vec!["--recommend-stable-if-nightly-broken".to_string()])
+            .unwrap()
+            .recommend_stable
+    );
+}